@@ -1,52 +1,77 @@
+use crate::async_pipeline::dead_letter::dead_letter_row;
 use crate::async_pipeline::message_queue::{
     ChannelReceiver, ChannelSender, MessageSender, QueueJoinHandle, QueueMessage,
 };
-use crate::parser::{GrokParser, GrokSchema, LogParser, RawMessage};
+use crate::parser::{DynLogParser, GrokSchema, RawMessage};
 use crate::ql_processor::{QlRow, QlRowBatch, QlSchema};
 use crate::DynError;
 use log::{error, info};
 use std::sync::Arc;
 
-pub struct AsyncParser {
+/// A parser and the schema/sender for the rows it produces. When there are multiple
+/// routes each message goes to the first one which parses it successfully.
+pub struct ParserRoute {
     parsed_tx: MessageSender<QlRowBatch>,
+    ql_schema: Arc<QlSchema>,
+    log_parser: DynLogParser,
+}
+
+impl ParserRoute {
+    pub fn new(
+        parsed_tx: MessageSender<QlRowBatch>,
+        schema: &GrokSchema,
+        log_parser: DynLogParser,
+    ) -> Self {
+        Self {
+            parsed_tx,
+            ql_schema: Arc::new(QlSchema::from(schema)),
+            log_parser,
+        }
+    }
+}
+
+type ParserAndSchema = (DynLogParser, Arc<QlSchema>);
+
+pub struct AsyncParser {
+    parsed_txs: Vec<MessageSender<QlRowBatch>>,
+    dead_letter_tx: Option<MessageSender<QlRowBatch>>,
     tx: ChannelSender<QueueMessage<Vec<RawMessage>>>,
     rx: ChannelReceiver<QueueMessage<Vec<RawMessage>>>,
-    ql_schema: Arc<QlSchema>,
-    log_parser: Arc<GrokParser>,
+    parsers: Arc<Vec<ParserAndSchema>>,
 }
 
 impl AsyncParser {
-    pub fn wrap_parsed_sender(
-        parsed_sender: MessageSender<QlRowBatch>,
-        schema: GrokSchema,
+    /// The messages which fail to parse with all routes go to dead_letter_tx
+    /// (as dead letter rows) if present, or are only logged otherwise
+    pub fn wrap_parsed_senders(
+        routes: Vec<ParserRoute>,
+        dead_letter_tx: Option<MessageSender<QlRowBatch>>,
         channel_size: usize,
     ) -> Result<(MessageSender<Vec<RawMessage>>, QueueJoinHandle), DynError> {
-        let ql_schema = Arc::new(QlSchema::from(&schema));
-        let grok_parser = GrokParser::new(schema)?;
-        let async_parser = AsyncParser::new(
-            parsed_sender,
-            ql_schema,
-            Arc::from(grok_parser),
-            channel_size,
-        );
+        let async_parser = AsyncParser::new(routes, dead_letter_tx, channel_size);
         let raw_sender = async_parser.clone_sender();
         let jh = async_parser.consume_parser_queue_async();
         Ok((raw_sender, jh))
     }
 
     fn new(
-        parsed_tx: MessageSender<QlRowBatch>,
-        ql_schema: Arc<QlSchema>,
-        log_parser: Arc<GrokParser>,
+        routes: Vec<ParserRoute>,
+        dead_letter_tx: Option<MessageSender<QlRowBatch>>,
         channel_size: usize,
     ) -> Self {
         let (tx, rx) = tokio::sync::mpsc::channel(channel_size);
+        let mut parsed_txs = Vec::with_capacity(routes.len());
+        let mut parsers = Vec::with_capacity(routes.len());
+        for r in routes {
+            parsed_txs.push(r.parsed_tx);
+            parsers.push((r.log_parser, r.ql_schema));
+        }
         Self {
-            parsed_tx,
+            parsed_txs,
+            dead_letter_tx,
             tx,
             rx,
-            log_parser,
-            ql_schema,
+            parsers: Arc::new(parsers),
         }
     }
 
@@ -62,30 +87,48 @@ impl AsyncParser {
 
     async fn consume_queue(&mut self) {
         info!("ASEN: Consuming Raw messages queue ...");
-        while let Some(msg) = self.rx.recv().await {
+        'outer: while let Some(msg) = self.rx.recv().await {
             //let parsed_tx = self.parsed_tx.clone();
             match msg {
                 QueueMessage::Data(batch) => {
-                    let parsed = self.parse_batch(batch).await;
-                    if !parsed.is_empty() {
-                        if let Err(err) = self.parsed_tx.send(parsed).await {
+                    let (parsed_batches, dead_letters) = self.parse_batch(batch).await;
+                    let all_txs = self.parsed_txs.iter().chain(&self.dead_letter_tx);
+                    let all_batches = parsed_batches.into_iter().chain(Some(dead_letters));
+                    for (parsed, parsed_tx) in all_batches.zip(all_txs) {
+                        if parsed.is_empty() {
+                            continue;
+                        }
+                        if let Err(err) = parsed_tx.send(parsed).await {
                             error!(
                                 "Failed to send parsed message batch downstream, aborting: {}",
                                 err
                             );
-                            break;
+                            break 'outer;
                         };
                     }
                 }
                 QueueMessage::Flush => {
-                    if let Err(err) = self.parsed_tx.flush().await {
-                        error!("Failed to send flush message downstream, aborting: {}", err);
-                        break;
+                    for parsed_tx in self.parsed_txs.iter().chain(&self.dead_letter_tx) {
+                        if let Err(err) = parsed_tx.flush().await {
+                            error!("Failed to send flush message downstream, aborting: {}", err);
+                            break 'outer;
+                        }
+                    }
+                }
+                QueueMessage::Checkpoint(marker) => {
+                    for parsed_tx in self.parsed_txs.iter().chain(&self.dead_letter_tx) {
+                        if let Err(err) = parsed_tx.checkpoint(marker.fork()).await {
+                            error!("Failed to send checkpoint downstream, aborting: {}", err);
+                            break 'outer;
+                        }
                     }
+                    marker.ack();
                 }
                 QueueMessage::Shutdown => {
-                    if let Err(err) = self.parsed_tx.shutdown().await {
-                        error!("Failed to send shutdown message downstream: {}", err);
+                    for parsed_tx in self.parsed_txs.iter().chain(&self.dead_letter_tx) {
+                        if let Err(err) = parsed_tx.shutdown().await {
+                            error!("Failed to send shutdown message downstream: {}", err);
+                        }
                     }
                     break;
                 }
@@ -93,25 +136,12 @@ impl AsyncParser {
         }
     }
 
-    async fn parse_batch(&self, raw_vec: Vec<RawMessage>) -> QlRowBatch {
-        let parser_ref = Arc::clone(&self.log_parser);
-        let ql_schema_ref = Arc::clone(&self.ql_schema);
+    /// Parse the batch, returning one row batch per route and the dead letters batch
+    async fn parse_batch(&self, raw_vec: Vec<RawMessage>) -> (Vec<QlRowBatch>, QlRowBatch) {
+        let parsers_ref = Arc::clone(&self.parsers);
+        let keep_dead_letters = self.dead_letter_tx.is_some();
         tokio_rayon::spawn_fifo(move || {
-            let mut ret_buf = Vec::with_capacity(raw_vec.len());
-            for raw in raw_vec {
-                let parse_res = parser_ref.parse(raw);
-                match parse_res {
-                    Ok(parsed) => {
-                        let ql_row = QlRow::from_parsed_message(parsed, ql_schema_ref.as_ref());
-                        ret_buf.push(ql_row)
-                    }
-                    Err(err) => {
-                        // TODO add send_error to MessageSender ?
-                        error!("Error parsing message: {}", err);
-                    }
-                }
-            }
-            ret_buf
+            route_batch(parsers_ref.as_ref(), raw_vec, keep_dead_letters)
         })
         .await
     }
@@ -120,3 +150,88 @@ impl AsyncParser {
         MessageSender::new(self.tx.clone())
     }
 }
+
+fn route_batch(
+    parsers: &[ParserAndSchema],
+    raw_vec: Vec<RawMessage>,
+    keep_dead_letters: bool,
+) -> (Vec<QlRowBatch>, QlRowBatch) {
+    let mut ret_bufs: Vec<QlRowBatch> = parsers.iter().map(|_| Vec::new()).collect();
+    let mut dead_letters = Vec::new();
+    for raw in raw_vec {
+        let mut raw = raw;
+        for (i, (parser, ql_schema)) in parsers.iter().enumerate() {
+            match parser.parse(raw) {
+                Ok(parsed) => {
+                    ret_bufs[i].push(QlRow::from_parsed_message(parsed, ql_schema.as_ref()));
+                    break;
+                }
+                Err(err) => {
+                    if i == parsers.len() - 1 {
+                        if keep_dead_letters {
+                            dead_letters.push(dead_letter_row(err));
+                        } else {
+                            error!("Error parsing message: {}", err);
+                        }
+                        break;
+                    }
+                    raw = err.consume_raw();
+                }
+            }
+        }
+    }
+    (ret_bufs, dead_letters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{GrokColumnDef, ParsedValueType, RegexParser};
+
+    fn test_route(pattern: &str, name: &str) -> ParserAndSchema {
+        let schema = GrokSchema::new(
+            pattern.to_string(),
+            vec![GrokColumnDef::new(
+                Arc::from("message"),
+                ParsedValueType::StrType(256),
+                vec![Arc::new(String::from("message"))],
+                true,
+            )],
+            false,
+            vec![],
+            false,
+            name.to_string(),
+        );
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        (Arc::new(RegexParser::new(schema).unwrap()), ql_schema)
+    }
+
+    #[test]
+    fn route_batch_works() {
+        let parsers = vec![
+            test_route(r"^sshd: (?P<message>.*)$", "auth"),
+            test_route(r"^nginx: (?P<message>.*)$", "access"),
+        ];
+        let raw_vec = vec![
+            "sshd: login",
+            "nginx: GET /",
+            "unknown: dropped",
+            "nginx: GET /favicon.ico",
+        ]
+        .into_iter()
+        .map(|s| RawMessage::new(s.to_string()))
+        .collect::<Vec<_>>();
+        let (routed, dead_letters) = route_batch(&parsers, raw_vec.clone(), false);
+        assert_eq!(routed.len(), 2);
+        assert_eq!(routed[0].len(), 1);
+        assert_eq!(routed[1].len(), 2);
+        assert!(dead_letters.is_empty());
+        let (routed, dead_letters) = route_batch(&parsers, raw_vec, true);
+        assert_eq!(routed[1].len(), 2);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(
+            dead_letters[0].raw().as_ref().unwrap().as_str(),
+            "unknown: dropped"
+        );
+    }
+}
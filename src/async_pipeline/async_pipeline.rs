@@ -1,77 +1,158 @@
-use crate::async_pipeline::async_parser::AsyncParser;
+use crate::async_pipeline::async_parser::{AsyncParser, ParserRoute};
 use crate::async_pipeline::batching_queue::BatchingQueue;
+use crate::async_pipeline::dead_letter::{dead_letter_schema, DEAD_LETTER_TABLE};
 use crate::async_pipeline::message_queue::{MessageSender, QueueJoinHandle};
 use crate::async_pipeline::output_processor::{DynOutputSink, OutputProcessor};
 use crate::async_pipeline::sql_batch_processor::SqlBatchProcessor;
 use crate::output::{AnsiSqlOutput, CsvOutput, OdbcSink};
-use crate::parser::RawMessage;
-use crate::ql_processor::QlSchema;
-use crate::{DynError, HustlogConfig, OutputFormat};
+use crate::parser::{
+    DynLogParser, GrokParser, JsonParser, LogfmtParser, RawMessage, RegexParser, Rfc5424Parser,
+};
+use crate::ql_processor::{QlRowBatch, QlSchema};
+use crate::{open_output_writer, DynError, HustlogConfig, OutputFormat, ParserType, SchemaConfig};
 use log::debug;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-/// Create and wire the processing pipeline
-/// return a tuple consisting of the raw message sender and a vector of JoinHandles
-/// to be awaited on shutdown, or return an error on failure
-pub async fn create_processing_pipeline(
-    hcrc: &Arc<HustlogConfig>,
-) -> Result<(MessageSender<Vec<RawMessage>>, Vec<QueueJoinHandle>), DynError> {
-    hcrc.init_rayon_pool()?;
-    let schema = hcrc.get_grok_schema();
-    let ql_input_schema = Arc::new(QlSchema::from(&schema));
-    let mut sql_processor: Option<SqlBatchProcessor> = None;
-    let ql_output_schema = if hcrc.query().is_some() {
-        sql_processor = Some(SqlBatchProcessor::new(
-            hcrc.query().as_ref().unwrap().as_str(),
-            schema,
-            hcrc.get_async_channel_size(),
-        )?);
-        sql_processor.as_ref().unwrap().get_output_schema().clone()
-    } else {
-        ql_input_schema.clone()
+fn create_log_parser(sc: &SchemaConfig) -> Result<DynLogParser, DynError> {
+    let schema = sc.get_grok_schema();
+    let log_parser: DynLogParser = match sc.parser_type() {
+        ParserType::GROK => {
+            debug!("Using grok parser");
+            Arc::new(GrokParser::new(schema.clone())?)
+        }
+        ParserType::REGEX => {
+            debug!("Using regex parser");
+            Arc::new(RegexParser::new(schema.clone())?)
+        }
+        ParserType::JSON => {
+            debug!("Using JSON parser");
+            Arc::new(JsonParser::new(schema.clone()))
+        }
+        ParserType::LOGFMT => {
+            debug!("Using logfmt parser");
+            Arc::new(LogfmtParser::new(
+                schema.clone(),
+                sc.logfmt_extra_column().clone(),
+            ))
+        }
+        ParserType::RFC5424 => {
+            debug!("Using RFC 5424 parser");
+            Arc::new(Rfc5424Parser::new(schema.clone()))
+        }
     };
-    let sink: DynOutputSink = match hcrc.output_format() {
+    Ok(log_parser)
+}
+
+/// Create an output processor writing to output (a file/"-" or an ODBC connection string)
+/// return its sender and add its JoinHandle to join_handles
+fn create_output(
+    hcrc: &Arc<HustlogConfig>,
+    name: &str,
+    ql_schema: Arc<QlSchema>,
+    output_format: OutputFormat,
+    output: &str,
+    add_ddl: bool,
+    join_handles: &mut Vec<QueueJoinHandle>,
+) -> Result<MessageSender<QlRowBatch>, DynError> {
+    let sink: DynOutputSink = match output_format {
         OutputFormat::DEFAULT => {
-            debug!("Using default (CSV) output");
+            debug!("Using default (CSV) output for {}", name);
             Arc::new(Mutex::new(CsvOutput::new(
-                ql_output_schema.clone(),
-                hcrc.get_output_writer()?,
-                hcrc.output_add_ddl(),
+                ql_schema,
+                open_output_writer(output)?,
+                add_ddl,
+                hcrc.output_timezone(),
             )))
         }
         OutputFormat::SQL => {
-            debug!("Using SQL output");
+            debug!("Using SQL output for {}", name);
             Arc::new(Mutex::new(AnsiSqlOutput::new(
-                ql_output_schema.clone(),
-                hcrc.output_add_ddl(),
-                hcrc.output_batch_size(),
-                hcrc.get_output_writer()?,
-                hcrc.get_ddl_pre_name_opts(),
-                hcrc.get_ddl_table_opts(),
+                ql_schema,
+                add_ddl,
+                open_output_writer(output)?,
+                hcrc,
             )))
         }
         OutputFormat::ODBC => {
-            debug!("Using ODBC output");
-            Arc::new(Mutex::new(OdbcSink::new(
-                ql_output_schema.clone(),
-                hcrc.get_output(),
-            )?))
+            debug!("Using ODBC output for {}", name);
+            Arc::new(Mutex::new(OdbcSink::new(ql_schema, output)?))
         }
-
     };
-    let mut join_handles = Vec::new();
-    let (mut output_sender, jh) =
-        OutputProcessor::wrap_sink(sink, hcrc.get_async_channel_size(), hcrc.output_add_ddl());
+    let (output_sender, jh) =
+        OutputProcessor::wrap_sink(sink, hcrc.get_async_channel_size(), add_ddl);
     join_handles.push(jh);
+    Ok(output_sender)
+}
+
+/// Create and wire the per-schema part of the pipeline (optional SQL processor + output)
+/// return the parser route feeding it and add the JoinHandles to join_handles
+fn create_schema_pipeline(
+    hcrc: &Arc<HustlogConfig>,
+    sc: &SchemaConfig,
+    join_handles: &mut Vec<QueueJoinHandle>,
+) -> Result<ParserRoute, DynError> {
+    let schema = sc.get_grok_schema();
+    let ql_input_schema = Arc::new(QlSchema::from(&schema));
+    let mut sql_processor: Option<SqlBatchProcessor> = None;
+    let ql_output_schema = if sc.query().is_some() {
+        sql_processor = Some(SqlBatchProcessor::new(
+            sc.query().as_ref().unwrap().as_str(),
+            schema,
+            hcrc.get_async_channel_size(),
+        )?);
+        sql_processor.as_ref().unwrap().get_output_schema().clone()
+    } else {
+        ql_input_schema.clone()
+    };
+    let mut output_sender = create_output(
+        hcrc,
+        sc.name(),
+        ql_output_schema,
+        sc.output_format(),
+        sc.get_output(),
+        sc.output_add_ddl(),
+        join_handles,
+    )?;
     if sql_processor.is_some() {
         let (new_sender, jh) = sql_processor.unwrap().wrap_sender(output_sender)?;
         output_sender = new_sender;
         join_handles.push(jh)
     }
-    let (batch_sender, jh) = AsyncParser::wrap_parsed_sender(
+    Ok(ParserRoute::new(
         output_sender,
-        schema.clone(),
+        schema,
+        create_log_parser(sc)?,
+    ))
+}
+
+/// Create and wire the processing pipeline
+/// return a tuple consisting of the raw message sender and a vector of JoinHandles
+/// to be awaited on shutdown, or return an error on failure
+pub async fn create_processing_pipeline(
+    hcrc: &Arc<HustlogConfig>,
+) -> Result<(MessageSender<Vec<RawMessage>>, Vec<QueueJoinHandle>), DynError> {
+    hcrc.init_rayon_pool()?;
+    let mut join_handles = Vec::new();
+    let mut routes = Vec::new();
+    for sc in hcrc.schemas() {
+        routes.push(create_schema_pipeline(hcrc, sc, &mut join_handles)?);
+    }
+    let dead_letter_sender = match hcrc.dead_letter_output() {
+        Some(output) => Some(create_output(
+            hcrc,
+            DEAD_LETTER_TABLE,
+            dead_letter_schema(),
+            hcrc.dead_letter_format(),
+            output,
+            hcrc.dead_letter_add_ddl(),
+            &mut join_handles,
+        )?),
+        None => None,
+    };
+    let (batch_sender, jh) = AsyncParser::wrap_parsed_senders(
+        routes,
+        dead_letter_sender,
         hcrc.get_async_channel_size(),
     )?;
     join_handles.push(jh);
@@ -102,6 +102,24 @@ impl BatchingQueue {
                     }
                     self.batch_processed = false;
                 }
+                QueueMessage::Checkpoint(marker) => {
+                    // the buffered messages always go before the marker
+                    let batch = self.flush();
+                    if !batch.is_empty() {
+                        if let Err(err) = self.batch_sender.send(batch).await {
+                            error!(
+                                "Error sending checkpoint batch downstream (aborting): {:?}",
+                                err
+                            );
+                            break;
+                        }
+                    }
+                    if let Err(err) = self.batch_sender.checkpoint(marker).await {
+                        error!("Failed to send checkpoint downstream (aborting): {:?}", err);
+                        break;
+                    }
+                    self.batch_processed = false;
+                }
                 QueueMessage::Shutdown => {
                     let batch = self.flush();
                     if !batch.is_empty() {
@@ -145,7 +163,7 @@ mod tests {
     async fn test_batching_queue1() {
         let (test_queue_sender, test_queue_jh) = TestMessageQueue::create(2, false, false);
         let (sender, bjh) = BatchingQueue::wrap_output(10, 2, test_queue_sender);
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         lb.get_buf().put(test_dummy_data(99).as_bytes());
         let raw_vec = lb.flush();
         sender.send(raw_vec).await.unwrap();
@@ -164,7 +182,7 @@ mod tests {
     async fn test_batching_queue_zero_batch_size2() {
         let (test_queue_sender, test_queue_jh) = TestMessageQueue::create(2, true, false);
         let (sender, bjh) = BatchingQueue::wrap_output(0, 2, test_queue_sender);
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         lb.get_buf().put(test_dummy_data(99).as_bytes());
         let raw_vec = lb.flush();
         sender.send(raw_vec).await.unwrap();
@@ -0,0 +1,69 @@
+use crate::parser::{
+    arc_null_pv, utc_time_val, LogParseError, ParsedValue, ParsedValueType, TimeTypeFormat,
+};
+use crate::ql_processor::{QlColDef, QlRow, QlSchema};
+use chrono::Utc;
+use std::sync::Arc;
+
+/// The table (output) name of the dead letter rows
+pub const DEAD_LETTER_TABLE: &str = "dead_letter";
+
+const DEAD_LETTER_COLS: [&str; 4] = ["received_at", "source", "error", "line"];
+
+/// The schema of the messages which failed to parse:
+/// received_at, source, error (the parse error description) and line (the raw message)
+pub fn dead_letter_schema() -> Arc<QlSchema> {
+    let col_types = [
+        ParsedValueType::TimeType(TimeTypeFormat::new("%Y-%m-%dT%H:%M:%S%.f%:z")),
+        ParsedValueType::StrType(256),
+        ParsedValueType::StrType(1024),
+        ParsedValueType::StrType(65535),
+    ];
+    let cols = DEAD_LETTER_COLS
+        .iter()
+        .zip(col_types)
+        .map(|(&name, ctype)| QlColDef::new(name, ctype, false))
+        .collect::<Vec<_>>();
+    Arc::new(QlSchema::new(Arc::from(DEAD_LETTER_TABLE), cols))
+}
+
+fn str_val(s: &str) -> Arc<ParsedValue> {
+    Arc::new(ParsedValue::StrVal(Arc::new(s.to_string())))
+}
+
+/// Convert a parse error into a dead letter row (matching dead_letter_schema)
+pub fn dead_letter_row(err: LogParseError) -> QlRow {
+    let raw = err.get_raw();
+    let received_at = raw.received_at().copied().unwrap_or_else(Utc::now);
+    let vals = [
+        utc_time_val(received_at),
+        raw.source().map(|s| str_val(s)).unwrap_or_else(arc_null_pv),
+        str_val(err.get_desc()),
+        str_val(raw.as_str()),
+    ];
+    let data = DEAD_LETTER_COLS
+        .iter()
+        .map(|&name| Arc::from(name))
+        .zip(vals)
+        .collect::<Vec<_>>();
+    QlRow::new(Some(err.consume_raw()), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RawMessage;
+
+    #[test]
+    fn dead_letter_row_works() {
+        let raw =
+            RawMessage::new(String::from("garbage")).with_source(Some(Arc::from("10.1.2.3:514")));
+        let row = dead_letter_row(LogParseError::new("Grok pattern did not match", raw));
+        let strs = row.data_as_strs();
+        assert_eq!(strs.len(), DEAD_LETTER_COLS.len());
+        assert_eq!(strs[1].as_ref(), "10.1.2.3:514");
+        assert_eq!(strs[2].as_ref(), "Grok pattern did not match");
+        assert_eq!(strs[3].as_ref(), "garbage");
+        assert_eq!(row.raw().as_ref().unwrap().as_str(), "garbage");
+    }
+}
@@ -1,6 +1,8 @@
-use crate::parser::{LineMerger, RawMessage, SpaceLineMerger};
+use crate::parser::{DynLineMerger, LineMergerConfig, RawMessage, SourceMeta, SyslogPri};
 use bstr::ByteSlice;
 use bytes::{Buf, BytesMut};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
 
 const LINE_ENDING_CHARS: [u8; 2] = ['\n' as u8, '\r' as u8];
 
@@ -14,28 +16,84 @@ const SYSLOG_PRI_CLOSE_TAG: u8 = '>' as u8;
 
 pub struct LinesBuffer {
     buf: BytesMut,
-    line_merger: Option<SpaceLineMerger>,
+    line_merger: Option<DynLineMerger>,
+    ref_time: Option<DateTime<Utc>>,
+    received_at: Option<DateTime<Utc>>,
+    source: Option<Arc<str>>,
+    meta: Option<Arc<SourceMeta>>,
+    // input offsets of the data consumed from the buffer, of the start of the last line
+    // read and of the end of the last message returned (after which nothing is pending)
+    consumed: u64,
+    line_start: u64,
+    emitted: u64,
 }
 
 impl LinesBuffer {
     pub fn new(
         //capacity: usize,
-        use_line_merger: bool,
+        line_merger_conf: Option<&LineMergerConfig>,
     ) -> Self {
-        let line_merger = if use_line_merger {
-            Some(SpaceLineMerger::new())
-        } else {
-            None
-        };
+        let line_merger = line_merger_conf.map(|lmc| lmc.create_line_merger());
         Self {
             buf: BytesMut::with_capacity(
                 //capacity,
                 64 * 1024, //TODO make configurable?
             ),
             line_merger,
+            ref_time: None,
+            received_at: None,
+            source: None,
+            meta: None,
+            consumed: 0,
+            line_start: 0,
+            emitted: 0,
         }
     }
 
+    /// Set the input offset of the data buffered from now on, e.g. when resuming
+    /// from a checkpoint or reading a file from the start again
+    pub fn set_offset(&mut self, offset: u64) {
+        self.consumed = offset;
+        self.line_start = offset;
+        self.emitted = offset;
+    }
+
+    /// The input offset up to which all of the messages were returned, the data
+    /// after it is buffered or pending in the line merger
+    pub fn emitted_offset(&self) -> u64 {
+        self.emitted
+    }
+
+    /// Set the reference time for the year inference of the messages read
+    /// from now on (the file mtime), the receive time is used if not set
+    pub fn set_ref_time(&mut self, ref_time: Option<DateTime<Utc>>) {
+        self.ref_time = ref_time;
+    }
+
+    /// Set the source (input file or remote address) of the messages read from now on
+    pub fn set_source(&mut self, source: Option<Arc<str>>) {
+        self.source = source;
+    }
+
+    /// Set the syslog server metadata of the messages read from now on
+    pub fn set_source_meta(&mut self, meta: Option<Arc<SourceMeta>>) {
+        self.meta = meta;
+    }
+
+    /// Set the receive time of the data buffered from now on (e.g. of an UDP datagram),
+    /// the time the messages are read from the buffer is used if not set
+    pub fn set_received_at(&mut self, received_at: Option<DateTime<Utc>>) {
+        self.received_at = received_at;
+    }
+
+    fn new_message(&self, line: String, pri: Option<SyslogPri>) -> RawMessage {
+        RawMessage::with_pri(line, pri)
+            .with_ref_time(self.ref_time)
+            .with_received_at(Some(self.received_at.unwrap_or_else(Utc::now)))
+            .with_source(self.source.clone())
+            .with_meta(self.meta.clone())
+    }
+
     // drop leading \r or \n s in buffer
     fn drop_leading_newlines(&mut self) {
         loop {
@@ -51,7 +109,8 @@ impl LinesBuffer {
         }
     }
 
-    fn drop_syslog_priority(&mut self) {
+    // strip the syslog priority prefix (e.g. <191>) and return its decoded value
+    fn read_syslog_priority(&mut self) -> Option<SyslogPri> {
         let first_c = self.buf.first();
         if let Some(&SYSLOG_PRI_OPEN_TAG) = first_c {
             let mut iter = self.buf.iter();
@@ -61,25 +120,33 @@ impl LinesBuffer {
                 if DECIMAL_DIGIT_CHARS.contains(c) {
                     to_advance += 1;
                 } else if SYSLOG_PRI_CLOSE_TAG == *c {
+                    let pri = std::str::from_utf8(&self.buf[1..to_advance])
+                        .ok()
+                        .and_then(SyslogPri::parse);
                     self.buf.advance(to_advance + 1);
-                    break;
+                    return pri;
                 } else {
                     break; // not a digit, nor a close tag - not a priority prefix
                 }
             }
         }
+        None
     }
 
-    fn read_line_from_buf(&mut self) -> Option<String> {
-        self.drop_syslog_priority(); // TODO make this call optional?
+    fn read_line_from_buf(&mut self) -> Option<RawMessage> {
         let pos_of_nl = self.buf.find_byteset(LINE_ENDING_CHARS);
         if pos_of_nl.is_none() {
             None
         } else {
-            let line = self.buf.split_to(pos_of_nl.unwrap());
+            let buf_len = self.buf.len();
+            let pri = self.read_syslog_priority(); // TODO make this call optional?
+            let pos_of_nl = self.buf.find_byteset(LINE_ENDING_CHARS).unwrap();
+            let line = self.buf.split_to(pos_of_nl);
             let utf8_str = String::from_utf8_lossy(line.as_ref()).to_string();
             self.drop_leading_newlines();
-            Some(utf8_str)
+            self.line_start = self.consumed;
+            self.consumed += (buf_len - self.buf.len()) as u64;
+            Some(self.new_message(utf8_str, pri))
         }
     }
 
@@ -91,12 +158,16 @@ impl LinesBuffer {
                 let lm = self.line_merger.as_mut().unwrap();
                 ret = lm.add_line(line);
                 if ret.is_some() {
+                    // the line starts the next (pending) message
+                    self.emitted = self.line_start;
                     break;
                 }
             }
             ret
         } else {
-            self.read_line_from_buf().map(|ln| RawMessage::new(ln))
+            let ret = self.read_line_from_buf();
+            self.emitted = self.consumed;
+            ret
         }
     }
 
@@ -109,12 +180,14 @@ impl LinesBuffer {
                 let line_ret = lm.add_line(line);
                 if line_ret.is_some() {
                     ret.push(line_ret.unwrap());
+                    self.emitted = self.line_start;
                 }
             }
         } else {
-            while let Some(ln) = self.read_line_from_buf() {
-                ret.push(RawMessage::new(ln));
+            while let Some(msg) = self.read_line_from_buf() {
+                ret.push(msg);
             }
+            self.emitted = self.consumed;
         }
         ret
     }
@@ -132,11 +205,17 @@ impl LinesBuffer {
         while let Some(msg) = self.read_message_from_buf() {
             ret.push(msg)
         }
+        let rest_len = self.buf.len() as u64;
         let last_line = if self.buf.is_empty() {
             None
         } else {
-            Some(String::from_utf8_lossy(self.buf.as_ref()).to_string())
+            let pri = self.read_syslog_priority();
+            let line = String::from_utf8_lossy(self.buf.as_ref()).to_string();
+            self.buf.clear();
+            Some(self.new_message(line, pri))
         };
+        self.consumed += rest_len;
+        self.emitted = self.consumed;
         if self.line_merger.is_some() {
             let lm = self.line_merger.as_mut().unwrap();
             if last_line.is_some() {
@@ -147,10 +226,8 @@ impl LinesBuffer {
             if let Some(last_msg) = self.line_merger.as_mut().unwrap().flush() {
                 ret.push(last_msg)
             }
-        } else {
-            if last_line.is_some() {
-                ret.push(RawMessage::new(last_line.unwrap()))
-            }
+        } else if let Some(last_line) = last_line {
+            ret.push(last_line)
         }
         ret
     }
@@ -159,8 +236,20 @@ impl LinesBuffer {
 #[cfg(test)]
 mod tests {
     use crate::async_pipeline::LinesBuffer;
+    use crate::parser::{LineMergerConfig, DEFAULT_MERGE_MAX_BYTES, DEFAULT_MERGE_MAX_LINES};
     use bytes::{BufMut, BytesMut};
 
+    fn test_line_merger_conf() -> LineMergerConfig {
+        LineMergerConfig::new(
+            "space",
+            None,
+            DEFAULT_MERGE_MAX_LINES,
+            DEFAULT_MERGE_MAX_BYTES,
+            " ",
+        )
+        .unwrap()
+    }
+
     const TEST_TEXT: &'static str = r#"<191>May 25 00:30:05 actek-mac syslogd[106]: Configuration Notice:
 	ASL Module "com.apple.authd" claims selected messages.
 	Those messages may not appear in standard system log files or in the ASL database.
@@ -203,7 +292,7 @@ mod tests {
 
     #[test]
     fn test_line_buffer_with_lm1() {
-        let mut lb = LinesBuffer::new(true);
+        let mut lb = LinesBuffer::new(Some(&test_line_merger_conf()));
         fill_buf(lb.get_buf(), false);
         let mut lines = lb.read_messages_from_buf();
         let mut flush_lines = lb.flush();
@@ -217,7 +306,7 @@ mod tests {
 
     #[test]
     fn test_line_buffer_with_lm2() {
-        let mut lb = LinesBuffer::new(true);
+        let mut lb = LinesBuffer::new(Some(&test_line_merger_conf()));
         for _ in 0..64 {
             fill_buf(lb.get_buf(), true);
         }
@@ -233,7 +322,7 @@ mod tests {
 
     #[test]
     fn test_line_buffer_no_lm1() {
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         fill_buf(lb.get_buf(), false);
         let mut lines = lb.read_messages_from_buf();
         let mut flush_lines = lb.flush();
@@ -247,7 +336,7 @@ mod tests {
 
     #[test]
     fn test_line_buffer_no_lm2() {
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         for _ in 0..64 {
             fill_buf(lb.get_buf(), true);
         }
@@ -264,7 +353,7 @@ mod tests {
 
     #[test]
     fn test_line_buffer_no_lm3() {
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         let mut lines = lb.read_messages_from_buf();
         for _ in 0..64 {
             fill_buf(lb.get_buf(), true);
@@ -280,4 +369,42 @@ mod tests {
         //     println!("LINE: {}", ln.as_str())
         // }
     }
+
+    #[test]
+    fn test_line_buffer_syslog_pri() {
+        let mut lb = LinesBuffer::new(Some(&test_line_merger_conf()));
+        fill_buf(lb.get_buf(), false);
+        lb.get_buf()
+            .put("\n<13>May 25 01:40:00 actek-mac user: no pri\n".as_bytes());
+        let mut lines = lb.read_messages_from_buf();
+        let mut flush_lines = lb.flush();
+        lines.append(&mut flush_lines);
+        assert_eq!(15, lines.len());
+        let first = lines.first().unwrap();
+        assert!(first.as_str().starts_with("May 25 00:30:05"));
+        assert_eq!(first.pri().unwrap().facility_name(), "local7");
+        assert_eq!(first.pri().unwrap().severity_name(), "debug");
+        let last = lines.last().unwrap();
+        assert_eq!(last.pri().unwrap().facility(), 1);
+        assert_eq!(last.pri().unwrap().severity(), 5);
+    }
+
+    #[test]
+    fn test_line_buffer_emitted_offset() {
+        let mut lb = LinesBuffer::new(None);
+        lb.set_offset(100);
+        lb.get_buf().put("<13>first\r\nsecond\npart".as_bytes());
+        assert_eq!(lb.read_messages_from_buf().len(), 2);
+        assert_eq!(lb.emitted_offset(), 100 + 18);
+        assert_eq!(lb.flush().len(), 1);
+        assert_eq!(lb.emitted_offset(), 100 + 22);
+
+        let mut lb = LinesBuffer::new(Some(&test_line_merger_conf()));
+        lb.get_buf().put("one\n two\nthree\n four\n".as_bytes());
+        assert_eq!(lb.read_messages_from_buf().len(), 1);
+        // "three" is pending in the line merger
+        assert_eq!(lb.emitted_offset(), 9);
+        assert_eq!(lb.flush().len(), 1);
+        assert_eq!(lb.emitted_offset(), 21);
+    }
 }
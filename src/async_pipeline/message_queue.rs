@@ -2,7 +2,9 @@ use crate::DynError;
 use log::{debug, error};
 use std::error::Error;
 use std::fmt;
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedSender};
 use tokio::task::JoinHandle;
 
 pub struct QueueJoinHandle {
@@ -41,9 +43,46 @@ impl QueueJoinHandle {
 pub enum QueueMessage<T> {
     Data(T),
     Flush,
+    Checkpoint(CheckpointMarker),
     Shutdown,
 }
 
+/// Sent down the pipeline after the data read up to an input checkpoint. It flushes the
+/// stages like Flush and is acknowledged by every output after flushing its sink, the
+/// checkpoint id is sent to the ack channel once all of them have done so.
+pub struct CheckpointMarker {
+    id: u64,
+    pending: Arc<AtomicUsize>,
+    ack_tx: UnboundedSender<u64>,
+}
+
+impl CheckpointMarker {
+    pub fn new(id: u64, ack_tx: UnboundedSender<u64>) -> Self {
+        Self {
+            id,
+            pending: Arc::new(AtomicUsize::new(1)),
+            ack_tx,
+        }
+    }
+
+    /// Another marker for the same checkpoint, to be sent to one more output
+    pub fn fork(&self) -> Self {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        Self {
+            id: self.id,
+            pending: self.pending.clone(),
+            ack_tx: self.ack_tx.clone(),
+        }
+    }
+
+    pub fn ack(self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // the receiver is gone only when not interested anymore
+            let _ = self.ack_tx.send(self.id);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct QueueError(String);
 impl fmt::Display for QueueError {
@@ -87,6 +126,13 @@ impl<T> MessageSender<T> {
             .map_err(|e| QueueError(e.to_string()))
     }
 
+    pub async fn checkpoint(&self, marker: CheckpointMarker) -> Result<(), QueueError> {
+        self.channel_sender
+            .send(QueueMessage::Checkpoint(marker))
+            .await
+            .map_err(|e| QueueError(e.to_string()))
+    }
+
     pub fn clone_sender(&self) -> Self {
         Self {
             channel_sender: self.channel_sender.clone(),
@@ -172,6 +218,10 @@ pub mod tests {
                             self.buf.truncate(0);
                         }
                     }
+                    QueueMessage::Checkpoint(marker) => {
+                        self.flushed += 1;
+                        marker.ack();
+                    }
                     QueueMessage::Shutdown => {
                         self.shutdown += 1;
                         break;
@@ -1,6 +1,7 @@
 pub mod async_parser;
 mod async_pipeline;
 pub mod batching_queue;
+pub mod dead_letter;
 pub mod lines_buffer;
 pub mod message_queue;
 pub mod output_processor;
@@ -77,6 +77,14 @@ impl OutputProcessor {
                         break;
                     }
                 }
+                QueueMessage::Checkpoint(marker) => {
+                    let mut sink = self.output_sink.lock().await;
+                    if let Err(err) = sink.flush() {
+                        error!("Failed to flush output sink, aborting: {:?}", err);
+                        break;
+                    }
+                    marker.ack();
+                }
                 QueueMessage::Shutdown => {
                     info!("Shutdown message received");
                     let mut sink = self.output_sink.lock().await;
@@ -147,6 +147,18 @@ impl SqlBatchProcessor {
                         break;
                     }
                 }
+                QueueMessage::Checkpoint(marker) => {
+                    if let Err(err) = self
+                        .output_sender
+                        .as_ref()
+                        .unwrap()
+                        .checkpoint(marker)
+                        .await
+                    {
+                        error!("Failed to send checkpoint downstream, aborting: {:?}", err);
+                        break;
+                    }
+                }
                 QueueMessage::Shutdown => {
                     info!("Shutdown message received");
                     if let Err(err) = self.output_sender.as_ref().unwrap().shutdown().await {
@@ -179,7 +191,7 @@ mod tests {
         let bp = SqlBatchProcessor::new("select * from DUMMY", &schema, 2).unwrap();
         let (sender, bjh) = bp.wrap_sender(test_queue_sender).unwrap();
         let parser = GrokParser::new(schema).unwrap();
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         lb.get_buf().put(test_dummy_data(100).as_bytes());
         let mut ql_rows = Vec::new();
         for raw in lb.flush() {
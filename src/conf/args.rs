@@ -1,8 +1,8 @@
-use crate::conf::external::ExternalConfig;
+use crate::conf::external::{ExternalConfig, ExternalSchemaConfig};
 use crate::DynError;
 use clap::Parser;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(name = "hustlog")]
 #[clap(author = "Asen Lazarov <asen.lazarov@gmail.com>")]
 #[clap(version = "0.1")]
@@ -13,8 +13,30 @@ pub struct MyArgs {
     #[clap(long)]
     pub grok_list_default_patterns: bool,
 
+    /// Sample the first N input messages, infer the schema columns from the grok pattern
+    /// named captures and print them as YAML (grok_schema_columns), then exit.
+    /// The derived (e.g. host=hostname) schema columns are kept, the others are replaced.
+    /// Not available in config
+    #[clap(long)]
+    pub grok_infer_schema: Option<usize>,
+
+    /// With --grok-infer-schema, process the (file) input using the inferred columns
+    /// instead of printing them. Not available in config
+    #[clap(long, requires = "grok-infer-schema")]
+    pub grok_infer_schema_run: bool,
+
+    /// Explain how the grok pattern (and the fallback ones) match the first N input
+    /// messages - the expanded regex, the longest matching prefix and the failing
+    /// sub-pattern, or the captured and converted column values, then exit.
+    /// Not available in config
+    #[clap(long)]
+    pub grok_debug: Option<usize>,
+
     /// Yaml config file to use for default values.
-    /// Command line options still override conf values
+    /// Command line options still override conf values.
+    /// The config file can also define a list of named "schemas", each with its own
+    /// parser settings, columns, query and output. Every message goes to the first
+    /// schema which parses it. With multiple schemas each one needs its own output.
     #[clap(short, long)]
     pub conf: Option<String>,
 
@@ -25,8 +47,44 @@ pub struct MyArgs {
     /// -i /var/log/system.log
     /// -i syslog-tcp:localhost:10514
     /// -i syslog-udp:localhost:10514
+    /// Files can be given multiple times and as globs, e.g. -i '/var/log/syslog*', these
+    /// are processed one after the other through the same pipeline (see --input-order)
+    /// A directory to watch for new files is given as dir:<path>, e.g. -i dir:/var/spool/logs
+    /// Following a file and watching a directory stop on SIGINT (Ctrl-C) or SIGTERM
     #[clap(short, long)]
-    pub input: Option<String>,
+    pub input: Vec<String>,
+
+    /// The order of processing multiple input files, one of:
+    ///     given (default) - as given, with the files matching a glob sorted by name
+    ///     name - sorted by name
+    ///     mtime - sorted by modification time, oldest first (e.g. rotated logs)
+    #[clap(long)]
+    pub input_order: Option<String>,
+
+    /// Keep reading the data appended to the input file until interrupted (like tail -F),
+    /// reading the file from the start again when it is rotated (replaced by a new file)
+    /// or truncated. The buffers are flushed every --tick-interval seconds and on
+    /// SIGINT (Ctrl-C) or SIGTERM, which stop following.
+    /// With multiple input files the last uncompressed one is followed, after reading the
+    /// others (e.g. the compressed rotated ones).
+    #[clap(long)]
+    pub follow: bool,
+
+    /// A state file to keep the input file checkpoints in (the file identity and the offset
+    /// of the last message written), updated after the outputs have flushed the messages.
+    /// Reading the input resumes from its checkpoint, unless it was rotated or truncated.
+    #[clap(long)]
+    pub checkpoint_file: Option<String>,
+
+    /// With a directory input (-i dir:<path>), only process the files with names matching
+    /// this glob, e.g. "*.log". Hidden (dot) files are always skipped
+    #[clap(long)]
+    pub watch_pattern: Option<String>,
+
+    /// With a directory input (-i dir:<path>), record the processed files in this state file
+    /// instead of moving them to the done/failed subdirectories of the watched directory
+    #[clap(long)]
+    pub watch_state_file: Option<String>,
 
     /// Output destination
     /// Currently only file + stdout output is supported
@@ -72,7 +130,46 @@ pub struct MyArgs {
     #[clap(short = 'g', long)]
     pub grok_pattern: Option<String>,
 
-    /// Grok Patterns file to use
+    /// Grok pattern names to try in turn when --grok-pattern does not match a line,
+    /// can be multiple. When set, the name of the pattern which matched is
+    /// available as the "_pattern" column, e.g.
+    ///     -g SYSLOGLINE --grok-fallback-patterns SYSLOGBASE2 -s +message
+    #[clap(long)]
+    pub grok_fallback_patterns: Vec<String>,
+
+    /// Parser to use for the input messages. One of:
+    ///     grok (default) - use the grok pattern set by --grok-pattern
+    ///     regex - use the regular expression set by --regex-pattern
+    ///     json - parse JSON lines, the column lookup names are field paths, e.g. -s +http.status:int
+    ///     logfmt - parse key=value pairs, the column lookup names are the keys
+    ///     rfc5424 - parse RFC 5424 syslog messages, the column lookup names are
+    ///         version, timestamp, hostname, app_name, procid, msgid, message, sd (the raw
+    ///         structured data) and sd.<SD-ID>.<PARAM-NAME>, e.g. -s sd.origin.ip
+    #[clap(short = 'P', long)]
+    pub parser: Option<String>,
+
+    /// Regular expression to use with the regex parser (--parser regex).
+    /// Named capture groups, e.g. (?P<message>.*) are mapped to the schema columns
+    /// lookup names the same way grok captures are. E.g.
+    ///     --regex-pattern '^(?P<num>\d+) (?P<message>.*)$' -s +num:int -s +message
+    #[clap(long)]
+    pub regex_pattern: Option<String>,
+
+    /// With the logfmt parser (--parser logfmt), add a string column with this name,
+    /// collecting all the keys not used by the other columns (as key=value pairs)
+    #[clap(long)]
+    pub logfmt_extra_column: Option<String>,
+
+    /// Extract key/value pairs from a parsed field, as <field>[:<delimiter>[:<separator>]]
+    /// (default " " and "="), can be multiple. The values are available to the columns as
+    /// <field>.<key> lookup names and in the queries as <field>['<key>'], e.g.
+    ///     --kv-extract message -s +message -s message.user -s message.status:int
+    ///     -q "select message['session'] as session from SYSLOGLINE"
+    #[clap(long)]
+    pub kv_extract: Vec<String>,
+
+    /// Grok Patterns file to use, with one "NAME pattern" definition per line
+    /// (# comments and empty lines are skipped), loaded before the extra patterns
     #[clap(short = 'p', long)]
     pub grok_patterns_file: Option<String>,
 
@@ -100,27 +197,99 @@ pub struct MyArgs {
     /// followed by the type: [+]<column_name>[:type_spec]
     ///
     /// Type spec can be one of:
-    ///   - str (default, can be omitted)
+    ///   - str[:<max_len>[:<policy>]] (default, can be omitted) - string value of up to
+    ///     max_len (default 256) bytes. The policy for longer values is one of reject
+    ///     (the message, the default for required columns), null (the default for
+    ///     optional columns), truncate or truncate-marker (truncate, ending with "...").
+    ///     The number of oversize values per column is logged at exit.
     ///   - int - integer value
     ///   - float - floating point value
     ///   - bool - a "true" or "false" value.
+    ///   - duration[:<unit>] - a duration given with units (12ms, 1.5s, 1h30m, units ns, us,
+    ///     ms, s, m, h, d) or as [hh:]mm:ss[.fff], numbers without a unit are in the
+    ///     given unit (default s). Output as e.g. 1m2.3s (CSV) or seconds (SQL/ODBC).
+    ///     The queries compare durations to values with a unit, e.g. took > '1.5s'
+    ///   - ip - an IPv4 or IPv6 address, see the IP_IN_CIDR(ip, 'network/prefix') and
+    ///     IP_FAMILY(ip) query functions
     ///   - ts:<ts_format> - timestamp type which includes the format string to be used to
-    ///         parse timestamps out of the parser input strings
+    ///     parse timestamps out of the parser input strings. Formats without an offset
+    ///     can end with a time zone name, e.g. "ts:%b %e %H:%M:%S:Europe/Sofia"
+    ///   - ts:epoch_s, ts:epoch_ms, ts:epoch_us, ts:epoch_ns - Unix epoch timestamps in
+    ///     seconds, milli-, micro- or nanoseconds, can be fractional, e.g. 1652140319.123
+    ///
+    /// Columns not coming from the parser are declared as <column_name>=<source>, where
+    /// the source is one of:
+    ///   - hostname - the name of the host running hustlog
+    ///   - input_file - the input file name (NULL for stdin and the syslog servers)
+    ///   - raw_hash - SHA-256 (hex) of the raw message, e.g. for deduplication
+    ///   - const:<value> - a constant string
     ///
     /// E.g.
     ///     -s "+timestamp:ts:%Y-%m-%d %H:%M:%S.%3f%z"
     ///     -s message:str
     ///     -s pid:int
     ///     -s resp_time:float
+    ///     -s env=const:prod
     //      -s another_str
+    ///
+    /// Messages starting with a syslog priority (e.g. <191>) also provide the built-in
+    /// facility and severity (numbers) and facility_name and severity_name columns, e.g.
+    ///     -s severity:int -s facility_name -q "select ... where severity <= 3"
+    ///
+    /// The messages also provide the built-in _received_at column (e.g. -s "_received_at:ts:%+")
+    /// and the syslog server ones the _remote_addr, _listener and _protocol (tcp/udp) columns
     #[clap(short = 's', long)]
     pub grok_schema_columns: Vec<String>,
 
-    /// Whether to merge lines starting with whitespace with the previous ones.
-    /// Useful if there are multi-line messages in the input.
-    /// Note that since the grok parsing is line-based, the new lines are replaced with space.
-    #[clap(short, long)]
-    pub merge_multi_line: bool,
+    /// Time zone (e.g. Europe/Sofia or UTC) of the timestamps without an offset, unless
+    /// their column sets one. Default is the system local time zone
+    #[clap(long)]
+    pub input_timezone: Option<String>,
+
+    /// Time zone (e.g. UTC) to convert the timestamps to when writing CSV or SQL output.
+    /// By default timestamps keep the offset they were parsed with
+    #[clap(long)]
+    pub output_timezone: Option<String>,
+
+    /// File (or "-" for stdout) to write the messages which failed to parse to, together
+    /// with the parse error, the source and the receive time. By default these are only
+    /// logged.
+    #[clap(long)]
+    pub dead_letter_output: Option<String>,
+
+    /// Dead letter output format - csv (default), sql or odbc (the output is then
+    /// the connection string). The sql/odbc table name is dead_letter
+    #[clap(long)]
+    pub dead_letter_format: Option<String>,
+
+    /// How to merge multi-line messages in the input, no merging by default:
+    /// space - lines starting with whitespace are merged with the previous ones,
+    /// start-regex - lines matching --merge-pattern start a new message, the rest are merged,
+    /// continuation-regex - lines matching --merge-pattern are merged with the previous ones.
+    /// Just -m means space
+    #[clap(short, long, min_values = 0, default_missing_value = "space")]
+    pub merge_multi_line: Option<String>,
+
+    /// The (rust) regex for the start-regex and continuation-regex multi-line mergers,
+    /// e.g. "^\d{4}-\d{2}-\d{2} " or "^(\s|Caused by:)"
+    #[clap(long)]
+    pub merge_pattern: Option<String>,
+
+    /// Maximum number of lines in a merged message, a new message is started after that.
+    /// Default is 1000
+    #[clap(long)]
+    pub merge_max_lines: Option<usize>,
+
+    /// Maximum size (in bytes) of a merged message, a new message is started after that.
+    /// Default is 1MB
+    #[clap(long)]
+    pub merge_max_bytes: Option<usize>,
+
+    /// The string to join the merged lines with, default is a single space.
+    /// \n, \r, \t and \\ escapes are supported. Note that the grok/regex patterns
+    /// need the (?s) flag for . to match the kept new lines
+    #[clap(long)]
+    pub merge_join_str: Option<String>,
 
     /// How many threads to use in the "CPU-intensive work" (Rayon) thread pool.
     /// Default is 2
@@ -158,6 +327,11 @@ pub struct MyArgs {
     // e.g. table engine and exit.
     #[clap(long)]
     pub ddl_table_opts: Option<String>,
+
+    /// The CREATE TABLE column type of the ip columns, default VARCHAR(45)
+    // e.g. --ddl-ip-type INET for PostgreSQL
+    #[clap(long)]
+    pub ddl_ip_type: Option<String>,
 }
 
 impl MyArgs {
@@ -184,6 +358,51 @@ impl MyArgs {
     //     Ok(writer)
     // }
 
+    /// Return a copy of the args with the schema-specific options
+    /// replaced by the ones set in the (named) schema config
+    pub fn with_schema_conf(&self, esc: &ExternalSchemaConfig) -> MyArgs {
+        let mut ret = self.clone();
+        if esc.parser.is_some() {
+            ret.parser = esc.parser.clone();
+        }
+        if esc.grok_pattern.is_some() {
+            ret.grok_pattern = esc.grok_pattern.clone();
+        }
+        if let Some(fallback_patterns) = &esc.grok_fallback_patterns {
+            ret.grok_fallback_patterns = fallback_patterns.clone();
+        }
+        if esc.regex_pattern.is_some() {
+            ret.regex_pattern = esc.regex_pattern.clone();
+        }
+        if esc.logfmt_extra_column.is_some() {
+            ret.logfmt_extra_column = esc.logfmt_extra_column.clone();
+        }
+        if let Some(kv_extract) = &esc.kv_extract {
+            ret.kv_extract = kv_extract.clone();
+        }
+        if let Some(columns) = &esc.grok_schema_columns {
+            ret.grok_schema_columns = columns.clone();
+        }
+        if esc.query.is_some() {
+            ret.query = esc.query.clone();
+        }
+        if esc.output.is_some() {
+            ret.output = esc.output.clone();
+        }
+        if esc.output_format.is_some() {
+            ret.output_format = esc.output_format.clone();
+        }
+        if let Some(add_ddl) = esc.output_add_ddl {
+            ret.output_add_ddl = add_ddl;
+        }
+        // the table name defaults to the schema name
+        ret.output_table_name = esc
+            .output_table_name
+            .clone()
+            .or_else(|| Some(esc.name.clone()));
+        ret
+    }
+
     pub fn grok_list_default_patterns(&self) -> bool {
         self.grok_list_default_patterns
     }
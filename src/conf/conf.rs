@@ -1,13 +1,24 @@
+use crate::async_pipeline::LinesBuffer;
 use crate::conf::external::ExternalConfig;
-use crate::parser::{str2type, GrokColumnDef, GrokSchema};
+use crate::file_processor::decompress_async_read;
+use crate::parser::{
+    split_str_overflow_policy, str2type, DerivedColumnDef, GrokColumnDef, GrokDebugger, GrokParser,
+    GrokSchema, KvExtractor, LineMergerConfig, ParsedValueType, ParserSchema, RawMessage,
+    SchemaInference, DEFAULT_MERGE_MAX_BYTES, DEFAULT_MERGE_MAX_LINES,
+};
 use crate::syslog_server::SyslogServerConfig;
 use crate::{ConfigError, MyArgs};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use glob::Pattern;
+use log::warn;
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fs;
 use std::io::{self, BufWriter, Write};
 use std::pin::Pin;
 use std::sync::Arc;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio_rayon::rayon::ThreadPoolBuilder;
 
 macro_rules! args_or_external_vec {
@@ -106,39 +117,190 @@ pub enum OutputFormat {
     ODBC,
 }
 
-#[derive(Debug, Clone)]
-pub struct HustlogConfig {
-    input: String,
-    merge_multi_line: bool,
+impl OutputFormat {
+    fn from_name(name: &str) -> OutputFormat {
+        match name {
+            "sql" => OutputFormat::SQL,
+            "odbc" => OutputFormat::ODBC,
+            _ => OutputFormat::DEFAULT,
+        }
+    }
+}
+
+/// The order of the input files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputOrder {
+    GIVEN, // as given, the matches of each glob sorted by name
+    NAME,
+    MTIME, // oldest first
+}
+
+impl InputOrder {
+    fn from_name(name: &str) -> Result<InputOrder, ConfigError> {
+        match name {
+            "given" => Ok(InputOrder::GIVEN),
+            "name" => Ok(InputOrder::NAME),
+            "mtime" => Ok(InputOrder::MTIME),
+            x => Err(ConfigError::new(
+                format!(
+                    "Invalid input order (must be one of given, name, mtime): {}",
+                    x
+                )
+                .as_str(),
+            )),
+        }
+    }
+}
+
+/// The input specs (paths, globs, "-", a watched directory or a syslog server) from the args
+/// or the config file
+fn input_specs(args: &MyArgs, external_conf: &ExternalConfig) -> Vec<String> {
+    let stdin = vec!["-".to_string()];
+    args_or_external_vec_default!(&args, &external_conf, input, &stdin).clone()
+}
+
+/// Expand the input globs, return the input files without duplicates in the given order
+fn resolve_input_files(inputs: &[String], order: InputOrder) -> Result<Vec<String>, DynError> {
+    let mut ret: Vec<String> = Vec::new();
+    for input in inputs {
+        let is_glob = input.contains(['*', '?', '[']);
+        let mut matches = if is_glob {
+            glob::glob(input)?
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .map(|p| p.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        } else {
+            vec![input.clone()]
+        };
+        if matches.is_empty() {
+            warn!("No input files match {}", input);
+        }
+        matches.sort();
+        for m in matches {
+            if !ret.contains(&m) {
+                ret.push(m);
+            }
+        }
+    }
+    if ret.is_empty() {
+        return Err(Box::new(ConfigError::new("No input files found")));
+    }
+    match order {
+        InputOrder::GIVEN => {}
+        InputOrder::NAME => ret.sort(),
+        InputOrder::MTIME => {
+            let mut with_mtime = ret
+                .into_iter()
+                .map(|f| Ok((fs::metadata(&f)?.modified()?, f)))
+                .collect::<Result<Vec<_>, std::io::Error>>()?;
+            // the name order for the same mtime
+            with_mtime.sort();
+            ret = with_mtime.into_iter().map(|(_, f)| f).collect();
+        }
+    }
+    Ok(ret)
+}
+
+/// The (name, pattern) definitions of a grok patterns file, one "NAME pattern" per line,
+/// skipping the # comments and the empty lines
+fn load_grok_patterns_file(patterns_file: &str) -> Result<Vec<(String, String)>, DynError> {
+    let mut ret = Vec::new();
+    for (ix, ln) in fs::read_to_string(patterns_file)?.lines().enumerate() {
+        let ln = ln.trim();
+        if ln.is_empty() || ln.starts_with('#') {
+            continue;
+        }
+        match ln.split_once(char::is_whitespace) {
+            Some((name, pattern)) if !pattern.trim().is_empty() => {
+                ret.push((name.to_string(), pattern.trim().to_string()))
+            }
+            _ => return Err(Box::new(ConfigError::new(
+                format!(
+                    "Invalid grok pattern definition (must be \"NAME pattern\") in {} line {}: {}",
+                    patterns_file,
+                    ix + 1,
+                    ln
+                )
+                .as_str(),
+            ))),
+        }
+    }
+    Ok(ret)
+}
+
+/// The (decompressed) reader of an input file, "-" is stdin
+async fn open_async_read(input: &str) -> Result<Pin<DynAsyncRead>, DynError> {
+    let reader: Pin<DynAsyncRead> = if input == "-" {
+        Box::pin(tokio::io::stdin())
+    } else {
+        Box::pin(tokio::fs::File::open(input).await?)
+    };
+    let (reader, _) = decompress_async_read(reader, input).await?;
+    Ok(reader)
+}
+
+/// A writer for a file output (appending), "-" is stdout
+pub fn open_output_writer(output: &str) -> Result<DynBoxWrite, DynError> {
+    let writer: DynBoxWrite = if output == "-" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        Box::new(BufWriter::new(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output)?,
+        ))
+    };
+    Ok(writer)
+}
+
+/// An IANA time zone name (e.g. Europe/Sofia), None if empty
+fn parse_timezone(tz_name: &str) -> Result<Option<Tz>, DynError> {
+    if tz_name.is_empty() {
+        return Ok(None);
+    }
+    match tz_name.parse::<Tz>() {
+        Ok(tz) => Ok(Some(tz)),
+        Err(_) => Err(Box::new(ConfigError::new(
+            format!("Invalid time zone: {}", tz_name).as_str(),
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParserType {
+    GROK,
+    REGEX,
+    JSON,
+    LOGFMT,
+    RFC5424,
+}
 
+/// The per-schema part of the configuration - how to parse the messages
+/// and where the resulting rows should go. There is a single (unnamed) schema
+/// unless the config file has a "schemas" list.
+#[derive(Debug, Clone)]
+pub struct SchemaConfig {
+    name: String,
+    parser_type: ParserType,
+    logfmt_extra_column: Option<Arc<str>>,
     grok_schema: GrokSchema,
     query: Option<String>,
 
     output: String,
     output_format: String,
-    output_batch_size: usize,
     output_add_ddl: bool,
-
-    rayon_threads: usize,
-    tick_interval: u64,
-
-    idle_timeout: u64,
-
-    async_channel_size: usize,
-    //async_file_processing: bool,
-
-    // ddl_only: bool,
-    ddl_pre_name_opts: Arc<str>,
-    ddl_table_opts:Arc<str>,
 }
 
-impl HustlogConfig {
-    pub fn new(args: MyArgs) -> Result<HustlogConfig, DynError> {
-        let external_conf = args.get_external_conf()?;
-        let schema = Self::parse_grok_schema(&args, &external_conf)?;
-        let input = args_or_external_opt_default!(&args, &external_conf, input, "-");
-        let merge_multi_line =
-            args_or_external_bool_default!(&args, &external_conf, merge_multi_line, false);
+impl SchemaConfig {
+    fn new(
+        name: Option<&str>,
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+    ) -> Result<SchemaConfig, DynError> {
+        let parser_type = Self::parse_parser_type(args, external_conf)?;
+        let schema = Self::parse_grok_schema(args, external_conf, parser_type)?;
         let query_str_ref = args_or_external_opt_default!(&args, &external_conf, query, "");
         let query_str: Option<String> = if query_str_ref == "" {
             None
@@ -148,61 +310,44 @@ impl HustlogConfig {
         let output = args_or_external_opt_default!(&args, &external_conf, output, "-");
         let output_format =
             args_or_external_opt_default!(&args, &external_conf, output_format, "csv");
-        let output_batch_size =
-            args_or_external_opt_default!(&args, &external_conf, output_batch_size, &1000);
         let output_add_ddl =
             args_or_external_bool_default!(&args, &external_conf, output_add_ddl, false);
-        // let async_file_processing = if args.async_file_processing.is_some() {
-        //     args.async_file_processing.unwrap()
-        // } else {
-        //     external_conf.async_file_processing.unwrap_or(true)
-        // };
-        let ddl_pre_name_opts =
-            args_or_external_opt_default!(&args, &external_conf, ddl_pre_name_opts, "");
-        let ddl_pre_name_opts: Arc<str> = Arc::from(ddl_pre_name_opts.as_ref());
-        let ddl_table_opts =
-            args_or_external_opt_default!(&args, &external_conf, ddl_table_opts, "");
-        let ddl_table_opts: Arc<str> = Arc::from(ddl_table_opts.as_ref());
         Ok(Self {
-            input: input.to_string(),
-            merge_multi_line: merge_multi_line,
+            name: name.unwrap_or(schema.output_name()).to_string(),
+            parser_type,
+            logfmt_extra_column: Self::parse_logfmt_extra_column(args, external_conf),
             grok_schema: schema,
             query: query_str,
             output: output.to_string(),
             output_format: output_format.to_string(),
-            output_batch_size: *output_batch_size,
             output_add_ddl: output_add_ddl,
-            rayon_threads: *args_or_external_opt_default!(&args, &external_conf, rayon_threads, &2),
-            tick_interval: *args_or_external_opt_default!(
-                &args,
-                &external_conf,
-                tick_interval,
-                &30
-            ),
-            idle_timeout: *args_or_external_opt_default!(&args, &external_conf, idle_timeout, &30),
-            async_channel_size: *args_or_external_opt_default!(
-                &args,
-                &external_conf,
-                async_channel_size,
-                &1000
-            ),
-            //async_file_processing,
-            ddl_pre_name_opts,
-            ddl_table_opts,
         })
     }
 
+    /// The parser columns and the derived (computed/constant) ones
     fn parse_col_defs(
         args: &MyArgs,
         external_conf: &ExternalConfig,
-    ) -> Result<Vec<GrokColumnDef>, DynError> {
+    ) -> Result<(Vec<GrokColumnDef>, Vec<DerivedColumnDef>), DynError> {
         let schema_columns = args_or_external_vec!(
             &args,
             &external_conf,
             grok_schema_columns,
             "At least one grok schema column is required (-s), use with --help for more information"
         )?;
-        let grok_schema_cols: Vec<_> = schema_columns
+        let input_timezone: &str =
+            args_or_external_opt_default!(&args, &external_conf, input_timezone, "");
+        let input_tz = parse_timezone(input_timezone)?;
+        let mut derived_cols = Vec::new();
+        let mut parser_col_specs = Vec::new();
+        for x in schema_columns {
+            match DerivedColumnDef::parse(x) {
+                Ok(Some(dc)) => derived_cols.push(dc),
+                Ok(None) => parser_col_specs.push(x),
+                Err(desc) => return Err(Box::new(ConfigError::new(desc.as_str()))),
+            }
+        }
+        let grok_schema_cols: Vec<_> = parser_col_specs
             .iter()
             .map(|x| {
                 let mut my_iter = x.splitn(2, ":").into_iter();
@@ -222,17 +367,29 @@ impl HustlogConfig {
                     Err(Box::new(ConfigError::new("Empty lookup names")))
                 } else {
                     let col_type = my_iter.next().unwrap_or("str");
-                    let col_type = str2type(col_type);
-                    if col_type.is_none() {
-                        Err(Box::new(ConfigError::new("Invalid column type")))
-                    } else {
-                        let col_name = lookup_names.first().unwrap().clone();
-                        Ok(GrokColumnDef::new(
-                            Arc::from(col_name.as_str()),
-                            col_type.unwrap(),
-                            lookup_names,
-                            required,
-                        ))
+                    let (col_type, str_overflow) = split_str_overflow_policy(col_type)
+                        .map_err(|desc| Box::new(ConfigError::new(desc.as_str())))?;
+                    match str2type(col_type) {
+                        None => Err(Box::new(ConfigError::new("Invalid column type"))),
+                        Some(col_type) => {
+                            let col_name = lookup_names.first().unwrap().clone();
+                            let col_type = match col_type {
+                                ParsedValueType::TimeType(fmt) => {
+                                    ParsedValueType::TimeType(fmt.with_default_tz(input_tz))
+                                }
+                                ct => ct,
+                            };
+                            let cd = GrokColumnDef::new(
+                                Arc::from(col_name.as_str()),
+                                col_type,
+                                lookup_names,
+                                required,
+                            );
+                            Ok(match str_overflow {
+                                Some(policy) => cd.with_str_overflow(policy),
+                                None => cd,
+                            })
+                        }
                     }
                 }
             })
@@ -245,32 +402,140 @@ impl HustlogConfig {
             .iter()
             .map(|x| x.as_ref().ok().unwrap().clone())
             .collect();
-        Ok(grok_schema_cols)
+        let mut col_names = BTreeSet::new();
+        let all_names = grok_schema_cols
+            .iter()
+            .map(|x| x.col_name())
+            .chain(derived_cols.iter().map(|x| x.pcd().name()));
+        for name in all_names {
+            if !col_names.insert(name) {
+                return Err(Box::new(ConfigError::new(
+                    format!("Duplicate schema column name: {}", name).as_str(),
+                )));
+            }
+        }
+        Ok((grok_schema_cols, derived_cols))
+    }
+
+    fn parse_parser_type(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+    ) -> Result<ParserType, DynError> {
+        let parser: &str = args_or_external_opt_default!(&args, &external_conf, parser, "grok");
+        match parser {
+            "grok" => Ok(ParserType::GROK),
+            "regex" => Ok(ParserType::REGEX),
+            "json" => Ok(ParserType::JSON),
+            "logfmt" => Ok(ParserType::LOGFMT),
+            "rfc5424" => Ok(ParserType::RFC5424),
+            x => Err(Box::new(ConfigError::new(
+                format!(
+                    "Invalid parser (must be one of grok, regex, json, logfmt, rfc5424): {}",
+                    x
+                )
+                .as_str(),
+            ))),
+        }
+    }
+
+    fn parse_logfmt_extra_column(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+    ) -> Option<Arc<str>> {
+        let extra_col: &str =
+            args_or_external_opt_default!(&args, &external_conf, logfmt_extra_column, "");
+        if extra_col.is_empty() {
+            None
+        } else {
+            Some(Arc::from(extra_col))
+        }
     }
 
     fn parse_grok_schema(
         args: &MyArgs,
         external_conf: &ExternalConfig,
+        parser_type: ParserType,
     ) -> Result<GrokSchema, DynError> {
-        let pattern = args_or_external_opt!(
-            &args,
-            &external_conf,
-            grok_pattern,
-            "GROK pattern (-g) is required, use with --help for more information"
+        let empty_pattern = String::new();
+        let (pattern, default_table_name) = match parser_type {
+            ParserType::GROK => {
+                let pattern = args_or_external_opt!(
+                    &args,
+                    &external_conf,
+                    grok_pattern,
+                    "GROK pattern (-g) is required, use with --help for more information"
+                )?;
+                (pattern, pattern.clone())
+            }
+            ParserType::REGEX => {
+                let pattern = args_or_external_opt!(
+                    &args,
+                    &external_conf,
+                    regex_pattern,
+                    "Regex pattern (--regex-pattern) is required when using the regex parser"
+                )?;
+                (pattern, "regex".to_string())
+            }
+            ParserType::JSON => (&empty_pattern, "json".to_string()),
+            ParserType::LOGFMT => (&empty_pattern, "logfmt".to_string()),
+            ParserType::RFC5424 => (&empty_pattern, "rfc5424".to_string()),
+        };
+        let (mut grok_schema_cols, derived_cols) = Self::parse_col_defs(args, external_conf)?;
+        if parser_type == ParserType::LOGFMT {
+            if let Some(extra_col) = Self::parse_logfmt_extra_column(args, external_conf) {
+                grok_schema_cols.push(GrokColumnDef::new(
+                    extra_col.clone(),
+                    ParsedValueType::StrType(65535),
+                    vec![Arc::new(extra_col.to_string())],
+                    false,
+                ));
+            }
+        }
+        let schema = Self::parse_grok_schema_with_cols(
+            args,
+            external_conf,
+            pattern,
+            &default_table_name,
+            parser_type,
+            grok_schema_cols,
         )?;
-        let grok_schema_cols: Vec<GrokColumnDef> = Self::parse_col_defs(&args, &external_conf)?;
+        let empty_vec = Vec::new();
+        let kv_extractors =
+            args_or_external_vec_default!(&args, &external_conf, kv_extract, &empty_vec)
+                .iter()
+                .map(|spec| KvExtractor::parse(spec))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|desc| ConfigError::new(desc.as_str()))?;
+        Ok(schema
+            .with_derived_columns(derived_cols)
+            .with_kv_extractors(kv_extractors))
+    }
+
+    fn parse_grok_schema_with_cols(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+        pattern: &str,
+        default_table_name: &String,
+        parser_type: ParserType,
+        grok_schema_cols: Vec<GrokColumnDef>,
+    ) -> Result<GrokSchema, DynError> {
         let empty_vec = Vec::new();
         let grok_extra_patterns =
             args_or_external_vec_default!(&args, &external_conf, grok_extra_patterns, &empty_vec);
-        let extra_patterns: Vec<(String, String)> = grok_extra_patterns
-            .iter()
-            .map(|x| {
-                let mut spliter = x.splitn(2, " ").into_iter();
-                let first: String = spliter.next().unwrap().to_string();
-                let second: String = spliter.next().unwrap_or("").to_string();
-                (first, second)
-            })
-            .collect();
+        // the patterns file ones first, so that the extra patterns can override them
+        let patterns_file: &str =
+            args_or_external_opt_default!(&args, &external_conf, grok_patterns_file, "");
+        let mut extra_patterns = if patterns_file.is_empty() {
+            Vec::new()
+        } else {
+            load_grok_patterns_file(patterns_file)?
+        };
+        extra_patterns.extend(grok_extra_patterns.iter().map(|x| {
+            let mut spliter = x.splitn(2, " ").into_iter();
+            let first: String = spliter.next().unwrap().to_string();
+            let second: String = spliter.next().unwrap_or("").to_string();
+            (first, second)
+        }));
         let grok_ignore_default_patterns = args_or_external_bool_default!(
             &args,
             &external_conf,
@@ -279,16 +544,385 @@ impl HustlogConfig {
         );
         let grok_with_alias_only =
             args_or_external_bool_default!(&args, &external_conf, grok_with_alias_only, false);
-        let output_table_name = args_or_external_opt_default!(&args, &external_conf, output_table_name, pattern);
+        let output_table_name = args_or_external_opt_default!(
+            &args,
+            &external_conf,
+            output_table_name,
+            default_table_name
+        );
+        let fallback_patterns = if parser_type == ParserType::GROK {
+            args_or_external_vec_default!(&args, &external_conf, grok_fallback_patterns, &empty_vec)
+                .clone()
+        } else {
+            Vec::new()
+        };
 
         Ok(GrokSchema::new(
-            pattern.clone(),
+            pattern.to_string(),
             grok_schema_cols,
             !grok_ignore_default_patterns,
             extra_patterns,
             grok_with_alias_only,
             output_table_name.clone()
-        ))
+        )
+        .with_fallback_patterns(fallback_patterns))
+    }
+
+    /// Sample the first sample_size input lines and infer the schema columns from the
+    /// named captures of the grok pattern (the top level one, named schemas are ignored)
+    pub fn infer_grok_columns(
+        args: &MyArgs,
+        sample_size: usize,
+    ) -> Result<SchemaInference, DynError> {
+        let external_conf = args.get_external_conf()?;
+        let schema = Self::parse_sampling_grok_schema(args, &external_conf, Vec::new())?;
+        let inputs = input_specs(args, &external_conf);
+        if inputs.iter().any(|input| input == "-") && args.grok_infer_schema_run {
+            return Err(Box::new(ConfigError::new(
+                "Running with the inferred schema needs a file input, stdin can only be read once",
+            )));
+        }
+        let msgs = Self::sample_input(args, &external_conf, sample_size)?;
+        // prefer the aliased captures (e.g. %{IPORHOST:clientip}) over the ones of all the
+        // nested patterns, if the pattern has any
+        let mut inference = SchemaInference::new();
+        for alias_only in [true, false] {
+            let grok_parser = GrokParser::new(schema.clone().with_grok_alias_only(alias_only))?;
+            inference = SchemaInference::new();
+            for msg in &msgs {
+                inference.add_line(msg.as_str(), grok_parser.named_captures(msg.as_str()));
+            }
+            if !inference.column_specs().is_empty() {
+                break;
+            }
+        }
+        Ok(inference)
+    }
+
+    /// The derived (computed/constant) ones of the configured schema column specs, kept
+    /// next to the inferred parser columns
+    pub fn derived_column_specs(args: &MyArgs) -> Result<Vec<String>, DynError> {
+        let external_conf = args.get_external_conf()?;
+        let no_cols = Vec::new();
+        let schema_columns =
+            args_or_external_vec_default!(&args, &external_conf, grok_schema_columns, &no_cols);
+        let mut ret = Vec::new();
+        for x in schema_columns {
+            match DerivedColumnDef::parse(x) {
+                Ok(Some(_)) => ret.push(x.clone()),
+                Ok(None) => {}
+                Err(desc) => return Err(Box::new(ConfigError::new(desc.as_str()))),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Sample the first sample_size input lines and explain how the grok pattern
+    /// (and the fallback ones) match each of them, see GrokDebugger
+    pub fn debug_grok_pattern(args: &MyArgs, sample_size: usize) -> Result<Vec<String>, DynError> {
+        let external_conf = args.get_external_conf()?;
+        // the schema columns are optional here, without them only the captures are shown
+        let has_cols = !args.grok_schema_columns.is_empty()
+            || external_conf
+                .grok_schema_columns
+                .as_ref()
+                .map(|cols| !cols.is_empty())
+                .unwrap_or(false);
+        let cols = if has_cols {
+            Self::parse_col_defs(args, &external_conf)?.0
+        } else {
+            Vec::new()
+        };
+        let schema = Self::parse_sampling_grok_schema(args, &external_conf, cols)?;
+        let mut debugger = GrokDebugger::new(schema)?;
+        let msgs = Self::sample_input(args, &external_conf, sample_size)?;
+        Ok(msgs.iter().map(|msg| debugger.explain(msg)).collect())
+    }
+
+    /// The grok schema for the inference/debugging modes which work on input samples
+    fn parse_sampling_grok_schema(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+        cols: Vec<GrokColumnDef>,
+    ) -> Result<GrokSchema, DynError> {
+        if Self::parse_parser_type(args, external_conf)? != ParserType::GROK {
+            return Err(Box::new(ConfigError::new(
+                "Schema inference and pattern debugging are only supported with the grok parser",
+            )));
+        }
+        let pattern = args_or_external_opt!(
+            &args,
+            &external_conf,
+            grok_pattern,
+            "GROK pattern (-g) is required, use with --help for more information"
+        )?;
+        Self::parse_grok_schema_with_cols(
+            args,
+            external_conf,
+            pattern,
+            pattern,
+            ParserType::GROK,
+            cols,
+        )
+    }
+
+    /// Read the first sample_size messages from the (file or stdin) input
+    fn sample_input(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+        sample_size: usize,
+    ) -> Result<Vec<RawMessage>, DynError> {
+        let inputs = input_specs(args, external_conf);
+        if inputs
+            .iter()
+            .any(|input| input.starts_with("syslog-") || input.starts_with("dir:"))
+        {
+            return Err(Box::new(ConfigError::new(
+                "Input sampling needs a file or stdin input",
+            )));
+        }
+        // the first input file is sampled
+        let input = resolve_input_files(&inputs, InputOrder::GIVEN)?.remove(0);
+        let line_merger_conf = HustlogConfig::parse_line_merger_conf(args, external_conf)?;
+        let mut lines_buffer = LinesBuffer::new(line_merger_conf.as_ref());
+        // read through the same (decompressing) reader as the processing
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(async {
+            let mut rdr = open_async_read(&input).await?;
+            let mut msgs = Vec::new();
+            while msgs.len() < sample_size {
+                let rd = rdr.as_mut().read_buf(lines_buffer.get_buf()).await?;
+                if rd == 0 {
+                    msgs.extend(lines_buffer.flush());
+                    break;
+                }
+                msgs.extend(lines_buffer.read_messages_from_buf());
+            }
+            msgs.truncate(sample_size);
+            Ok(msgs)
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_output(&self) -> &String {
+        &self.output
+    }
+
+    pub fn parser_type(&self) -> ParserType {
+        self.parser_type
+    }
+
+    pub fn logfmt_extra_column(&self) -> &Option<Arc<str>> {
+        &self.logfmt_extra_column
+    }
+
+    pub fn get_grok_schema(&self) -> &GrokSchema {
+        &self.grok_schema
+    }
+
+    pub fn query(&self) -> &Option<String> {
+        &self.query
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        OutputFormat::from_name(self.output_format.as_str())
+    }
+
+    pub fn output_add_ddl(&self) -> bool {
+        self.output_add_ddl
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HustlogConfig {
+    input: String,
+    input_files: Vec<String>,
+    follow: bool,
+    checkpoint_file: Option<String>,
+    watch_dir: Option<String>,
+    watch_pattern: Option<Pattern>,
+    watch_state_file: Option<String>,
+    line_merger_conf: Option<LineMergerConfig>,
+
+    schemas: Vec<SchemaConfig>,
+
+    output_batch_size: usize,
+
+    rayon_threads: usize,
+    tick_interval: u64,
+
+    idle_timeout: u64,
+
+    async_channel_size: usize,
+    //async_file_processing: bool,
+
+    // ddl_only: bool,
+    ddl_pre_name_opts: Arc<str>,
+    ddl_table_opts: Arc<str>,
+    ddl_ip_type: Arc<str>,
+
+    output_timezone: Option<Tz>,
+
+    dead_letter_output: Option<String>,
+    dead_letter_format: String,
+    dead_letter_add_ddl: bool,
+}
+
+impl HustlogConfig {
+    pub fn new(args: MyArgs) -> Result<HustlogConfig, DynError> {
+        let external_conf = args.get_external_conf()?;
+        Self::from_args_and_external(args, external_conf)
+    }
+
+    fn from_args_and_external(
+        args: MyArgs,
+        external_conf: ExternalConfig,
+    ) -> Result<HustlogConfig, DynError> {
+        let schemas = match &external_conf.schemas {
+            Some(ext_schemas) if !ext_schemas.is_empty() => {
+                // the outputs of the schemas would interleave in the inherited one
+                if ext_schemas.len() > 1 {
+                    if let Some(esc) = ext_schemas.iter().find(|esc| esc.output.is_none()) {
+                        return Err(Box::new(ConfigError::new(
+                            format!(
+                                "Schema {} has no output, each of multiple schemas needs its own",
+                                esc.name
+                            )
+                            .as_str(),
+                        )));
+                    }
+                }
+                ext_schemas
+                    .iter()
+                    .map(|esc| {
+                        SchemaConfig::new(
+                            Some(esc.name.as_str()),
+                            &args.with_schema_conf(esc),
+                            &external_conf,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            _ => vec![SchemaConfig::new(None, &args, &external_conf)?],
+        };
+        let mut file_outputs = BTreeSet::new();
+        for sc in &schemas {
+            if !matches!(sc.output_format(), OutputFormat::ODBC)
+                && !file_outputs.insert(sc.get_output())
+            {
+                return Err(Box::new(ConfigError::new(
+                    format!("Schemas can not share the output {}", sc.get_output()).as_str(),
+                )));
+            }
+        }
+        let inputs = input_specs(&args, &external_conf);
+        let input = &inputs[0];
+        let is_single_input =
+            |i: &String| i == "-" || i.starts_with("syslog-") || i.starts_with("dir:");
+        if inputs.len() > 1 && inputs.iter().any(is_single_input) {
+            return Err(Box::new(ConfigError::new(
+                "Stdin, a directory and the syslog servers can not be combined with other inputs",
+            )));
+        }
+        let watch_dir = input.strip_prefix("dir:");
+        let input_order: &str =
+            args_or_external_opt_default!(&args, &external_conf, input_order, "given");
+        let input_order = InputOrder::from_name(input_order)?;
+        let input_files = if input.starts_with("syslog-") || watch_dir.is_some() {
+            Vec::new()
+        } else {
+            resolve_input_files(&inputs, input_order)?
+        };
+        let follow = args_or_external_bool_default!(&args, &external_conf, follow, false);
+        if follow && (input == "-" || input.starts_with("syslog-") || watch_dir.is_some()) {
+            return Err(Box::new(ConfigError::new(
+                "Follow mode (--follow) requires a file input",
+            )));
+        }
+        let checkpoint_file: &str =
+            args_or_external_opt_default!(&args, &external_conf, checkpoint_file, "");
+        if !checkpoint_file.is_empty()
+            && (input == "-" || input.starts_with("syslog-") || watch_dir.is_some())
+        {
+            return Err(Box::new(ConfigError::new(
+                "Checkpoints (--checkpoint-file) require a file input",
+            )));
+        }
+        let (watch_pattern, watch_state_file) =
+            Self::parse_watch_dir_conf(watch_dir, &args, &external_conf)?;
+        let line_merger_conf = Self::parse_line_merger_conf(&args, &external_conf)?;
+        let output_batch_size =
+            args_or_external_opt_default!(&args, &external_conf, output_batch_size, &1000);
+        // let async_file_processing = if args.async_file_processing.is_some() {
+        //     args.async_file_processing.unwrap()
+        // } else {
+        //     external_conf.async_file_processing.unwrap_or(true)
+        // };
+        let ddl_pre_name_opts =
+            args_or_external_opt_default!(&args, &external_conf, ddl_pre_name_opts, "");
+        let ddl_pre_name_opts: Arc<str> = Arc::from(ddl_pre_name_opts.as_ref());
+        let ddl_table_opts =
+            args_or_external_opt_default!(&args, &external_conf, ddl_table_opts, "");
+        let ddl_table_opts: Arc<str> = Arc::from(ddl_table_opts.as_ref());
+        let ddl_ip_type =
+            args_or_external_opt_default!(&args, &external_conf, ddl_ip_type, "VARCHAR(45)");
+        let ddl_ip_type: Arc<str> = Arc::from(ddl_ip_type);
+        let output_timezone: &str =
+            args_or_external_opt_default!(&args, &external_conf, output_timezone, "");
+        let output_timezone = parse_timezone(output_timezone)?;
+        let dead_letter_output: &str =
+            args_or_external_opt_default!(&args, &external_conf, dead_letter_output, "");
+        let dead_letter_format: &str =
+            args_or_external_opt_default!(&args, &external_conf, dead_letter_format, "csv");
+        let dead_letter_add_ddl =
+            args_or_external_bool_default!(&args, &external_conf, output_add_ddl, false);
+        Ok(Self {
+            input: input.to_string(),
+            input_files,
+            follow,
+            checkpoint_file: if checkpoint_file.is_empty() {
+                None
+            } else {
+                Some(checkpoint_file.to_string())
+            },
+            watch_dir: watch_dir.map(|d| d.to_string()),
+            watch_pattern,
+            watch_state_file,
+            line_merger_conf,
+            schemas,
+            output_batch_size: *output_batch_size,
+            rayon_threads: *args_or_external_opt_default!(&args, &external_conf, rayon_threads, &2),
+            tick_interval: *args_or_external_opt_default!(
+                &args,
+                &external_conf,
+                tick_interval,
+                &30
+            ),
+            idle_timeout: *args_or_external_opt_default!(&args, &external_conf, idle_timeout, &30),
+            async_channel_size: *args_or_external_opt_default!(
+                &args,
+                &external_conf,
+                async_channel_size,
+                &1000
+            ),
+            //async_file_processing,
+            ddl_pre_name_opts,
+            ddl_table_opts,
+            ddl_ip_type,
+            output_timezone,
+            dead_letter_output: if dead_letter_output.is_empty() {
+                None
+            } else {
+                Some(dead_letter_output.to_string())
+            },
+            dead_letter_format: dead_letter_format.to_string(),
+            dead_letter_add_ddl,
+        })
     }
 
     // pub fn get_buf_read(&self) -> Result<DynBufRead, DynError> {
@@ -300,31 +934,19 @@ impl HustlogConfig {
     //     Ok(reader)
     // }
 
-    pub fn get_output(&self) -> &String {
-        &self.output
-    }
-
-    pub fn get_output_writer(&self) -> Result<DynBoxWrite, DynError> {
-        let writer: DynBoxWrite = if &self.output == "-" {
-            Box::new(BufWriter::new(io::stdout()))
-        } else {
-            Box::new(BufWriter::new(
-                fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(&self.output)?,
-            ))
-        };
-        Ok(writer)
+    /// The reader of one of the input files, "-" is stdin. Compressed (gzip, zstd
+    /// or bzip2) data is decompressed.
+    pub async fn get_async_read(&self, input: &str) -> Result<Pin<DynAsyncRead>, DynError> {
+        open_async_read(input).await
     }
 
-    pub async fn get_async_read(&self) -> Result<Pin<DynAsyncRead>, DynError> {
-        let reader: Pin<DynAsyncRead> = if &self.input == "-" {
-            Box::pin(tokio::io::stdin())
-        } else {
-            Box::pin(tokio::fs::File::open(&self.input).await?)
-        };
-        Ok(reader)
+    /// The input file modification time, None for stdin
+    pub async fn get_input_mtime(&self, input: &str) -> Option<DateTime<Utc>> {
+        if input == "-" {
+            return None;
+        }
+        let modified = tokio::fs::metadata(input).await.ok()?.modified().ok()?;
+        Some(DateTime::<Utc>::from(modified))
     }
 
     // pub async fn get_async_outp(&self) -> Result<DynBoxAsyncWrite, DynError> {
@@ -341,28 +963,126 @@ impl HustlogConfig {
     //     Ok(writer)
     // }
 
-    pub fn get_grok_schema(&self) -> &GrokSchema {
-        &self.grok_schema
+    /// The input files (or "-" for stdin) in the processing order
+    pub fn get_input_files(&self) -> &Vec<String> {
+        &self.input_files
     }
 
-    pub fn merge_multi_line(&self) -> bool {
-        self.merge_multi_line
+    pub fn get_follow(&self) -> bool {
+        self.follow
     }
 
-    pub fn query(&self) -> &Option<String> {
-        &self.query
+    pub fn get_checkpoint_file(&self) -> Option<&String> {
+        self.checkpoint_file.as_ref()
     }
 
-    pub fn output_format(&self) -> OutputFormat {
-        match self.output_format.as_str() {
-            "sql" => OutputFormat::SQL,
-            "odbc" => OutputFormat::ODBC,
-            _ => OutputFormat::DEFAULT,
+    /// The watched directory, with a dir:<path> input
+    pub fn get_watch_dir(&self) -> Option<&String> {
+        self.watch_dir.as_ref()
+    }
+
+    pub fn get_watch_pattern(&self) -> &Option<Pattern> {
+        &self.watch_pattern
+    }
+
+    pub fn get_watch_state_file(&self) -> Option<&String> {
+        self.watch_state_file.as_ref()
+    }
+
+    pub fn schemas(&self) -> &Vec<SchemaConfig> {
+        &self.schemas
+    }
+
+    pub fn log_str_overflow_stats(&self) {
+        for sc in &self.schemas {
+            sc.get_grok_schema().log_str_overflow_stats();
         }
     }
 
-    pub fn output_add_ddl(&self) -> bool {
-        self.output_add_ddl
+    /// The file name glob and the state file of the watched directory
+    fn parse_watch_dir_conf(
+        watch_dir: Option<&str>,
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+    ) -> Result<(Option<Pattern>, Option<String>), DynError> {
+        let watch_pattern: &str =
+            args_or_external_opt_default!(&args, &external_conf, watch_pattern, "");
+        let watch_state_file: &str =
+            args_or_external_opt_default!(&args, &external_conf, watch_state_file, "");
+        let dir =
+            match watch_dir {
+                Some(dir) => dir,
+                None if watch_pattern.is_empty() && watch_state_file.is_empty() => {
+                    return Ok((None, None))
+                }
+                None => return Err(Box::new(ConfigError::new(
+                    "--watch-pattern and --watch-state-file require a directory input (dir:<path>)",
+                ))),
+            };
+        if !fs::metadata(dir).map(|m| m.is_dir()).unwrap_or(false) {
+            return Err(Box::new(ConfigError::new(
+                format!("The watched directory does not exist: {}", dir).as_str(),
+            )));
+        }
+        let watch_pattern = if watch_pattern.is_empty() {
+            None
+        } else {
+            Some(Pattern::new(watch_pattern).map_err(|e| {
+                ConfigError::new(format!("Invalid watch pattern {}: {}", watch_pattern, e).as_str())
+            })?)
+        };
+        let watch_state_file = if watch_state_file.is_empty() {
+            None
+        } else {
+            Some(watch_state_file.to_string())
+        };
+        Ok((watch_pattern, watch_state_file))
+    }
+
+    fn parse_line_merger_conf(
+        args: &MyArgs,
+        external_conf: &ExternalConfig,
+    ) -> Result<Option<LineMergerConfig>, DynError> {
+        let merger_type: &str =
+            args_or_external_opt_default!(&args, &external_conf, merge_multi_line, "");
+        // true/false are still accepted from the config file, for compatibility
+        let merger_type = match merger_type {
+            "" | "none" | "false" => return Ok(None),
+            "true" => "space",
+            x => x,
+        };
+        let pattern: &str = args_or_external_opt_default!(&args, &external_conf, merge_pattern, "");
+        let max_lines = args_or_external_opt_default!(
+            &args,
+            &external_conf,
+            merge_max_lines,
+            &DEFAULT_MERGE_MAX_LINES
+        );
+        let max_bytes = args_or_external_opt_default!(
+            &args,
+            &external_conf,
+            merge_max_bytes,
+            &DEFAULT_MERGE_MAX_BYTES
+        );
+        let join_str: &str =
+            args_or_external_opt_default!(&args, &external_conf, merge_join_str, " ");
+        let lmc = LineMergerConfig::new(
+            merger_type,
+            if pattern.is_empty() {
+                None
+            } else {
+                Some(pattern)
+            },
+            *max_lines,
+            *max_bytes,
+            join_str,
+        )?;
+        Ok(Some(lmc))
+    }
+
+    /// How to merge multi-line messages, None if no merging is configured
+    pub fn line_merger_conf(&self) -> &Option<LineMergerConfig> {
+        &self.line_merger_conf
     }
 
     pub fn output_batch_size(&self) -> usize {
@@ -440,23 +1160,62 @@ impl HustlogConfig {
     pub fn get_ddl_table_opts(&self) -> &Arc<str> {
         &self.ddl_table_opts
     }
+
+    pub fn get_ddl_ip_type(&self) -> &Arc<str> {
+        &self.ddl_ip_type
+    }
+
+    pub fn output_timezone(&self) -> Option<Tz> {
+        self.output_timezone
+    }
+
+    /// Where to write the messages which failed to parse, if anywhere
+    pub fn dead_letter_output(&self) -> &Option<String> {
+        &self.dead_letter_output
+    }
+
+    pub fn dead_letter_format(&self) -> OutputFormat {
+        OutputFormat::from_name(self.dead_letter_format.as_str())
+    }
+
+    /// The dead letter output gets a header/DDL if the (top level) outputs do
+    pub fn dead_letter_add_ddl(&self) -> bool {
+        self.dead_letter_add_ddl
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{load_grok_patterns_file, resolve_input_files, InputOrder, SchemaConfig};
+    use crate::conf::external::ExternalConfig;
+    use crate::parser::ParserSchema;
     use crate::{HustlogConfig, MyArgs};
+    use std::path::PathBuf;
 
     pub fn test_args(input: &str) -> MyArgs {
         MyArgs {
             grok_list_default_patterns: false,
+            grok_infer_schema: None,
+            grok_infer_schema_run: false,
+            grok_debug: None,
             conf: None,
-            input: Some(input.to_string()),
+            input: vec![input.to_string()],
+            input_order: None,
+            follow: false,
+            checkpoint_file: None,
+            watch_pattern: None,
+            watch_state_file: None,
             output: None,
             output_format: None,
             output_batch_size: None,
             output_add_ddl: false,
             output_table_name: None,
             grok_pattern: Some("SYSLOGLINE".to_string()),
+            grok_fallback_patterns: vec![],
+            parser: None,
+            regex_pattern: None,
+            logfmt_extra_column: None,
+            kv_extract: vec![],
             grok_patterns_file: None,
             grok_extra_patterns: vec![],
             query: None,
@@ -469,7 +1228,11 @@ mod tests {
                 "pid:int".to_string(),
                 "message".to_string(),
             ],
-            merge_multi_line: false,
+            merge_multi_line: None,
+            merge_pattern: None,
+            merge_max_lines: None,
+            merge_max_bytes: None,
+            merge_join_str: None,
             rayon_threads: None,
             tick_interval: None,
             idle_timeout: None,
@@ -477,6 +1240,11 @@ mod tests {
             //async_file_processing: None,
             ddl_pre_name_opts: None,
             ddl_table_opts: None,
+            ddl_ip_type: None,
+            input_timezone: None,
+            output_timezone: None,
+            dead_letter_output: None,
+            dead_letter_format: None,
         }
     }
 
@@ -491,6 +1259,83 @@ mod tests {
         println!("{:?}", hc)
     }
 
+    #[test]
+    fn schemas_conf_works() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("config_examples/multi_schema.yml");
+        let mut args = test_args("-");
+        args.grok_pattern = None;
+        args.grok_schema_columns = vec![];
+        let external_conf = ExternalConfig::from_yaml_file(d.to_str().unwrap()).unwrap();
+        let hc = HustlogConfig::from_args_and_external(args, external_conf).unwrap();
+        let schemas = hc.schemas();
+        assert_eq!(schemas.len(), 2);
+        assert_eq!(schemas[0].name(), "auth");
+        assert_eq!(schemas[0].get_grok_schema().output_name(), "auth");
+        assert_eq!(schemas[0].get_output(), "auth.sql");
+        assert!(schemas[0].query().is_none());
+        assert_eq!(schemas[1].get_grok_schema().columns().len(), 5);
+        assert!(schemas[1].query().is_some());
+    }
+
+    #[test]
+    fn duplicate_column_names_rejected() {
+        let mut args = test_args("-");
+        args.grok_schema_columns
+            .push("logsource=hostname".to_string());
+        let err = HustlogConfig::new(args).unwrap_err().to_string();
+        assert!(err.contains("logsource"), "{}", err);
+        let mut args = test_args("-");
+        args.grok_schema_columns.push("env=const:prod".to_string());
+        args.grok_schema_columns.push("env=const:test".to_string());
+        assert!(HustlogConfig::new(args).is_err());
+    }
+
+    #[test]
+    fn derived_column_specs_works() {
+        let mut args = test_args("-");
+        assert!(SchemaConfig::derived_column_specs(&args)
+            .unwrap()
+            .is_empty());
+        args.grok_schema_columns.push("host=hostname".to_string());
+        args.grok_schema_columns.push("env=const:prod".to_string());
+        assert_eq!(
+            SchemaConfig::derived_column_specs(&args).unwrap(),
+            vec!["host=hostname".to_string(), "env=const:prod".to_string()]
+        );
+        args.grok_schema_columns.push("bad=nosuch".to_string());
+        assert!(SchemaConfig::derived_column_specs(&args).is_err());
+    }
+
+    #[test]
+    fn schemas_output_required() {
+        let mut args = test_args("-");
+        args.grok_schema_columns = vec![];
+        let yaml = "
+grok_schema_columns: [message]
+schemas:
+  - name: auth
+    output: auth.sql
+  - name: other
+";
+        let external_conf: ExternalConfig = serde_yaml::from_str(yaml).unwrap();
+        let err = HustlogConfig::from_args_and_external(args.clone(), external_conf)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("other"), "{}", err);
+        let external_conf: ExternalConfig = serde_yaml::from_str(
+            &yaml.replace("- name: other", "- name: other\n    output: auth.sql"),
+        )
+        .unwrap();
+        assert!(HustlogConfig::from_args_and_external(args.clone(), external_conf).is_err());
+        // a single schema inherits the top-level output
+        let external_conf: ExternalConfig =
+            serde_yaml::from_str("grok_schema_columns: [message]\nschemas:\n  - name: auth\n")
+                .unwrap();
+        let hc = HustlogConfig::from_args_and_external(args, external_conf).unwrap();
+        assert_eq!(hc.schemas()[0].get_output(), "-");
+    }
+
     #[test]
     fn parse_server_conf_works() {
         let hc = test_config("syslog-tcp:127.0.0.1:514");
@@ -504,4 +1349,89 @@ mod tests {
         assert_eq!(ssc.listen_host, "[::1]");
         assert_eq!(ssc.port, 514);
     }
+    #[test]
+    fn load_grok_patterns_file_works() {
+        let dir =
+            std::env::temp_dir().join(format!("hustlog_patterns_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("patterns");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "# comment\n\nMY_NUM \\d+\n  MY_PAIR\t%{WORD}=%{MY_NUM}  \n",
+        )
+        .unwrap();
+        assert_eq!(
+            load_grok_patterns_file(path).unwrap(),
+            vec![
+                ("MY_NUM".to_string(), "\\d+".to_string()),
+                ("MY_PAIR".to_string(), "%{WORD}=%{MY_NUM}".to_string()),
+            ]
+        );
+        std::fs::write(path, "MY_NUM \\d+\n# comment\nMY_WORD\n").unwrap();
+        let err = load_grok_patterns_file(path).unwrap_err().to_string();
+        assert!(err.contains(path) && err.contains("line 3"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_dir_conf_works() {
+        let dir =
+            std::env::temp_dir().join(format!("hustlog_watch_conf_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let d = dir.to_str().unwrap();
+        let mut args = test_args(&format!("dir:{}", d));
+        args.watch_pattern = Some("*.log".to_string());
+        let hc = HustlogConfig::new(args.clone()).unwrap();
+        assert_eq!(hc.get_watch_dir().map(|d| d.as_str()), Some(d));
+        assert!(hc.get_watch_pattern().as_ref().unwrap().matches("a.log"));
+        assert!(hc.get_input_files().is_empty());
+        args.follow = true;
+        assert!(HustlogConfig::new(args).is_err());
+        let mut args = test_args(&format!("dir:{}/missing", d));
+        assert!(HustlogConfig::new(args.clone()).is_err());
+        args.input = vec!["-".to_string()];
+        args.watch_state_file = Some("state.json".to_string());
+        assert!(HustlogConfig::new(args).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_input_files_works() {
+        let dir = std::env::temp_dir().join(format!("hustlog_inputs_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = std::time::SystemTime::now();
+        for (ix, name) in ["b.log", "c.log", "a.log", "x.txt"].iter().enumerate() {
+            let f = std::fs::File::create(dir.join(name)).unwrap();
+            f.set_modified(now - std::time::Duration::from_secs(100 - ix as u64))
+                .unwrap();
+        }
+        let d = dir.to_str().unwrap();
+        let inputs = vec![
+            format!("{}/x.txt", d),
+            format!("{}/*.log", d),
+            format!("{}/a.log", d),
+        ];
+        let names = |order| {
+            resolve_input_files(&inputs, order)
+                .unwrap()
+                .iter()
+                .map(|f| f.rsplit('/').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(InputOrder::GIVEN),
+            vec!["x.txt", "a.log", "b.log", "c.log"]
+        );
+        assert_eq!(
+            names(InputOrder::NAME),
+            vec!["a.log", "b.log", "c.log", "x.txt"]
+        );
+        assert_eq!(
+            names(InputOrder::MTIME),
+            vec!["b.log", "c.log", "a.log", "x.txt"]
+        );
+        assert!(resolve_input_files(&[format!("{}/*.gz", d)], InputOrder::GIVEN).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,12 +1,42 @@
 use crate::DynError;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fs;
 use std::io::BufReader;
 
+/// A named schema, with its own parser settings, query and output. Any options
+/// which are not set here are taken from the top-level config, except for the output
+/// which is required when there are multiple schemas
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExternalSchemaConfig {
+    pub name: String,
+
+    pub parser: Option<String>,
+    pub grok_pattern: Option<String>,
+    pub grok_fallback_patterns: Option<Vec<String>>,
+    pub regex_pattern: Option<String>,
+    pub logfmt_extra_column: Option<String>,
+    pub kv_extract: Option<Vec<String>>,
+    pub grok_schema_columns: Option<Vec<String>>,
+
+    pub query: Option<String>,
+
+    pub output: Option<String>,
+    pub output_format: Option<String>,
+    pub output_add_ddl: Option<bool>,
+    pub output_table_name: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct ExternalConfig {
-    pub input: Option<String>,
-    pub merge_multi_line: Option<bool>,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub input: Option<Vec<String>>,
+    pub input_order: Option<String>,
+    #[serde(default, deserialize_with = "merger_or_bool")]
+    pub merge_multi_line: Option<String>,
+    pub merge_pattern: Option<String>,
+    pub merge_max_lines: Option<usize>,
+    pub merge_max_bytes: Option<usize>,
+    pub merge_join_str: Option<String>,
 
     pub grok_schema_columns: Option<Vec<String>>,
     pub grok_pattern: Option<String>,
@@ -16,6 +46,13 @@ pub struct ExternalConfig {
     pub grok_with_alias_only: Option<bool>,
     pub grok_ignore_default_patterns: Option<bool>,
 
+    pub grok_fallback_patterns: Option<Vec<String>>,
+
+    pub parser: Option<String>,
+    pub regex_pattern: Option<String>,
+    pub logfmt_extra_column: Option<String>,
+    pub kv_extract: Option<Vec<String>>,
+
     pub query: Option<String>,
 
     pub output: Option<String>,
@@ -26,6 +63,10 @@ pub struct ExternalConfig {
 
     pub rayon_threads: Option<usize>,
     pub tick_interval: Option<u64>,
+    pub follow: Option<bool>,
+    pub checkpoint_file: Option<String>,
+    pub watch_pattern: Option<String>,
+    pub watch_state_file: Option<String>,
 
     pub idle_timeout: Option<u64>,
 
@@ -34,6 +75,53 @@ pub struct ExternalConfig {
 
     pub ddl_pre_name_opts: Option<String>,
     pub ddl_table_opts: Option<String>,
+    pub ddl_ip_type: Option<String>,
+
+    pub input_timezone: Option<String>,
+    pub output_timezone: Option<String>,
+
+    pub dead_letter_output: Option<String>,
+    pub dead_letter_format: Option<String>,
+
+    pub schemas: Option<Vec<ExternalSchemaConfig>>,
+}
+
+/// A single string or a list of them, e.g. "input: a.log" or "input: [a.log, b.log]"
+fn string_or_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        ONE(String),
+        LIST(Vec<String>),
+    }
+    Ok(match Option::<StringOrList>::deserialize(deserializer)? {
+        Some(StringOrList::ONE(s)) => Some(vec![s]),
+        Some(StringOrList::LIST(v)) => Some(v),
+        None => None,
+    })
+}
+
+/// A multi-line merger name or the older true/false, e.g. "merge_multi_line: space" or
+/// "merge_multi_line: true" (the same as space)
+fn merger_or_bool<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MergerOrBool {
+        BOOL(bool),
+        MERGER(String),
+    }
+    Ok(match Option::<MergerOrBool>::deserialize(deserializer)? {
+        Some(MergerOrBool::BOOL(true)) => Some("space".to_string()),
+        Some(MergerOrBool::BOOL(false)) => Some("none".to_string()),
+        Some(MergerOrBool::MERGER(s)) => Some(s),
+        None => None,
+    })
 }
 
 impl ExternalConfig {
@@ -48,7 +136,12 @@ impl ExternalConfig {
     pub fn empty() -> Self {
         Self {
             input: None,
+            input_order: None,
             merge_multi_line: None,
+            merge_pattern: None,
+            merge_max_lines: None,
+            merge_max_bytes: None,
+            merge_join_str: None,
             grok_schema_columns: None,
             grok_pattern: None,
             grok_patterns_file: None,
@@ -56,6 +149,11 @@ impl ExternalConfig {
             grok_load_default: None,
             grok_with_alias_only: None,
             grok_ignore_default_patterns: None,
+            grok_fallback_patterns: None,
+            parser: None,
+            regex_pattern: None,
+            logfmt_extra_column: None,
+            kv_extract: None,
             query: None,
             output: None,
             output_format: None,
@@ -64,11 +162,21 @@ impl ExternalConfig {
             output_table_name: None,
             rayon_threads: None,
             tick_interval: None,
+            follow: None,
+            checkpoint_file: None,
+            watch_pattern: None,
+            watch_state_file: None,
             idle_timeout: None,
             async_channel_size: None,
             // async_file_processing: None,
             ddl_pre_name_opts: None,
             ddl_table_opts: None,
+            ddl_ip_type: None,
+            input_timezone: None,
+            output_timezone: None,
+            dead_letter_output: None,
+            dead_letter_format: None,
+            schemas: None,
         }
     }
 }
@@ -89,7 +197,14 @@ mod tests {
     fn test_deser() {
         let yaml = "input: blah\n";
         let pc: ExternalConfig = serde_yaml::from_str(&yaml).unwrap();
-        println!("{:?}", pc)
+        println!("{:?}", pc);
+        assert_eq!(pc.input, Some(vec!["blah".to_string()]));
+        let yaml = "input: [a.log, 'b*.log']\n";
+        let pc: ExternalConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            pc.input,
+            Some(vec!["a.log".to_string(), "b*.log".to_string()])
+        );
     }
 
     #[test]
@@ -100,4 +215,34 @@ mod tests {
         let pc = ExternalConfig::from_yaml_file(d.to_str().unwrap()).unwrap();
         println!("{:?}", pc)
     }
+
+    #[test]
+    fn test_merge_multi_line_deser() {
+        let pc: ExternalConfig = serde_yaml::from_str("merge_multi_line: true").unwrap();
+        assert_eq!(pc.merge_multi_line.as_deref(), Some("space"));
+        let pc: ExternalConfig = serde_yaml::from_str("merge_multi_line: false").unwrap();
+        assert_eq!(pc.merge_multi_line.as_deref(), Some("none"));
+        let pc: ExternalConfig = serde_yaml::from_str("merge_multi_line: start-regex").unwrap();
+        assert_eq!(pc.merge_multi_line.as_deref(), Some("start-regex"));
+        let pc: ExternalConfig = serde_yaml::from_str("input: a.log").unwrap();
+        assert_eq!(pc.merge_multi_line, None);
+    }
+
+    #[test]
+    fn test_schemas_deser() {
+        let yaml = "
+grok_pattern: SYSLOGLINE
+schemas:
+  - name: auth
+    grok_pattern: SSHD
+    output: auth.sql
+  - name: other
+";
+        let pc: ExternalConfig = serde_yaml::from_str(yaml).unwrap();
+        let schemas = pc.schemas.unwrap();
+        assert_eq!(schemas.len(), 2);
+        assert_eq!(schemas[0].name, "auth");
+        assert_eq!(schemas[0].grok_pattern.as_deref(), Some("SSHD"));
+        assert_eq!(schemas[1].grok_pattern, None);
+    }
 }
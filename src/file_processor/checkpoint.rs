@@ -0,0 +1,235 @@
+use crate::async_pipeline::message_queue::CheckpointMarker;
+use crate::file_processor::file_follower::file_id;
+use crate::DynError;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::Read;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// The number of leading bytes identifying a file, along with its inode
+const FINGERPRINT_BYTES: u64 = 1024;
+
+/// The read position of a file, up to the end of the last message fully emitted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileCheckpoint {
+    dev: Option<u64>,
+    inode: Option<u64>,
+    fingerprint: String,
+    fingerprint_len: u64,
+    offset: u64,
+}
+
+/// The hex SHA-256 of up to len leading bytes of the file and the number of bytes hashed
+fn fingerprint(file: &fs::File, len: u64) -> Result<(String, u64), std::io::Error> {
+    let mut data = Vec::new();
+    file.take(len).read_to_end(&mut data)?;
+    let hex = Sha256::digest(&data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Ok((hex, data.len() as u64))
+}
+
+impl FileCheckpoint {
+    /// The checkpoint of the file read from path, None if the path
+    /// is not that file (with the file_id) anymore
+    pub fn of_file(
+        path: &str,
+        read_file_id: Option<(u64, u64)>,
+        offset: u64,
+    ) -> Result<Option<FileCheckpoint>, std::io::Error> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            // e.g. between a rotation and the creation of the new file
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if file_id(&file.metadata()?) != read_file_id {
+            return Ok(None);
+        }
+        let (fingerprint, fingerprint_len) = fingerprint(&file, offset.min(FINGERPRINT_BYTES))?;
+        Ok(Some(FileCheckpoint {
+            dev: read_file_id.map(|(dev, _)| dev),
+            inode: read_file_id.map(|(_, inode)| inode),
+            fingerprint,
+            fingerprint_len,
+            offset,
+        }))
+    }
+
+    /// The offset to resume reading the file from, 0 if it is not the
+    /// checkpointed file anymore (rotated or truncated)
+    pub fn resume_offset(&self, file: &fs::File) -> Result<u64, std::io::Error> {
+        let meta = file.metadata()?;
+        let same_id = match file_id(&meta) {
+            Some((dev, inode)) => self.dev == Some(dev) && self.inode == Some(inode),
+            None => true,
+        };
+        if !same_id || meta.len() < self.offset {
+            return Ok(0);
+        }
+        let same_content = fingerprint(file, self.fingerprint_len)?
+            == (self.fingerprint.clone(), self.fingerprint_len);
+        Ok(if same_content { self.offset } else { 0 })
+    }
+}
+
+/// The per-file checkpoints persisted in the state file (as JSON). The checkpoints are
+/// sent down the pipeline as markers after the data read up to them and are saved only
+/// once all of the outputs have flushed that data.
+pub struct CheckpointStore {
+    state_file: String,
+    checkpoints: BTreeMap<String, FileCheckpoint>,
+    next_id: u64,
+    last_marked: Option<(String, FileCheckpoint)>,
+    pending: VecDeque<(u64, String, FileCheckpoint)>,
+    ack_tx: UnboundedSender<u64>,
+    ack_rx: UnboundedReceiver<u64>,
+}
+
+impl CheckpointStore {
+    /// Load the checkpoints from the state file, if it exists
+    pub fn load(state_file: &str) -> Result<CheckpointStore, DynError> {
+        let checkpoints = match fs::read_to_string(state_file) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(Box::new(err)),
+        };
+        let (ack_tx, ack_rx) = unbounded_channel();
+        Ok(CheckpointStore {
+            state_file: state_file.to_string(),
+            checkpoints,
+            next_id: 0,
+            last_marked: None,
+            pending: VecDeque::new(),
+            ack_tx,
+            ack_rx,
+        })
+    }
+
+    /// The offset to resume reading the (just opened) file at path from
+    pub fn resume_offset(&self, path: &str, file: &fs::File) -> Result<u64, DynError> {
+        let offset = match self.checkpoints.get(path) {
+            Some(cp) => cp.resume_offset(file)?,
+            None => 0,
+        };
+        if offset > 0 {
+            info!("Resuming {} from the checkpoint at offset {}", path, offset);
+        }
+        Ok(offset)
+    }
+
+    /// The marker of the file checkpoint, to send down the pipeline after the data
+    /// up to it. None if it is the same as the last one.
+    pub fn marker(&mut self, path: &str, cp: FileCheckpoint) -> Option<CheckpointMarker> {
+        let marked = Some((path.to_string(), cp));
+        if self.last_marked == marked {
+            return None;
+        }
+        let (path, cp) = marked.clone().unwrap();
+        self.last_marked = marked;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push_back((id, path, cp));
+        Some(CheckpointMarker::new(id, self.ack_tx.clone()))
+    }
+
+    /// Save the acknowledged checkpoints, if any
+    pub fn save_acked(&mut self) -> Result<(), DynError> {
+        let mut changed = false;
+        while let Ok(acked_id) = self.ack_rx.try_recv() {
+            // the markers are acknowledged in the order sent
+            while let Some((id, path, cp)) = self.pending.pop_front() {
+                self.checkpoints.insert(path, cp);
+                changed = true;
+                if id == acked_id {
+                    break;
+                }
+            }
+        }
+        if changed {
+            debug!("Saving the checkpoints to {}", &self.state_file);
+            let tmp_file = format!("{}.tmp", &self.state_file);
+            fs::write(&tmp_file, serde_json::to_string_pretty(&self.checkpoints)?)?;
+            fs::rename(&tmp_file, &self.state_file)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn checkpoint_store_works() {
+        let dir =
+            std::env::temp_dir().join(format!("hustlog_checkpoint_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let path = path.to_str().unwrap();
+        let state_file = dir.join("state.json");
+        let state_file = state_file.to_str().unwrap();
+        fs::write(path, "line 1\nline 2\n").unwrap();
+        let file_id = file_id(&fs::metadata(path).unwrap());
+
+        let mut store = CheckpointStore::load(state_file).unwrap();
+        let cp = FileCheckpoint::of_file(path, file_id, 7).unwrap().unwrap();
+        let marker = store.marker(path, cp.clone()).unwrap();
+        assert!(store.marker(path, cp).is_none());
+        let cp = FileCheckpoint::of_file(path, file_id, 14).unwrap().unwrap();
+        let marker2 = store.marker(path, cp).unwrap();
+        // not saved before acknowledged
+        store.save_acked().unwrap();
+        assert!(!std::path::Path::new(state_file).exists());
+        let fork = marker.fork();
+        marker.ack();
+        store.save_acked().unwrap();
+        assert!(!std::path::Path::new(state_file).exists());
+        fork.ack();
+        store.save_acked().unwrap();
+        let store = CheckpointStore::load(state_file).unwrap();
+        assert_eq!(
+            store
+                .resume_offset(path, &fs::File::open(path).unwrap())
+                .unwrap(),
+            7
+        );
+        drop(marker2);
+
+        // appended, truncated and replaced
+        fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(b"line 3\n")
+            .unwrap();
+        assert_eq!(
+            store
+                .resume_offset(path, &fs::File::open(path).unwrap())
+                .unwrap(),
+            7
+        );
+        fs::write(path, "line\n").unwrap();
+        assert_eq!(
+            store
+                .resume_offset(path, &fs::File::open(path).unwrap())
+                .unwrap(),
+            0
+        );
+        fs::remove_file(path).unwrap();
+        fs::write(path, "other 1\nother 2\n").unwrap();
+        assert_eq!(
+            store
+                .resume_offset(path, &fs::File::open(path).unwrap())
+                .unwrap(),
+            0
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use crate::DynAsyncRead;
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use std::fs;
+use std::io::{Cursor, Read, Seek};
+use std::pin::Pin;
+use tokio::io::{AsyncReadExt, BufReader};
+
+/// The number of leading bytes needed to detect the compression
+const MAGIC_LEN: usize = 4;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression {
+    NONE,
+    GZIP,
+    ZSTD,
+    BZIP2,
+}
+
+impl Compression {
+    /// The compression of a file or stream from its leading bytes, or from the path
+    /// extension when these are too short to tell
+    pub fn detect(head: &[u8], path: &str) -> Compression {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Compression::GZIP
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::ZSTD
+        } else if head.starts_with(b"BZh") {
+            Compression::BZIP2
+        } else if head.len() < MAGIC_LEN && !head.is_empty() {
+            Self::from_extension(path)
+        } else {
+            Compression::NONE
+        }
+    }
+
+    fn from_extension(path: &str) -> Compression {
+        match path.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("gz") => Compression::GZIP,
+            Some("zst") => Compression::ZSTD,
+            Some("bz2") => Compression::BZIP2,
+            _ => Compression::NONE,
+        }
+    }
+
+    /// The compression of the file (read from its start, leaving it at its start)
+    pub fn of_file(mut file: &fs::File, path: &str) -> Result<Compression, std::io::Error> {
+        file.rewind()?;
+        let mut head = Vec::with_capacity(MAGIC_LEN);
+        file.take(MAGIC_LEN as u64).read_to_end(&mut head)?;
+        file.rewind()?;
+        Ok(Self::detect(&head, path))
+    }
+}
+
+/// The decompressed data of the (compressed) async reader
+pub fn decoding_async_read(rdr: Pin<DynAsyncRead>, compression: Compression) -> Pin<DynAsyncRead> {
+    // concatenated streams (e.g. cat a.gz b.gz) are read one after the other
+    match compression {
+        Compression::NONE => rdr,
+        Compression::GZIP => {
+            let mut decoder = GzipDecoder::new(BufReader::new(rdr));
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Compression::ZSTD => {
+            let mut decoder = ZstdDecoder::new(BufReader::new(rdr));
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Compression::BZIP2 => {
+            let mut decoder = BzDecoder::new(BufReader::new(rdr));
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+    }
+}
+
+/// The (transparently) decompressed data of the async reader of path, e.g. a file or stdin
+pub async fn decompress_async_read(
+    mut rdr: Pin<DynAsyncRead>,
+    path: &str,
+) -> Result<(Pin<DynAsyncRead>, Compression), std::io::Error> {
+    let mut head = Vec::with_capacity(MAGIC_LEN);
+    // a pipe can return fewer bytes per read
+    while head.len() < MAGIC_LEN {
+        if rdr
+            .as_mut()
+            .take((MAGIC_LEN - head.len()) as u64)
+            .read_buf(&mut head)
+            .await?
+            == 0
+        {
+            break;
+        }
+    }
+    let compression = Compression::detect(&head, path);
+    let chained: Pin<DynAsyncRead> = Box::pin(AsyncReadExt::chain(Cursor::new(head), rdr));
+    Ok((decoding_async_read(chained, compression), compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::{BzEncoder, GzipEncoder, ZstdEncoder};
+
+    const TEXT: &str = "line 1\nline 2\n";
+
+    async fn compressed(compression: Compression) -> Vec<u8> {
+        let data = TEXT.as_bytes();
+        let mut ret = Vec::new();
+        match compression {
+            Compression::NONE => AsyncReadExt::read_to_end(&mut &data[..], &mut ret).await,
+            Compression::GZIP => GzipEncoder::new(data).read_to_end(&mut ret).await,
+            Compression::ZSTD => ZstdEncoder::new(data).read_to_end(&mut ret).await,
+            Compression::BZIP2 => BzEncoder::new(data).read_to_end(&mut ret).await,
+        }
+        .unwrap();
+        ret
+    }
+
+    #[tokio::test]
+    async fn decompress_works() {
+        for compression in [
+            Compression::NONE,
+            Compression::GZIP,
+            Compression::ZSTD,
+            Compression::BZIP2,
+        ] {
+            // concatenated streams
+            let data = [compressed(compression).await, compressed(compression).await].concat();
+            assert_eq!(Compression::detect(&data, "-"), compression);
+            let (mut rdr, detected) = decompress_async_read(Box::pin(Cursor::new(data)), "-")
+                .await
+                .unwrap();
+            assert_eq!(detected, compression);
+            let mut s = String::new();
+            rdr.read_to_string(&mut s).await.unwrap();
+            assert_eq!(s, TEXT.repeat(2));
+        }
+        assert_eq!(Compression::detect(b"x", "a.log.gz"), Compression::GZIP);
+        assert_eq!(Compression::detect(b"", "a.log.gz"), Compression::NONE);
+        assert_eq!(Compression::detect(b"BZ", "a.log"), Compression::NONE);
+    }
+}
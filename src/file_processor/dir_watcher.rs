@@ -0,0 +1,355 @@
+use crate::async_pipeline::message_queue::CheckpointMarker;
+use crate::file_processor::file_follower::file_id;
+use crate::DynError;
+use glob::Pattern;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// How often the watched directory is checked for new files
+pub const DIR_POLL_INTERVAL_MS: u64 = 1000;
+
+/// The subdirectories the processed files are moved to (without a state file)
+const DONE_SUBDIR: &str = "done";
+const FAILED_SUBDIR: &str = "failed";
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    DONE,
+    // the file could not be (fully) read
+    FAILED,
+}
+
+/// A processed file, as recorded in the state file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ProcessedFile {
+    dev: Option<u64>,
+    inode: Option<u64>,
+    status: FileStatus,
+}
+
+/// Picks up the (matching) files appearing in a directory, once they stopped changing.
+/// After the outputs have flushed the data of a processed file, it is moved to the
+/// done/failed subdirectory or recorded in the state file, so it is never processed twice.
+pub struct DirWatcher {
+    dir: String,
+    pattern: Option<Pattern>,
+    state_file: Option<String>,
+    // the state file (and its temp file) names, when kept in the watched directory
+    state_file_names: Vec<String>,
+    processed: BTreeMap<String, ProcessedFile>,
+    // the size and mtime of the candidate files at the previous scan
+    last_seen: BTreeMap<String, (u64, SystemTime)>,
+    in_flight: BTreeSet<String>,
+    next_id: u64,
+    pending: VecDeque<(u64, String, FileStatus)>,
+    ack_tx: UnboundedSender<u64>,
+    ack_rx: UnboundedReceiver<u64>,
+}
+
+impl DirWatcher {
+    /// Watch the directory, loading the processed files from the state file (if any)
+    pub fn load(
+        dir: &str,
+        pattern: Option<Pattern>,
+        state_file: Option<&String>,
+    ) -> Result<DirWatcher, DynError> {
+        let processed = match state_file.map(fs::read_to_string) {
+            Some(Ok(json)) => serde_json::from_str(&json)?,
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(Box::new(err))
+            }
+            _ => BTreeMap::new(),
+        };
+        let (ack_tx, ack_rx) = unbounded_channel();
+        Ok(DirWatcher {
+            dir: dir.to_string(),
+            pattern,
+            state_file: state_file.cloned(),
+            state_file_names: state_file_names(dir, state_file),
+            processed,
+            last_seen: BTreeMap::new(),
+            in_flight: BTreeSet::new(),
+            next_id: 0,
+            pending: VecDeque::new(),
+            ack_tx,
+            ack_rx,
+        })
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    fn is_processed(&self, path: &str, meta: &fs::Metadata) -> bool {
+        match self.processed.get(path) {
+            // the same name can be reused by a new file
+            Some(pf) => match file_id(meta) {
+                Some((dev, inode)) => pf.dev == Some(dev) && pf.inode == Some(inode),
+                None => true,
+            },
+            None => false,
+        }
+    }
+
+    /// The new files which did not change since the previous scan, oldest first.
+    /// Hidden (e.g. temporary) files and the state file are skipped.
+    pub fn new_files(&mut self) -> Result<Vec<String>, DynError> {
+        let mut seen = BTreeMap::new();
+        let mut ret = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || self.state_file_names.contains(&name) {
+                continue;
+            }
+            if let Some(pattern) = &self.pattern {
+                if !pattern.matches(&name) {
+                    continue;
+                }
+            }
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                // removed or renamed since listed
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Box::new(err)),
+            };
+            let path = entry.path().to_string_lossy().to_string();
+            if !meta.is_file() || self.in_flight.contains(&path) || self.is_processed(&path, &meta)
+            {
+                continue;
+            }
+            let len_mtime = (meta.len(), meta.modified()?);
+            if self.last_seen.get(&path) == Some(&len_mtime) {
+                ret.push((len_mtime.1, path.clone()));
+            }
+            seen.insert(path, len_mtime);
+        }
+        self.last_seen = seen;
+        // the name order for the same mtime
+        ret.sort();
+        Ok(ret.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// The marker to send down the pipeline after the data of the processed file
+    pub fn marker(&mut self, path: &str, status: FileStatus) -> CheckpointMarker {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.in_flight.insert(path.to_string());
+        self.pending.push_back((id, path.to_string(), status));
+        CheckpointMarker::new(id, self.ack_tx.clone())
+    }
+
+    /// Move or record the files whose data the outputs have flushed
+    pub fn finish_acked(&mut self) -> Result<(), DynError> {
+        let mut changed = false;
+        while let Ok(acked_id) = self.ack_rx.try_recv() {
+            // the markers are acknowledged in the order sent
+            while let Some((id, path, status)) = self.pending.pop_front() {
+                self.finish_file(&path, status)?;
+                self.in_flight.remove(&path);
+                changed = true;
+                if id == acked_id {
+                    break;
+                }
+            }
+        }
+        if let (true, Some(state_file)) = (changed, &self.state_file) {
+            debug!("Saving the processed files to {}", state_file);
+            let tmp_file = format!("{}.tmp", state_file);
+            fs::write(&tmp_file, serde_json::to_string_pretty(&self.processed)?)?;
+            fs::rename(&tmp_file, state_file)?;
+        }
+        Ok(())
+    }
+
+    /// Record the file (by its identity) as processed
+    fn record_file(&mut self, path: &str, status: FileStatus) {
+        let read_file_id = fs::metadata(path).ok().and_then(|meta| file_id(&meta));
+        self.processed.insert(
+            path.to_string(),
+            ProcessedFile {
+                dev: read_file_id.map(|(dev, _)| dev),
+                inode: read_file_id.map(|(_, inode)| inode),
+                status,
+            },
+        );
+    }
+
+    fn finish_file(&mut self, path: &str, status: FileStatus) -> Result<(), DynError> {
+        if self.state_file.is_some() {
+            self.record_file(path, status);
+            return Ok(());
+        }
+        let subdir = Path::new(&self.dir).join(match status {
+            FileStatus::DONE => DONE_SUBDIR,
+            FileStatus::FAILED => FAILED_SUBDIR,
+        });
+        match move_file(&subdir, path) {
+            Ok(target) => info!("Moved the input file {} to {}", path, target.display()),
+            // e.g. removed by someone else in the meantime
+            Err(_) if !Path::new(path).exists() => {
+                info!("The input file {} is gone, not moving it", path)
+            }
+            Err(err) => {
+                // kept in memory only, the file is processed again after a restart
+                error!(
+                    "Error moving the input file {} to {}, skipping it from now on: {:?}",
+                    path,
+                    subdir.display(),
+                    err
+                );
+                self.record_file(path, status);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The names of the state file and its temp file if they are in dir, otherwise empty
+fn state_file_names(dir: &str, state_file: Option<&String>) -> Vec<String> {
+    let state_path = match state_file {
+        Some(state_file) => Path::new(state_file),
+        None => return Vec::new(),
+    };
+    let state_dir = match state_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let same_dir = match (fs::canonicalize(dir), fs::canonicalize(state_dir)) {
+        (Ok(dir), Ok(state_dir)) => dir == state_dir,
+        _ => false,
+    };
+    match (same_dir, state_path.file_name()) {
+        (true, Some(name)) => {
+            let name = name.to_string_lossy().to_string();
+            vec![format!("{}.tmp", name), name]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Move the file to (the created if missing) dir, return the path moved to
+fn move_file(dir: &Path, path: &str) -> Result<PathBuf, std::io::Error> {
+    fs::create_dir_all(dir)?;
+    let target = unique_target(dir, Path::new(path));
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// The path to move the file to in dir, with a numeric suffix if the name is taken
+fn unique_target(dir: &Path, path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut target = dir.join(&*name);
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dir_watcher_move_works() {
+        let dir = test_dir("hustlog_dir_watcher_test");
+        let d = dir.to_str().unwrap();
+        fs::write(dir.join("a.log"), "line 1\n").unwrap();
+        fs::write(dir.join("b.log"), "line 2\n").unwrap();
+        fs::write(dir.join("c.txt"), "line 3\n").unwrap();
+        fs::write(dir.join(".d.log"), "line 4\n").unwrap();
+        fs::create_dir_all(dir.join(DONE_SUBDIR)).unwrap();
+        fs::write(dir.join(DONE_SUBDIR).join("a.log"), "old\n").unwrap();
+
+        let mut watcher = DirWatcher::load(d, Some(Pattern::new("*.log").unwrap()), None).unwrap();
+        // not changed since the previous scan
+        assert!(watcher.new_files().unwrap().is_empty());
+        let files = watcher.new_files().unwrap();
+        assert_eq!(files.len(), 2);
+        let marker_a = watcher.marker(&files[0], FileStatus::DONE);
+        let marker_b = watcher.marker(&files[1], FileStatus::FAILED);
+        assert!(watcher.new_files().unwrap().is_empty());
+        // not moved before acknowledged
+        watcher.finish_acked().unwrap();
+        assert!(dir.join("a.log").exists());
+        marker_a.ack();
+        watcher.finish_acked().unwrap();
+        assert!(!dir.join("a.log").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("done/a.log.1")).unwrap(),
+            "line 1\n"
+        );
+        assert!(dir.join("b.log").exists());
+        marker_b.ack();
+        watcher.finish_acked().unwrap();
+        assert!(dir.join("failed/b.log").exists());
+        assert!(dir.join("c.txt").exists());
+
+        // not picked up again when it can not be moved
+        fs::write(dir.join("e.log"), "line 5\n").unwrap();
+        fs::remove_dir_all(dir.join(DONE_SUBDIR)).unwrap();
+        fs::write(dir.join(DONE_SUBDIR), "not a directory\n").unwrap();
+        watcher.new_files().unwrap();
+        let files = watcher.new_files().unwrap();
+        assert_eq!(files.len(), 1);
+        watcher.marker(&files[0], FileStatus::DONE).ack();
+        watcher.finish_acked().unwrap();
+        assert!(dir.join("e.log").exists());
+        watcher.new_files().unwrap();
+        assert!(watcher.new_files().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_watcher_state_file_works() {
+        let dir = test_dir("hustlog_dir_watcher_state_test");
+        let d = dir.to_str().unwrap();
+        let state_file = dir.join("state.json").to_str().unwrap().to_string();
+        fs::write(dir.join("a.log"), "line 1\n").unwrap();
+        let pattern = Some(Pattern::new("*.log").unwrap());
+
+        let mut watcher = DirWatcher::load(d, pattern.clone(), Some(&state_file)).unwrap();
+        watcher.new_files().unwrap();
+        let files = watcher.new_files().unwrap();
+        assert_eq!(files.len(), 1);
+        watcher.marker(&files[0], FileStatus::DONE).ack();
+        watcher.finish_acked().unwrap();
+        assert!(dir.join("a.log").exists());
+
+        // not processed again after a restart, unless replaced
+        let mut watcher = DirWatcher::load(d, pattern, Some(&state_file)).unwrap();
+        watcher.new_files().unwrap();
+        assert!(watcher.new_files().unwrap().is_empty());
+        fs::write(dir.join(".a.log.tmp"), "line 2\n").unwrap();
+        fs::rename(dir.join(".a.log.tmp"), dir.join("a.log")).unwrap();
+        watcher.new_files().unwrap();
+        assert_eq!(watcher.new_files().unwrap().len(), 1);
+
+        // the state file is not an input, even without a pattern
+        fs::write(format!("{}.tmp", state_file), "{}").unwrap();
+        let mut watcher = DirWatcher::load(d, None, Some(&state_file)).unwrap();
+        watcher.new_files().unwrap();
+        assert_eq!(
+            watcher.new_files().unwrap(),
+            vec![dir.join("a.log").to_str().unwrap().to_string()]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use bytes::BytesMut;
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// How often the followed file is checked for appended data, when idle
+pub const FOLLOW_POLL_INTERVAL_MS: u64 = 250;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FileChange {
+    // the path is a new file now (e.g. the old one was renamed by logrotate)
+    ROTATED,
+    // the file is shorter than the position read up to (e.g. logrotate copytruncate)
+    TRUNCATED,
+}
+
+/// The (device, inode) identifying a file, regardless of its path
+#[cfg(unix)]
+pub fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Reads the data appended to a file (like tail -F), detecting when
+/// the file is rotated or truncated and reopening/rereading it
+pub struct FileFollower {
+    path: String,
+    file: File,
+    file_id: Option<(u64, u64)>,
+    pos: u64,
+}
+
+impl FileFollower {
+    /// Follow the file opened from path (with the file_id), read up to pos
+    pub fn new(path: &str, file: File, file_id: Option<(u64, u64)>, pos: u64) -> FileFollower {
+        FileFollower {
+            path: path.to_string(),
+            file,
+            file_id,
+            pos,
+        }
+    }
+
+    pub fn file_id(&self) -> Option<(u64, u64)> {
+        self.file_id
+    }
+
+    /// Read the data available in the current file, 0 at its end
+    pub async fn read_buf(&mut self, buf: &mut BytesMut) -> Result<usize, std::io::Error> {
+        let rd = self.file.read_buf(buf).await?;
+        self.pos += rd as u64;
+        Ok(rd)
+    }
+
+    /// Check (at the end of the current file) whether the path was rotated or truncated,
+    /// in which case it is read from the start. The current file is kept while the path
+    /// does not exist, e.g. between the rotation and the creation of the new file.
+    pub async fn check_change(&mut self) -> Result<Option<FileChange>, std::io::Error> {
+        let meta = match tokio::fs::metadata(&self.path).await {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let new_id = file_id(&meta);
+        if new_id != self.file_id {
+            self.file = File::open(&self.path).await?;
+            self.file_id = file_id(&self.file.metadata().await?);
+            self.pos = 0;
+            return Ok(Some(FileChange::ROTATED));
+        }
+        if meta.len() < self.pos {
+            self.file.seek(SeekFrom::Start(0)).await?;
+            self.pos = 0;
+            return Ok(Some(FileChange::TRUNCATED));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    async fn read_all(ff: &mut FileFollower) -> String {
+        let mut buf = BytesMut::new();
+        while ff.read_buf(&mut buf).await.unwrap() > 0 {}
+        String::from_utf8_lossy(buf.as_ref()).to_string()
+    }
+
+    fn append(path: &str, s: &str) {
+        let mut f = fs::OpenOptions::new().append(true).open(path).unwrap();
+        f.write_all(s.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_follower_works() {
+        let dir = std::env::temp_dir().join(format!("hustlog_follow_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let path = path.to_str().unwrap();
+        fs::write(path, "a\n").unwrap();
+
+        let file = File::open(path).await.unwrap();
+        let mut ff = FileFollower::new(path, file, file_id(&fs::metadata(path).unwrap()), 0);
+        assert_eq!(read_all(&mut ff).await, "a\n");
+        assert_eq!(ff.check_change().await.unwrap(), None);
+        append(path, "b\n");
+        assert_eq!(read_all(&mut ff).await, "b\n");
+        assert_eq!(ff.pos, 4);
+
+        // copytruncate
+        fs::write(path, "c\n").unwrap();
+        assert_eq!(
+            ff.check_change().await.unwrap(),
+            Some(FileChange::TRUNCATED)
+        );
+        assert_eq!(read_all(&mut ff).await, "c\n");
+
+        // rename and create, the rest of the old file is read first
+        let rotated = dir.join("test.log.1");
+        fs::rename(path, &rotated).unwrap();
+        append(rotated.to_str().unwrap(), "d\n");
+        assert_eq!(ff.check_change().await.unwrap(), None);
+        assert_eq!(read_all(&mut ff).await, "d\n");
+        fs::write(path, "e\n").unwrap();
+        assert_eq!(ff.check_change().await.unwrap(), Some(FileChange::ROTATED));
+        assert_eq!(read_all(&mut ff).await, "e\n");
+        assert_eq!(ff.pos, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
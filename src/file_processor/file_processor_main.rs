@@ -1,17 +1,135 @@
 use crate::async_pipeline::message_queue::MessageSender;
 use crate::async_pipeline::{create_processing_pipeline, LinesBuffer};
+use crate::file_processor::checkpoint::{CheckpointStore, FileCheckpoint};
+use crate::file_processor::decompress::{decoding_async_read, Compression};
+use crate::file_processor::dir_watcher::{DirWatcher, FileStatus, DIR_POLL_INTERVAL_MS};
+use crate::file_processor::file_follower::{file_id, FileFollower, FOLLOW_POLL_INTERVAL_MS};
 use crate::parser::RawMessage;
-use crate::{DynError, HustlogConfig};
-use log::error;
+use crate::{ConfigError, DynAsyncRead, DynError, HustlogConfig};
+use chrono::{DateTime, Utc};
+use log::{error, info, log_enabled, trace, Level};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::io::AsyncReadExt;
+use std::time::{Duration, Instant};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time::{interval, sleep};
 
-async fn process_input(
-    hcrc: Arc<HustlogConfig>,
+/// The input file opened at the checkpoint offset (if resuming)
+struct InputFile {
+    file: File,
+    file_id: Option<(u64, u64)>,
+    offset: u64,
+    len: u64,
+    compression: Compression,
+}
+
+/// Open the input file at the checkpoint offset (if resuming) and detect its compression
+async fn open_input_file(
+    path: &str,
+    checkpoints: &Option<CheckpointStore>,
+) -> Result<InputFile, DynError> {
+    let file = std::fs::File::open(path)?;
+    let offset = match checkpoints {
+        Some(store) => store.resume_offset(path, &file)?,
+        None => 0,
+    };
+    let compression = Compression::of_file(&file, path)?;
+    let meta = file.metadata()?;
+    let mut file = File::from_std(file);
+    file.seek(SeekFrom::Start(offset)).await?;
+    Ok(InputFile {
+        file,
+        file_id: file_id(&meta),
+        offset,
+        len: meta.len(),
+        compression,
+    })
+}
+
+/// Send the checkpoint of the data emitted up to offset down the pipeline (which
+/// also flushes it), or just a flush if there is nothing new to checkpoint
+async fn checkpoint_or_flush(
+    checkpoints: &mut Option<CheckpointStore>,
     raw_sender: &MessageSender<Vec<RawMessage>>,
+    path: &str,
+    read_file_id: Option<(u64, u64)>,
+    offset: u64,
 ) -> Result<(), DynError> {
-    let mut async_read = hcrc.get_async_read().await?;
-    let mut lines_buffer = LinesBuffer::new(hcrc.merge_multi_line());
+    if let Some(store) = checkpoints {
+        store.save_acked()?;
+        let marker = FileCheckpoint::of_file(path, read_file_id, offset)?
+            .and_then(|cp| store.marker(path, cp));
+        if let Some(marker) = marker {
+            raw_sender.checkpoint(marker).await?;
+            return Ok(());
+        }
+    }
+    raw_sender.flush().await?;
+    Ok(())
+}
+
+/// Resolves with the name of the shutdown signal received, SIGINT (Ctrl-C) or SIGTERM
+/// (e.g. a stopped service). The handlers are registered right away, so that the signals
+/// received while busy (e.g. reading a file) are not missed.
+fn shutdown_signal() -> Result<Pin<Box<dyn Future<Output = &'static str> + Send>>, DynError> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+        Ok(Box::pin(async move {
+            tokio::select! {
+                _ = sigint.recv() => "SIGINT",
+                _ = sigterm.recv() => "SIGTERM",
+            }
+        }))
+    }
+    #[cfg(not(unix))]
+    Ok(Box::pin(async {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }))
+}
+
+/// Read the input file (or stdin) to its end, return false if reading (or sending) it failed
+async fn process_input(
+    hcrc: &HustlogConfig,
+    path: &str,
+    raw_sender: &MessageSender<Vec<RawMessage>>,
+    checkpoints: &mut Option<CheckpointStore>,
+) -> Result<bool, DynError> {
+    let mut lines_buffer = LinesBuffer::new(hcrc.line_merger_conf().as_ref());
+    // a compressed file is checkpointed (at its length) only once read to its end
+    let mut compressed_len = None;
+    let (mut async_read, read_file_id): (Pin<DynAsyncRead>, _) = if checkpoints.is_some() {
+        let input_file = open_input_file(path, checkpoints).await?;
+        if input_file.compression == Compression::NONE {
+            lines_buffer.set_offset(input_file.offset);
+            (Box::pin(input_file.file), input_file.file_id)
+        } else if input_file.offset > 0 && input_file.offset == input_file.len {
+            info!(
+                "Skipping the already processed compressed input file {}",
+                path
+            );
+            return Ok(true);
+        } else {
+            let mut file = input_file.file;
+            file.seek(SeekFrom::Start(0)).await?;
+            compressed_len = Some(input_file.len);
+            let reader = decoding_async_read(Box::pin(file), input_file.compression);
+            (reader, input_file.file_id)
+        }
+    } else {
+        (hcrc.get_async_read(path).await?, None)
+    };
+    lines_buffer.set_ref_time(hcrc.get_input_mtime(path).await);
+    lines_buffer.set_source(Some(Arc::from(path)));
+    let checkpoint_interval = Duration::from_secs(hcrc.get_tick_interval());
+    let mut last_checkpoint = Instant::now();
+    let mut read_failed = false;
     loop {
         let read_res = async_read.as_mut().read_buf(lines_buffer.get_buf()).await;
         match read_res {
@@ -23,25 +141,209 @@ async fn process_input(
                 let msgs = lines_buffer.read_messages_from_buf();
                 if let Err(err) = raw_sender.send(msgs).await {
                     error!("Error sending raw message downstream, aborting: {:?}", err);
+                    read_failed = true;
                     break;
                 }
+                if checkpoints.is_some()
+                    && compressed_len.is_none()
+                    && last_checkpoint.elapsed() >= checkpoint_interval
+                {
+                    let offset = lines_buffer.emitted_offset();
+                    checkpoint_or_flush(checkpoints, raw_sender, path, read_file_id, offset)
+                        .await?;
+                    last_checkpoint = Instant::now();
+                }
             }
             Err(err) => {
                 error!("Error reading from input, aborting: {:?}", err);
+                read_failed = true;
                 break;
             }
         }
     }
     let msgs = lines_buffer.flush();
     raw_sender.send(msgs).await?;
+    // the data emitted from a compressed file can not be resumed from
+    if checkpoints.is_some() && !(read_failed && compressed_len.is_some()) {
+        let offset = compressed_len.unwrap_or_else(|| lines_buffer.emitted_offset());
+        checkpoint_or_flush(checkpoints, raw_sender, path, read_file_id, offset).await?;
+    }
+    Ok(!read_failed)
+}
+
+async fn file_mtime(path: &str) -> Option<DateTime<Utc>> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    Some(DateTime::<Utc>::from(modified))
+}
+
+/// Keep reading the data appended to the input file until SIGINT or SIGTERM, the downstream
+/// buffers are flushed (and the checkpoints sent) on every tick, like with the syslog servers
+async fn follow_input(
+    hcrc: &HustlogConfig,
+    path: &str,
+    raw_sender: &MessageSender<Vec<RawMessage>>,
+    checkpoints: &mut Option<CheckpointStore>,
+) -> Result<(), DynError> {
+    let input_file = open_input_file(path, checkpoints).await?;
+    if input_file.compression != Compression::NONE {
+        return Err(Box::new(ConfigError::new(&format!(
+            "The compressed input file {} can not be followed",
+            path
+        ))));
+    }
+    let mut follower =
+        FileFollower::new(path, input_file.file, input_file.file_id, input_file.offset);
+    let mut lines_buffer = LinesBuffer::new(hcrc.line_merger_conf().as_ref());
+    lines_buffer.set_offset(input_file.offset);
+    lines_buffer.set_ref_time(hcrc.get_input_mtime(path).await);
+    lines_buffer.set_source(Some(Arc::from(path)));
+    let mut intvl = interval(Duration::from_secs(hcrc.get_tick_interval()));
+    let mut shutdown = shutdown_signal()?;
+    info!("Following input file {}", path);
+    let mut at_end = false;
+    loop {
+        let poll_delay = if at_end {
+            Duration::from_millis(FOLLOW_POLL_INTERVAL_MS)
+        } else {
+            Duration::ZERO
+        };
+        tokio::select! {
+            sig = &mut shutdown => {
+                info!("{} received, flushing buffers ...", sig);
+                break
+            }
+            _tick = intvl.tick() => {
+                if log_enabled!(Level::Trace) {
+                    trace!("TICK");
+                }
+                let offset = lines_buffer.emitted_offset();
+                checkpoint_or_flush(checkpoints, raw_sender, path, follower.file_id(), offset)
+                    .await?;
+            }
+            _ = sleep(poll_delay) => {
+                let rd = follower.read_buf(lines_buffer.get_buf()).await?;
+                at_end = rd == 0;
+                if !at_end {
+                    let msgs = lines_buffer.read_messages_from_buf();
+                    if !msgs.is_empty() {
+                        raw_sender.send(msgs).await?;
+                    }
+                } else if let Some(change) = follower.check_change().await? {
+                    info!("Input file {} {:?}, reading it from the start", path, change);
+                    // the incomplete last line (if any) of the old content
+                    let msgs = lines_buffer.flush();
+                    raw_sender.send(msgs).await?;
+                    lines_buffer.set_offset(0);
+                    lines_buffer.set_ref_time(file_mtime(path).await);
+                    at_end = false;
+                }
+            }
+        }
+    }
+    let msgs = lines_buffer.flush();
+    raw_sender.send(msgs).await?;
+    if checkpoints.is_some() {
+        let offset = lines_buffer.emitted_offset();
+        checkpoint_or_flush(checkpoints, raw_sender, path, follower.file_id(), offset).await?;
+    }
+    Ok(())
+}
+
+/// The index of the last input file which is not compressed, the one to follow
+fn followed_input(input_files: &[String]) -> Result<usize, DynError> {
+    for (ix, path) in input_files.iter().enumerate().rev() {
+        let file = std::fs::File::open(path)?;
+        if Compression::of_file(&file, path)? == Compression::NONE {
+            return Ok(ix);
+        }
+    }
+    Err(Box::new(ConfigError::new(
+        "Follow mode (--follow) requires an uncompressed input file",
+    )))
+}
+
+/// Process the input files one after the other through the same pipeline,
+/// in follow mode the last uncompressed one is followed after reading the others
+/// (e.g. the compressed rotated ones)
+async fn process_inputs(
+    hcrc: &HustlogConfig,
+    raw_sender: &MessageSender<Vec<RawMessage>>,
+    checkpoints: &mut Option<CheckpointStore>,
+) -> Result<(), DynError> {
+    let input_files = hcrc.get_input_files();
+    let followed = if hcrc.get_follow() {
+        Some(followed_input(input_files)?)
+    } else {
+        None
+    };
+    for (ix, path) in input_files.iter().enumerate() {
+        if followed != Some(ix) {
+            info!("Processing input file {}", path);
+            process_input(hcrc, path, raw_sender, checkpoints).await?;
+        }
+    }
+    if let Some(ix) = followed {
+        follow_input(hcrc, &input_files[ix], raw_sender, checkpoints).await?;
+    }
+    raw_sender.shutdown().await?;
+    Ok(())
+}
+
+/// Process the files appearing in the watched directory until interrupted, each followed
+/// by a marker which finishes (moves or records) the file once the outputs flushed its data
+async fn watch_dir_inputs(
+    hcrc: &HustlogConfig,
+    raw_sender: &MessageSender<Vec<RawMessage>>,
+    watcher: &mut DirWatcher,
+) -> Result<(), DynError> {
+    let mut no_checkpoints = None;
+    let mut shutdown = shutdown_signal()?;
+    info!("Watching input directory {}", watcher.dir());
+    loop {
+        watcher.finish_acked()?;
+        for path in watcher.new_files()? {
+            info!("Processing input file {}", path);
+            let status = match process_input(hcrc, &path, raw_sender, &mut no_checkpoints).await {
+                Ok(true) => FileStatus::DONE,
+                Ok(false) => FileStatus::FAILED,
+                Err(err) => {
+                    error!("Error processing input file {}: {:?}", path, err);
+                    FileStatus::FAILED
+                }
+            };
+            raw_sender.checkpoint(watcher.marker(&path, status)).await?;
+        }
+        tokio::select! {
+            sig = &mut shutdown => {
+                info!("{} received, flushing buffers ...", sig);
+                break
+            }
+            _ = sleep(Duration::from_millis(DIR_POLL_INTERVAL_MS)) => {}
+        }
+    }
     raw_sender.shutdown().await?;
     Ok(())
 }
 
 pub async fn file_process_main(hc: HustlogConfig) -> Result<(), DynError> {
     let hcrc = Arc::new(hc);
+    let mut checkpoints = match hcrc.get_checkpoint_file() {
+        Some(state_file) => Some(CheckpointStore::load(state_file)?),
+        None => None,
+    };
+    let mut dir_watcher = match hcrc.get_watch_dir() {
+        Some(dir) => Some(DirWatcher::load(
+            dir,
+            hcrc.get_watch_pattern().clone(),
+            hcrc.get_watch_state_file(),
+        )?),
+        None => None,
+    };
     let (raw_sender, join_handles) = create_processing_pipeline(&hcrc).await?;
-    let process_input_res = process_input(hcrc, &raw_sender).await;
+    let process_input_res = match dir_watcher.as_mut() {
+        Some(watcher) => watch_dir_inputs(&hcrc, &raw_sender, watcher).await,
+        None => process_inputs(&hcrc, &raw_sender, &mut checkpoints).await,
+    };
     let err = if let Err(e) = process_input_res {
         error!("Error from the input processing: {:?}", e);
         if let Err(e) = raw_sender.shutdown().await {
@@ -54,6 +356,14 @@ pub async fn file_process_main(hc: HustlogConfig) -> Result<(), DynError> {
     for jh in join_handles {
         jh.join().await;
     }
+    if let Some(store) = checkpoints.as_mut() {
+        // the checkpoints acknowledged by the outputs before they were shut down
+        store.save_acked()?;
+    }
+    if let Some(watcher) = dir_watcher.as_mut() {
+        watcher.finish_acked()?;
+    }
+    hcrc.log_str_overflow_stats();
     if err.is_some() {
         Err(err.unwrap())
     } else {
@@ -1,3 +1,8 @@
+mod checkpoint;
+mod decompress;
+mod dir_watcher;
+mod file_follower;
 mod file_processor_main;
 
+pub use decompress::decompress_async_read;
 pub use file_processor_main::file_process_main;
@@ -6,6 +6,7 @@ use crate::file_processor::file_process_main;
 use crate::parser::GrokParser;
 use crate::syslog_server::server_main;
 use conf::*;
+use std::collections::BTreeMap;
 
 mod async_pipeline;
 mod conf;
@@ -35,12 +36,51 @@ fn main_print_default_patterns() -> Result<(), DynError> {
     return Ok(());
 }
 
+/// Infer the schema columns, print them (as YAML) and return None, or return the
+/// args with the inferred columns to run with (--grok-infer-schema-run).
+/// The configured derived columns are kept after the inferred ones
+fn main_infer_schema(mut args: MyArgs, sample_size: usize) -> Result<Option<MyArgs>, DynError> {
+    let inference = SchemaConfig::infer_grok_columns(&args, sample_size)?;
+    if inference.matched() == 0 {
+        return Err(Box::new(ConfigError::new(
+            "The grok pattern did not match any of the sampled lines",
+        )));
+    }
+    let mut columns = inference.column_specs();
+    columns.extend(SchemaConfig::derived_column_specs(&args)?);
+    if args.grok_infer_schema_run {
+        args.grok_schema_columns = columns;
+        return Ok(Some(args));
+    }
+    let mut yaml = BTreeMap::new();
+    yaml.insert("grok_schema_columns", columns);
+    println!(
+        "# inferred from {} matching of {} sampled lines",
+        inference.matched(),
+        inference.sampled()
+    );
+    print!("{}", serde_yaml::to_string(&yaml)?);
+    Ok(None)
+}
+
 fn main() -> Result<(), DynError> {
-    let args: MyArgs = MyArgs::parse();
+    let mut args: MyArgs = MyArgs::parse();
     //println!("ARGS: {:?}", args);
     if args.grok_list_default_patterns() {
         return main_print_default_patterns();
     }
+    if let Some(sample_size) = args.grok_debug {
+        for explanation in SchemaConfig::debug_grok_pattern(&args, sample_size)? {
+            println!("{}", explanation);
+        }
+        return Ok(());
+    }
+    if let Some(sample_size) = args.grok_infer_schema {
+        args = match main_infer_schema(args, sample_size)? {
+            Some(args) => args,
+            None => return Ok(()),
+        };
+    }
     // no conf/schema before this point, no args after it.
     let conf = HustlogConfig::new(args)?;
     env_logger::init(); // TODO use conf?
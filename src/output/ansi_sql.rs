@@ -1,28 +1,49 @@
+use crate::conf::HustlogConfig;
 use crate::output::output_sink::OutputSink;
 use crate::ql_processor::{QlRow, QlSchema};
 use crate::sqlgen::{BatchedInserts, SqlCreateSchema};
 use crate::{DynBoxWrite, DynError};
+use chrono_tz::Tz;
 use std::sync::Arc;
 
 pub struct AnsiSqlOutput {
     ddl: Option<SqlCreateSchema>,
     inserts: BatchedInserts,
+    output_tz: Option<Tz>,
     // outp: Box<dyn Write>,
 }
 
 impl AnsiSqlOutput {
-    pub fn new(schema: Arc<QlSchema>, add_ddl: bool, batch_size: usize, outp: DynBoxWrite, pre_name_opts: &Arc<str>, table_opts:&Arc<str>) -> Self {
+    /// The batch size, the DDL options and the output timezone come from the config
+    pub fn new(
+        schema: Arc<QlSchema>,
+        add_ddl: bool,
+        outp: DynBoxWrite,
+        conf: &HustlogConfig,
+    ) -> Self {
         let ddl = if add_ddl {
-            Some(SqlCreateSchema::from_ql_schema(&schema, Arc::clone(pre_name_opts), Arc::clone(table_opts)))
+            Some(SqlCreateSchema::from_ql_schema(
+                &schema,
+                Arc::clone(conf.get_ddl_pre_name_opts()),
+                Arc::clone(conf.get_ddl_table_opts()),
+                conf.get_ddl_ip_type(),
+            ))
         } else {
             None
         };
-        let inserts = BatchedInserts::new(schema, batch_size, outp);
-        Self { ddl, inserts }
+        let inserts = BatchedInserts::new(schema, conf.output_batch_size(), outp);
+        Self {
+            ddl,
+            inserts,
+            output_tz: conf.output_timezone(),
+        }
     }
 
     fn output_row(&mut self, row: QlRow) -> Result<(), DynError> {
-        self.inserts.add_to_batch(row)
+        match &self.output_tz {
+            Some(tz) => self.inserts.add_to_batch(row.with_timezone(tz)),
+            None => self.inserts.add_to_batch(row),
+        }
     }
 }
 
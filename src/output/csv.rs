@@ -2,24 +2,36 @@ use crate::output::output_sink::OutputSink;
 use crate::parser::ParserSchema;
 use crate::ql_processor::{QlRow, QlSchema};
 use crate::{DynBoxWrite, DynError};
+use chrono_tz::Tz;
 use std::sync::Arc;
 
 pub struct CsvOutput {
     schema: Arc<QlSchema>,
     wr: csv::Writer<DynBoxWrite>,
     add_header: bool,
+    output_tz: Option<Tz>,
 }
 
 impl CsvOutput {
-    pub fn new(schema: Arc<QlSchema>, outp: DynBoxWrite, add_header: bool) -> Self {
+    pub fn new(
+        schema: Arc<QlSchema>,
+        outp: DynBoxWrite,
+        add_header: bool,
+        output_tz: Option<Tz>,
+    ) -> Self {
         Self {
             schema,
             wr: csv::Writer::from_writer(outp),
             add_header,
+            output_tz,
         }
     }
 
     fn output_row(&mut self, row: QlRow) -> Result<(), DynError> {
+        let row = match &self.output_tz {
+            Some(tz) => row.with_timezone(tz),
+            None => row,
+        };
         let rc_row = row.data_as_strs();
         let o = rc_row.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
         let ret = self.wr.write_record(o);
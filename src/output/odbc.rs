@@ -1,5 +1,5 @@
 use crate::output::OutputSink;
-use crate::parser::{ParsedValue, ParsedValueType, ParserSchema};
+use crate::parser::{duration_secs, ParsedValue, ParsedValueType, ParserSchema};
 use crate::ql_processor::{QlRow, QlSchema};
 use crate::DynError;
 use chrono::{DateTime, Datelike, FixedOffset, Timelike};
@@ -25,7 +25,11 @@ fn pv_type2buffer_kind(pv_type: &ParsedValueType) -> BufferKind {
         }
         ParsedValueType::LongType => BufferKind::I64,
         ParsedValueType::DoubleType => BufferKind::F64,
+        // in seconds
+        ParsedValueType::DurationType(_) => BufferKind::F64,
         ParsedValueType::TimeType(_) => BufferKind::Timestamp,
+        // the textual form, up to a full IPv6 address with an embedded IPv4 one
+        ParsedValueType::IpType => BufferKind::Text { max_str_len: 45 },
         ParsedValueType::StrType(sz) => BufferKind::Text { max_str_len: *sz },
     }
 }
@@ -113,6 +117,8 @@ impl OdbcSink {
                             x.set_cell(row_index, None);
                         } else if let ParsedValue::StrVal(sv) = v.as_ref() {
                             x.set_cell(row_index, Some(sv.as_bytes()))
+                        } else if let ParsedValue::IpVal(ip) = v.as_ref() {
+                            x.set_cell(row_index, Some(ip.to_string().as_bytes()))
                         } else {
                             panic!("BUG: Incompatible ODBC Text value: {:?}", v)
                         }
@@ -131,6 +137,8 @@ impl OdbcSink {
                     AnyColumnSliceMut::F64(x) => {
                         if let ParsedValue::DoubleVal(d) = v.as_ref() {
                             x[row_index] = *d;
+                        } else if let ParsedValue::DurationVal(d) = v.as_ref() {
+                            x[row_index] = duration_secs(d);
                         } else {
                             panic!("BUG: Incompatible ODBC Text value: {:?}", v)
                         }
@@ -166,6 +174,8 @@ impl OdbcSink {
                         let (values, _is) = x.raw_values();
                         if let ParsedValue::DoubleVal(d) = v.as_ref() {
                             values[row_index] = *d;
+                        } else if let ParsedValue::DurationVal(d) = v.as_ref() {
+                            values[row_index] = duration_secs(d);
                         }
                     }
                     // AnyColumnSliceMut::NullableF32(_) => {}
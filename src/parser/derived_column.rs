@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::parser::parser::*;
+use crate::parser::schema::ParserColDef;
+
+/// Where the value of a column which does not come from the parser comes from
+#[derive(Debug, Clone)]
+pub enum DerivedValue {
+    CONST(Arc<ParsedValue>), // constant, incl. the host name
+    FILE,                    // the input file the message was read from
+    HASH,                    // SHA-256 (hex) of the raw message, e.g. for deduplication
+}
+
+/// A computed or constant column, declared as <column_name>=<source> where source is one of
+/// hostname, input_file, raw_hash or const:<value>, e.g. "env=const:prod".
+/// The receive time is the built-in _received_at column
+#[derive(Debug, Clone)]
+pub struct DerivedColumnDef {
+    pcd: ParserColDef,
+    value: DerivedValue,
+}
+
+fn str_const(s: &str, max_len: usize) -> (ParsedValueType, DerivedValue) {
+    (
+        ParsedValueType::StrType(max_len),
+        DerivedValue::CONST(Arc::new(ParsedValue::StrVal(Arc::new(s.to_string())))),
+    )
+}
+
+impl DerivedColumnDef {
+    /// Parse a derived column spec, None if the spec is not one (i.e. a parser column)
+    pub fn parse(spec: &str) -> Result<Option<DerivedColumnDef>, String> {
+        let (name, source) = match spec.split_once('=') {
+            Some((name, source)) if !name.contains(':') => (name, source),
+            _ => return Ok(None),
+        };
+        // derived columns are never missing
+        let name = name.strip_prefix('+').unwrap_or(name);
+        if name.is_empty() {
+            return Err(format!("Empty derived column name: {}", spec));
+        }
+        let (pv_type, value) = match source.split_once(':') {
+            Some(("const", v)) => str_const(v, v.len().max(1)),
+            None if source == "hostname" => {
+                str_const(gethostname::gethostname().to_string_lossy().as_ref(), 256)
+            }
+            None if source == "input_file" => (ParsedValueType::StrType(1024), DerivedValue::FILE),
+            None if source == "raw_hash" => (ParsedValueType::StrType(64), DerivedValue::HASH),
+            _ => {
+                return Err(format!(
+                    "Invalid derived column (must be one of hostname, input_file, raw_hash, const:<value>): {}",
+                    spec
+                ))
+            }
+        };
+        Ok(Some(DerivedColumnDef {
+            pcd: ParserColDef::new(name, &pv_type, false),
+            value,
+        }))
+    }
+
+    pub fn pcd(&self) -> &ParserColDef {
+        &self.pcd
+    }
+
+    pub fn value(&self) -> &DerivedValue {
+        &self.value
+    }
+}
+
+impl DerivedValue {
+    pub fn value_of(&self, raw: &RawMessage) -> Arc<ParsedValue> {
+        match self {
+            DerivedValue::CONST(v) => v.clone(),
+            // null for stdin and the syslog servers
+            DerivedValue::FILE => match raw.input_file() {
+                Some(f) => Arc::new(ParsedValue::StrVal(Arc::new(f.to_string()))),
+                None => arc_null_pv(),
+            },
+            DerivedValue::HASH => {
+                let digest = Sha256::digest(raw.as_str().as_bytes());
+                let hex = digest
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                Arc::new(ParsedValue::StrVal(Arc::new(hex)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_columns_work() {
+        let raw = RawMessage::new(String::from("abc"));
+        let env = DerivedColumnDef::parse("env=const:prod").unwrap().unwrap();
+        assert_eq!(env.pcd().name().as_ref(), "env");
+        assert_eq!(env.pcd().pv_type(), &ParsedValueType::StrType(4));
+        assert_eq!(env.value().value_of(&raw).to_rc_str().as_ref(), "prod");
+        let hash = DerivedColumnDef::parse("h=raw_hash").unwrap().unwrap();
+        assert_eq!(
+            hash.value().value_of(&raw).to_rc_str().as_ref(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let file = DerivedColumnDef::parse("file=input_file").unwrap().unwrap();
+        assert_eq!(file.value().value_of(&raw).as_ref(), &ParsedValue::NullVal);
+        let file_raw = RawMessage::new(String::from("abc")).with_source(Some(Arc::from("a.log")));
+        assert_eq!(
+            file.value().value_of(&file_raw).to_rc_str().as_ref(),
+            "a.log"
+        );
+        assert!(DerivedColumnDef::parse("+ts:ts:%Y=%m").unwrap().is_none());
+        assert!(DerivedColumnDef::parse("message").unwrap().is_none());
+        assert!(DerivedColumnDef::parse("x=whatever").is_err());
+        assert!(DerivedColumnDef::parse("recv=received_at").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::Duration;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// The duration unit suffixes and their length in nanoseconds
+const DURATION_UNITS: [(&str, i64); 8] = [
+    ("ns", 1),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ms", 1_000_000),
+    ("s", NANOS_PER_SEC),
+    ("m", 60 * NANOS_PER_SEC),
+    ("h", 3600 * NANOS_PER_SEC),
+    ("d", 86400 * NANOS_PER_SEC),
+];
+
+/// The default unit of the duration values without one
+pub const DEFAULT_DURATION_UNIT_NANOS: i64 = NANOS_PER_SEC;
+
+pub fn duration_unit_nanos(unit: &str) -> Option<i64> {
+    DURATION_UNITS
+        .iter()
+        .find(|(u, _)| *u == unit)
+        .map(|(_, nanos)| *nanos)
+}
+
+fn to_nanos(n: f64, unit_nanos: i64) -> Option<i64> {
+    let nanos = (n * unit_nanos as f64).round();
+    if nanos.is_finite() && nanos.abs() < i64::MAX as f64 {
+        Some(nanos as i64)
+    } else {
+        None
+    }
+}
+
+/// [hh:]mm:ss[.fff]
+fn parse_clock(s: &str) -> Option<i64> {
+    let parts = s.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return None;
+    }
+    let (secs, mins) = parts.split_last()?;
+    if !secs.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    let secs = secs.parse::<f64>().ok().filter(|&s| s < 60.0)?;
+    let mut total = 0i64;
+    for (ix, &m) in mins.iter().enumerate() {
+        if m.is_empty() || !m.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let v = m.parse::<i64>().ok()?;
+        // the first (hours or minutes) part is not limited
+        if ix > 0 && v >= 60 {
+            return None;
+        }
+        total = total.checked_mul(60)?.checked_add(v)?;
+    }
+    to_nanos(total as f64 * 60.0 + secs, NANOS_PER_SEC)
+}
+
+/// A sequence of numbers with units, e.g. 1h30m or 1.5s
+fn parse_with_units(s: &str) -> Option<i64> {
+    let mut rest = s;
+    let mut total = 0i64;
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        if num_len == 0 {
+            return None;
+        }
+        let n = rest[..num_len].parse::<f64>().ok()?;
+        rest = rest[num_len..].trim_start();
+        let unit_len = rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(rest.len());
+        let unit_nanos = duration_unit_nanos(&rest[..unit_len])?;
+        total = total.checked_add(to_nanos(n, unit_nanos)?)?;
+        rest = rest[unit_len..].trim_start();
+    }
+    Some(total)
+}
+
+/// Parse a duration given with units (12ms, 1.5s, 1h30m), as a clock value (00:01:02.300)
+/// or as a plain number in the default unit (given in nanoseconds)
+pub fn parse_duration(s: &str, default_unit_nanos: i64) -> Option<Duration> {
+    let s = s.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    if s.is_empty() || s.starts_with(['-', '+']) {
+        return None;
+    }
+    let nanos = if s.contains(':') {
+        parse_clock(s)?
+    } else if s.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        to_nanos(s.parse::<f64>().ok()?, default_unit_nanos)?
+    } else {
+        parse_with_units(s)?
+    };
+    Some(Duration::nanoseconds(if negative { -nanos } else { nanos }))
+}
+
+/// n / unit with up to all the significant fraction digits, e.g. 1.5
+fn format_fraction(n: i64, unit: i64) -> String {
+    let (whole, rem) = (n / unit, n % unit);
+    if rem == 0 {
+        return whole.to_string();
+    }
+    let width = unit.to_string().len() - 1;
+    let frac = format!("{:0width$}", rem, width = width);
+    format!("{}.{}", whole, frac.trim_end_matches('0'))
+}
+
+/// The same form as parsed, e.g. 1h2m3.5s, 1.5s or 12ms
+pub fn format_duration(d: &Duration) -> String {
+    let nanos = match d.num_nanoseconds() {
+        Some(nanos) => nanos,
+        None => return format!("{}s", d.num_seconds()),
+    };
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let sign = if nanos < 0 { "-" } else { "" };
+    let abs = nanos.unsigned_abs() as i64;
+    if abs < NANOS_PER_SEC {
+        let (unit, name) = if abs >= 1_000_000 {
+            (1_000_000, "ms")
+        } else if abs >= 1_000 {
+            (1_000, "us")
+        } else {
+            (1, "ns")
+        };
+        return format!("{}{}{}", sign, format_fraction(abs, unit), name);
+    }
+    let hours = abs / (3600 * NANOS_PER_SEC);
+    let mins = abs / (60 * NANOS_PER_SEC) % 60;
+    let secs = abs % (60 * NANOS_PER_SEC);
+    let mut ret = sign.to_string();
+    if hours > 0 {
+        ret.push_str(&format!("{}h", hours));
+    }
+    if mins > 0 {
+        ret.push_str(&format!("{}m", mins));
+    }
+    if secs > 0 {
+        ret.push_str(&format!("{}s", format_fraction(secs, NANOS_PER_SEC)));
+    }
+    ret
+}
+
+/// The duration in (fractional) seconds, as output to SQL
+pub fn duration_secs(d: &Duration) -> f64 {
+    match d.num_nanoseconds() {
+        Some(nanos) => nanos as f64 / NANOS_PER_SEC as f64,
+        None => d.num_milliseconds() as f64 / 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_works() {
+        let ms = |s| parse_duration(s, DEFAULT_DURATION_UNIT_NANOS).map(|d| d.num_milliseconds());
+        assert_eq!(ms("12ms"), Some(12));
+        assert_eq!(ms("1.5s"), Some(1500));
+        assert_eq!(ms("1.5 s"), Some(1500));
+        assert_eq!(ms("00:01:02.300"), Some(62300));
+        assert_eq!(ms("01:02"), Some(62000));
+        assert_eq!(ms("25:00:00"), Some(90_000_000));
+        assert_eq!(ms("1h30m"), Some(5_400_000));
+        assert_eq!(ms("-2m"), Some(-120_000));
+        assert_eq!(ms("2"), Some(2000));
+        assert_eq!(
+            parse_duration("2", 1_000_000).map(|d| d.num_milliseconds()),
+            Some(2)
+        );
+        assert_eq!(
+            parse_duration("250µs", 1).unwrap().num_microseconds(),
+            Some(250)
+        );
+        for invalid in [
+            "",
+            "ms",
+            "12 parsecs",
+            "1:60:00",
+            "1:2:3:4",
+            "nan",
+            "inf",
+            "--1s",
+            "1e3",
+        ] {
+            assert!(ms(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn format_duration_works() {
+        for (s, formatted) in [
+            ("12ms", "12ms"),
+            ("1.5ms", "1.5ms"),
+            ("250us", "250us"),
+            ("0", "0s"),
+            ("1.5s", "1.5s"),
+            ("00:01:02.300", "1m2.3s"),
+            ("2h0m5s", "2h5s"),
+            ("-90s", "-1m30s"),
+        ] {
+            let d = parse_duration(s, DEFAULT_DURATION_UNIT_NANOS).unwrap();
+            assert_eq!(format_duration(&d), formatted);
+            assert_eq!(
+                parse_duration(formatted, DEFAULT_DURATION_UNIT_NANOS),
+                Some(d)
+            );
+        }
+        assert_eq!(duration_secs(&Duration::milliseconds(1500)), 1.5);
+    }
+}
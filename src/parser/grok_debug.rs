@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use grok::Grok;
+use regex::Regex;
+
+use crate::parser::grok_parser::GrokSchema;
+use crate::parser::parser::*;
+use crate::DynError;
+
+/// A %{NAME}, %{NAME:alias} or %{NAME:alias=definition} grok pattern reference
+const GROK_REF_PATTERN: &str = r"%\{(?P<name>[A-Za-z0-9_]+)(?::(?P<alias>[A-Za-z0-9_:;/\s\.]+))?(?:=(?P<definition>[^{}]+))?\}";
+/// A regex quantifier following a pattern reference, e.g. %{INT}? or %{WORD}{1,3}
+const QUANTIFIER_PATTERN: &str = r"^(?:[?*+]|\{\d+(?:,\d*)?\})[?+]?";
+
+const PREFIX_CAPTURE: &str = "hustlog_prefix";
+const MAX_EXPAND_DEPTH: usize = 64;
+const MAX_EXPLAIN_DEPTH: usize = 32;
+
+/// Explains how the grok patterns of a schema match (or fail to match) sample lines:
+/// the fully expanded regex, and the captured values converted to the column types, or
+/// the longest matching prefix and the (nested) sub-pattern which failed to match after it
+pub struct GrokDebugger {
+    schema: GrokSchema,
+    grok: Grok,
+    definitions: HashMap<String, String>,
+    ref_regex: Regex,
+    quantifier_regex: Regex,
+}
+
+impl GrokDebugger {
+    pub fn new(schema: GrokSchema) -> Result<GrokDebugger, DynError> {
+        let mut grok = Grok::empty();
+        let mut definitions = HashMap::new();
+        for (n, p) in schema.grok_definitions() {
+            grok.insert_definition(n.as_str(), p.as_str());
+            definitions.insert(n, p);
+        }
+        Ok(GrokDebugger {
+            schema,
+            grok,
+            definitions,
+            ref_regex: Regex::new(GROK_REF_PATTERN)?,
+            quantifier_regex: Regex::new(QUANTIFIER_PATTERN)?,
+        })
+    }
+
+    /// A multi-line explanation of how the patterns match the message, the fallback
+    /// patterns are explained until one of them matches with all the required columns
+    pub fn explain(&mut self, msg: &RawMessage) -> String {
+        let mut out = vec![format!("LINE: {:?}", msg.as_str())];
+        let pattern_names = self
+            .schema
+            .pattern_names()
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        for pat_name in pattern_names {
+            if self.explain_pattern(&pat_name, msg, &mut out) {
+                break;
+            }
+        }
+        out.push(String::new());
+        out.join("\n")
+    }
+
+    fn explain_pattern(&mut self, pat_name: &str, msg: &RawMessage, out: &mut Vec<String>) -> bool {
+        let top = format!("%{{{}}}", pat_name);
+        out.push(format!("PATTERN: {}", pat_name));
+        match self.expand(&top, &mut self.definitions.clone(), 0) {
+            Ok(re) => out.push(format!("  REGEX: {}", re)),
+            Err(err) => {
+                out.push(format!("  ERROR: {}", err));
+                return false;
+            }
+        }
+        let pattern = match self.grok.compile(&top, self.schema.grok_with_alias_only()) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                out.push(format!("  ERROR: {}", err));
+                return false;
+            }
+        };
+        let line = msg.as_str();
+        let m = match pattern.match_against(line) {
+            Some(m) => m,
+            None => {
+                out.push("  NOT MATCHED".to_string());
+                self.explain_failure(&top, line, out);
+                return false;
+            }
+        };
+        out.push("  MATCHED".to_string());
+        let mut captures = m.iter().filter(|(_, v)| !v.is_empty()).collect::<Vec<_>>();
+        captures.sort();
+        for (name, v) in captures {
+            out.push(format!("  CAPTURE {} = {:?}", name, v));
+        }
+        let mut rejected = false;
+        for c in self.schema.columns() {
+            let found = c
+                .lookup_names()
+                .iter()
+                .find_map(|lnm| m.get(lnm).filter(|v| !v.is_empty()));
+            let desc = match found {
+                Some(v) => match c.str2val(v, msg.ref_time()) {
+                    Ok(Some(pv)) => format!("{:?} -> {:?}", v, pv),
+                    Ok(None) => format!("{:?} -> conversion to {:?} failed", v, c.col_type()),
+                    Err(err) => {
+                        rejected = true;
+                        format!("{:?} -> rejected: {}", v, err)
+                    }
+                },
+                None if c.required() => {
+                    rejected = true;
+                    "not captured (required)".to_string()
+                }
+                None => "not captured".to_string(),
+            };
+            out.push(format!("  COLUMN {}: {}", c.col_name(), desc));
+        }
+        // the parser tries the next pattern in this case
+        !rejected
+    }
+
+    /// Fully expand the pattern references, the same way grok does
+    /// (but with the aliases as the capture names)
+    fn expand(
+        &self,
+        pattern: &str,
+        definitions: &mut HashMap<String, String>,
+        depth: usize,
+    ) -> Result<String, String> {
+        if depth > MAX_EXPAND_DEPTH {
+            return Err("Grok pattern recursion is too deep".to_string());
+        }
+        let mut ret = String::with_capacity(pattern.len());
+        let mut last = 0;
+        for caps in self.ref_regex.captures_iter(pattern) {
+            let m = caps.get(0).unwrap();
+            ret.push_str(&pattern[last..m.start()]);
+            last = m.end();
+            let name = &caps["name"];
+            if let Some(definition) = caps.name("definition") {
+                definitions.insert(name.to_string(), definition.as_str().to_string());
+            }
+            let definition = match definitions.get(name) {
+                Some(definition) => definition.clone(),
+                None => return Err(format!("Grok pattern definition not found: {}", name)),
+            };
+            let expanded = self.expand(&definition, definitions, depth + 1)?;
+            match caps.name("alias") {
+                None if self.schema.grok_with_alias_only() => {
+                    ret.push_str(&format!("(?:{})", expanded))
+                }
+                alias => ret.push_str(&format!(
+                    "(?<{}>{})",
+                    alias.map(|a| a.as_str()).unwrap_or(name),
+                    expanded
+                )),
+            }
+        }
+        ret.push_str(&pattern[last..]);
+        Ok(ret)
+    }
+
+    /// Split a pattern into its top level parts - the (possibly quantified) pattern
+    /// references and the regex text between them. Returns None for patterns with a
+    /// top level alternation, as these can not be matched part by part.
+    fn split_top_level(&self, pattern: &str) -> Option<Vec<String>> {
+        let mut parts = Vec::new();
+        let mut cur = String::new();
+        let mut depth = 0;
+        let mut in_class = false;
+        let mut chars = pattern.char_indices();
+        while let Some((ix, c)) = chars.next() {
+            if c == '\\' {
+                cur.push(c);
+                if let Some((_, esc)) = chars.next() {
+                    cur.push(esc);
+                }
+                continue;
+            }
+            if in_class {
+                in_class = c != ']';
+                cur.push(c);
+                continue;
+            }
+            match c {
+                '[' => in_class = true,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '|' if depth == 0 => return None,
+                '%' if depth == 0 => {
+                    if let Some(m) = self
+                        .ref_regex
+                        .find_at(pattern, ix)
+                        .filter(|m| m.start() == ix)
+                    {
+                        let q_len = self
+                            .quantifier_regex
+                            .find(&pattern[m.end()..])
+                            .map(|q| q.end())
+                            .unwrap_or(0);
+                        if !cur.is_empty() {
+                            parts.push(std::mem::take(&mut cur));
+                        }
+                        let end = m.end() + q_len;
+                        parts.push(pattern[ix..end].to_string());
+                        // skip the rest of the reference
+                        while chars.clone().next().map(|(i, _)| i < end).unwrap_or(false) {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            cur.push(c);
+        }
+        if !cur.is_empty() {
+            parts.push(cur);
+        }
+        Some(parts)
+    }
+
+    /// The number of leading parts matching at the start of text and the matched length
+    fn longest_matching_parts(&mut self, parts: &[String], text: &str) -> (usize, usize) {
+        let mut ret = (0, 0);
+        for k in 1..=parts.len() {
+            let prefix = format!("^(?<{}>{})", PREFIX_CAPTURE, parts[..k].concat());
+            let matched_len = self.grok.compile(&prefix, false).ok().and_then(|p| {
+                p.match_against(text)
+                    .and_then(|m| m.get(PREFIX_CAPTURE).map(|s| s.len()))
+            });
+            match matched_len {
+                Some(len) => ret = (k, len),
+                None => break,
+            }
+        }
+        ret
+    }
+
+    /// Find the longest matching prefix of the line, descending into the
+    /// definition of the first sub-pattern which failed to match after it
+    fn explain_failure(&mut self, top: &str, line: &str, out: &mut Vec<String>) {
+        let mut prefix_len = 0;
+        let mut path = Vec::new();
+        let mut pattern = top.to_string();
+        for _ in 0..MAX_EXPLAIN_DEPTH {
+            let parts = match self.split_top_level(&pattern) {
+                Some(parts) => parts,
+                None => break,
+            };
+            let (matched_parts, matched_len) =
+                self.longest_matching_parts(&parts, &line[prefix_len..]);
+            if matched_parts == parts.len() {
+                break;
+            }
+            prefix_len += matched_len;
+            let failed = &parts[matched_parts];
+            path.push(failed.clone());
+            let definition = self
+                .ref_regex
+                .captures(failed)
+                .filter(|caps| caps.get(0).unwrap().start() == 0)
+                .and_then(|caps| self.definitions.get(&caps["name"]).cloned());
+            match definition {
+                Some(definition) => pattern = definition,
+                None => break,
+            }
+        }
+        out.push(format!(
+            "  LONGEST MATCHING PREFIX: {:?}",
+            &line[..prefix_len]
+        ));
+        if !path.is_empty() {
+            out.push(format!("  FAILED AT: {}", path.join(" > ")));
+        }
+        out.push(format!("  REMAINING: {:?}", &line[prefix_len..]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::GrokColumnDef;
+    use std::sync::Arc;
+
+    fn test_debugger() -> GrokDebugger {
+        let schema = GrokSchema::new(
+            String::from("MYLINE"),
+            vec![GrokColumnDef::new(
+                Arc::from("num"),
+                ParsedValueType::LongType,
+                vec![Arc::new(String::from("num"))],
+                true,
+            )],
+            false,
+            vec![
+                (String::from("DIGITS"), String::from(r"\d+")),
+                (String::from("WORDS"), String::from(r"[a-z]+")),
+                (
+                    String::from("PAIR"),
+                    String::from(r"%{WORDS:key}=%{DIGITS:num}"),
+                ),
+                (
+                    String::from("MYLINE"),
+                    String::from(r"%{WORDS:level}: %{PAIR}( %{WORDS})?"),
+                ),
+            ],
+            false,
+            String::from("debug"),
+        );
+        GrokDebugger::new(schema).unwrap()
+    }
+
+    #[test]
+    fn grok_debugger_split_works() {
+        let dbg = test_debugger();
+        assert_eq!(
+            dbg.split_top_level(r"%{WORDS:level}: [%{}]+ %{PAIR}? (%{WORDS}|x)")
+                .unwrap(),
+            vec!["%{WORDS:level}", ": [%{}]+ ", "%{PAIR}?", " (%{WORDS}|x)"]
+        );
+        assert!(dbg.split_top_level("%{WORDS}|%{DIGITS}").is_none());
+    }
+
+    #[test]
+    fn grok_debugger_explain_works() {
+        let mut dbg = test_debugger();
+        let matched = dbg.explain(&RawMessage::new(String::from("info: count=42 done")));
+        assert!(matched.contains(r"REGEX: (?<MYLINE>(?<level>[a-z]+): (?<PAIR>(?<key>[a-z]+)=(?<num>\d+))( (?<WORDS>[a-z]+))?)"));
+        assert!(matched.lines().any(|ln| ln == "  MATCHED"), "{}", matched);
+        assert!(!matched.contains("NOT MATCHED"));
+        assert!(matched.contains(r#"COLUMN num: "42" -> LongVal(42)"#));
+
+        let failed = dbg.explain(&RawMessage::new(String::from("info: count=many")));
+        assert!(failed.lines().any(|ln| ln == "  NOT MATCHED"), "{}", failed);
+        assert!(failed.contains(r#"LONGEST MATCHING PREFIX: "info: count=""#));
+        assert!(failed.contains("FAILED AT: %{MYLINE} > %{PAIR} > %{DIGITS:num}"));
+        assert!(failed.contains(r#"REMAINING: "many""#));
+    }
+}
@@ -3,19 +3,54 @@
 extern crate grok;
 
 use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use crate::DynError;
+use chrono::{DateTime, Utc};
 use grok::{patterns, Grok, Pattern};
+use log::warn;
 
+use crate::parser::derived_column::DerivedColumnDef;
+use crate::parser::kv_extract::KvExtractor;
 use crate::parser::parser::*;
 use crate::parser::schema::{ParserColDef, ParserSchema};
 
+/// The synthetic column recording which of the grok patterns matched a line,
+/// present when fallback patterns are configured
+pub const GROK_PATTERN_COLUMN: &str = "_pattern";
+
+/// How many values exceeded the max length of a str column and the longest one,
+/// shared by the clones of the column def (i.e. the parser instances)
+#[derive(Debug, Default)]
+pub struct StrOverflowStats {
+    count: AtomicU64,
+    max_len: AtomicUsize,
+}
+
+impl StrOverflowStats {
+    fn add(&self, len: usize) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max_len.fetch_max(len, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len.load(Ordering::Relaxed)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GrokColumnDef {
     pcd: ParserColDef,
     lookup_names: Vec<Arc<String>>,
     required: bool,
+    str_overflow: StrOverflowPolicy,
+    str_overflow_stats: Arc<StrOverflowStats>,
 }
 
 impl GrokColumnDef {
@@ -26,18 +61,33 @@ impl GrokColumnDef {
         required: bool,
     ) -> GrokColumnDef {
         let pcd = ParserColDef::new(col_name.as_ref(), &col_type, required);
+        // oversize values used to reject the required and nullify the optional columns
+        let str_overflow = if required {
+            StrOverflowPolicy::REJECT
+        } else {
+            StrOverflowPolicy::NULL
+        };
         Self {
             pcd,
             lookup_names,
             required,
+            str_overflow,
+            str_overflow_stats: Arc::new(StrOverflowStats::default()),
         }
     }
 
+    pub fn with_str_overflow(mut self, policy: StrOverflowPolicy) -> GrokColumnDef {
+        self.str_overflow = policy;
+        self
+    }
+
     pub fn clone(&self) -> GrokColumnDef {
         GrokColumnDef {
             pcd: self.pcd.clone(),
             lookup_names: self.lookup_names.iter().map(|s| s.clone()).collect(),
             required: self.required,
+            str_overflow: self.str_overflow,
+            str_overflow_stats: self.str_overflow_stats.clone(),
         }
     }
 
@@ -50,16 +100,61 @@ impl GrokColumnDef {
     }
 
     pub fn required(&self) -> bool { self.required }
+
+    pub fn lookup_names(&self) -> &Vec<Arc<String>> {
+        &self.lookup_names
+    }
+
+    pub fn str_overflow(&self) -> StrOverflowPolicy {
+        self.str_overflow
+    }
+
+    pub fn str_overflow_stats(&self) -> &StrOverflowStats {
+        &self.str_overflow_stats
+    }
+
+    /// Convert a value to the column type, applying the oversize policy to str values
+    /// longer than the column max length. Returns the error description if the policy
+    /// rejects the message, Ok(None) if the value can not be converted.
+    pub fn str2val(
+        &self,
+        s: &str,
+        ref_time: Option<&DateTime<Utc>>,
+    ) -> Result<Option<Arc<ParsedValue>>, String> {
+        let max_len = match self.col_type() {
+            ParsedValueType::StrType(max_len) if s.len() > *max_len => *max_len,
+            ctype => return Ok(str2val_ref(s, ctype, ref_time)),
+        };
+        self.str_overflow_stats.add(s.len());
+        let marker = match self.str_overflow {
+            StrOverflowPolicy::REJECT => {
+                return Err(format!(
+                    "Value of column {} exceeds max length {}: {} bytes",
+                    self.col_name(),
+                    max_len,
+                    s.len()
+                ))
+            }
+            StrOverflowPolicy::NULL => return Ok(Some(arc_null_pv())),
+            StrOverflowPolicy::TRUNCATE => "",
+            StrOverflowPolicy::MARKER => STR_TRUNCATION_MARKER,
+        };
+        let truncated = truncate_str(s, max_len, marker);
+        Ok(Some(Arc::new(ParsedValue::StrVal(Arc::new(truncated)))))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct GrokSchema {
     pattern: String,
+    fallback_patterns: Vec<String>,
     columns: Vec<GrokColumnDef>,
     load_default: bool,
     extra_patterns: Vec<(String, String)>,
     grok_with_alias_only: bool,
     output_name: String,
+    derived_columns: Vec<DerivedColumnDef>,
+    kv_extractors: Vec<KvExtractor>,
 }
 
 impl GrokSchema {
@@ -73,17 +168,177 @@ impl GrokSchema {
     ) -> GrokSchema {
         Self {
             pattern,
+            fallback_patterns: Vec::new(),
             columns,
             load_default,
             extra_patterns,
             grok_with_alias_only,
             output_name,
+            derived_columns: Vec::new(),
+            kv_extractors: Vec::new(),
         }
     }
 
+    /// Set the computed/constant columns, these follow the parsed ones
+    pub fn with_derived_columns(mut self, derived_columns: Vec<DerivedColumnDef>) -> GrokSchema {
+        self.derived_columns = derived_columns;
+        self
+    }
+
+    pub fn derived_columns(&self) -> &Vec<DerivedColumnDef> {
+        &self.derived_columns
+    }
+
+    /// Set the fields to extract key/value pairs from, after parsing
+    pub fn with_kv_extractors(mut self, kv_extractors: Vec<KvExtractor>) -> GrokSchema {
+        self.kv_extractors = kv_extractors;
+        self
+    }
+
+    /// Set the patterns to try in turn when the main pattern does not match a line.
+    /// This also adds the (optional) GROK_PATTERN_COLUMN to the schema
+    pub fn with_fallback_patterns(mut self, fallback_patterns: Vec<String>) -> GrokSchema {
+        let has_pattern_col = self
+            .columns
+            .iter()
+            .any(|c| c.col_name().as_ref() == GROK_PATTERN_COLUMN);
+        if !fallback_patterns.is_empty() && !has_pattern_col {
+            self.columns.push(GrokColumnDef::new(
+                Arc::from(GROK_PATTERN_COLUMN),
+                ParsedValueType::StrType(256),
+                vec![Arc::new(GROK_PATTERN_COLUMN.to_string())],
+                false,
+            ));
+        }
+        self.fallback_patterns = fallback_patterns;
+        self
+    }
+
+    /// The named grok patterns available to the pattern - the default ones
+    /// (unless ignored) followed by the extra ones
+    pub fn grok_definitions(&self) -> Vec<(String, String)> {
+        let defaults = if self.load_default { patterns() } else { &[] };
+        defaults
+            .iter()
+            .map(|&(n, p)| (n.to_string(), p.to_string()))
+            .chain(self.extra_patterns.iter().cloned())
+            .collect()
+    }
+
+    /// The main pattern followed by the fallback ones
+    pub fn pattern_names(&self) -> Vec<&String> {
+        std::iter::once(&self.pattern)
+            .chain(self.fallback_patterns.iter())
+            .collect()
+    }
+
+    pub fn grok_with_alias_only(&self) -> bool {
+        self.grok_with_alias_only
+    }
+
+    pub fn with_grok_alias_only(mut self, grok_with_alias_only: bool) -> GrokSchema {
+        self.grok_with_alias_only = grok_with_alias_only;
+        self
+    }
+
     pub fn columns(&self) -> &Vec<GrokColumnDef> {
         &self.columns
     }
+
+    /// Log how many values exceeded the max length of each str column (if any did),
+    /// with the policy applied and the longest value, e.g. to resize the DB columns
+    pub fn log_str_overflow_stats(&self) {
+        for c in &self.columns {
+            let stats = c.str_overflow_stats();
+            if let ParsedValueType::StrType(max_len) = c.col_type() {
+                if stats.count() > 0 {
+                    warn!(
+                        "{}.{}: {} values exceeded max length {} ({}), the longest was {} bytes",
+                        self.output_name,
+                        c.col_name(),
+                        stats.count(),
+                        max_len,
+                        c.str_overflow().name(),
+                        stats.max_len()
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Build the parsed data for a message, using the lookup function to
+    /// resolve a (column, lookup name) pair to a typed value. Lookup names
+    /// are tried in order and the first successfully converted value wins.
+    /// Names not resolved by the lookup function fall back to the extracted
+    /// key/value pairs (<field>.<key>, also kept as the dynamic fields), the built-in
+    /// syslog priority columns (if the message had a <PRI> prefix) and the
+    /// message source metadata ones (_remote_addr, _received_at etc.).
+    /// Returns the error description if a required column is missing
+    /// or the lookup function rejects the message.
+    pub fn lookup_parsed_data<F>(
+        &self,
+        msg: &RawMessage,
+        mut lookup: F,
+    ) -> Result<ParsedData, String>
+    where
+        F: FnMut(&GrokColumnDef, &str) -> Result<Option<Arc<ParsedValue>>, String>,
+    {
+        let mut kv_fields: HashMap<Arc<str>, Arc<ParsedValue>> = HashMap::new();
+        for kve in &self.kv_extractors {
+            if let Some(pv) = lookup(kve.source_col(), kve.field())? {
+                if let ParsedValue::StrVal(s) = pv.as_ref() {
+                    for (k, v) in kve.split_pairs(s) {
+                        // the first one of repeated keys is used
+                        kv_fields
+                            .entry(Arc::from(kve.sub_field_name(&k)))
+                            .or_insert_with(|| Arc::new(ParsedValue::StrVal(Arc::new(v))));
+                    }
+                }
+            }
+        }
+        let mut hm: HashMap<Arc<str>, Arc<ParsedValue>> = HashMap::new();
+        for c in &self.columns {
+            let mut found = false;
+            for lnm in &c.lookup_names {
+                let mut pv_opt = lookup(c, lnm.as_str())?;
+                if pv_opt.is_none() {
+                    if let Some(ParsedValue::StrVal(v)) =
+                        kv_fields.get(lnm.as_str()).map(|v| v.as_ref())
+                    {
+                        pv_opt = c.str2val(v, msg.ref_time())?;
+                    }
+                }
+                let pv_opt = pv_opt
+                    .or_else(|| {
+                        msg.pri()
+                            .and_then(|pri| pri.column_value(lnm.as_str(), c.col_type()))
+                    })
+                    .or_else(|| msg.meta_column_value(lnm.as_str(), c.col_type()));
+                if let Some(pv) = pv_opt {
+                    hm.insert(c.col_name().clone(), pv);
+                    found = true;
+                    break;
+                }
+            }
+            if c.required && !found {
+                return Err(format!(
+                    "Required field not found: {} RAW: {}",
+                    c.pcd.name(),
+                    msg.as_str()
+                ));
+            }
+        }
+        let dynamic = if kv_fields.is_empty() {
+            None
+        } else {
+            Some(Arc::new(kv_fields))
+        };
+        Ok(ParsedData::new(hm).with_dynamic_fields(dynamic))
+    }
 }
 
 impl ParserSchema for GrokSchema {
@@ -92,13 +347,17 @@ impl ParserSchema for GrokSchema {
     }
 
     fn col_defs(&self) -> Vec<&ParserColDef> {
-        self.columns.iter().map(|x| &x.pcd).collect::<Vec<_>>()
+        self.columns
+            .iter()
+            .map(|x| &x.pcd)
+            .chain(self.derived_columns.iter().map(|x| x.pcd()))
+            .collect::<Vec<_>>()
     }
 }
 
 pub struct GrokParser {
     schema: GrokSchema,
-    pattern: Pattern,
+    patterns: Vec<(Arc<ParsedValue>, Pattern)>,
 }
 
 impl GrokParser {
@@ -111,11 +370,18 @@ impl GrokParser {
         for (n, p) in &schema.extra_patterns {
             grok.insert_definition(n, p)
         }
-        let pattern = grok.compile(
-            format!("%{{{}}}", schema.pattern.as_str()).as_str(),
-            schema.grok_with_alias_only,
-        )?;
-        Result::Ok(GrokParser { schema, pattern })
+        let mut patterns = Vec::new();
+        for pat_name in schema.pattern_names() {
+            let pattern = grok.compile(
+                format!("%{{{}}}", pat_name.as_str()).as_str(),
+                schema.grok_with_alias_only,
+            )?;
+            patterns.push((
+                Arc::new(ParsedValue::StrVal(Arc::new(pat_name.clone()))),
+                pattern,
+            ));
+        }
+        Result::Ok(GrokParser { schema, patterns })
     }
 
     pub fn default_patterns() -> Vec<(String, String)> {
@@ -125,6 +391,24 @@ impl GrokParser {
             .collect()
     }
 
+    /// The (name, position in the line) of the non-empty named captures
+    /// of the first pattern matching the line
+    pub fn named_captures(&self, line: &str) -> Option<Vec<(String, Range<usize>)>> {
+        let m = self
+            .patterns
+            .iter()
+            .find_map(|(_, pattern)| pattern.match_against(line))?;
+        let captures = m
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(name, v)| {
+                let start = v.as_ptr() as usize - line.as_ptr() as usize;
+                (name.to_string(), start..start + v.len())
+            })
+            .collect();
+        Some(captures)
+    }
+
     // pub fn get_schema(&self) -> &GrokSchema {
     //     &self.schema
     // }
@@ -132,37 +416,27 @@ impl GrokParser {
 
 impl LogParser for GrokParser {
     fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError> {
-        let mopt = self.pattern.match_against(msg.as_str());
-        if mopt.is_some() {
-            let m = mopt.unwrap();
-            let mut hm: HashMap<Arc<str>, Arc<ParsedValue>> = HashMap::new();
-            for c in &self.schema.columns {
-                let mut found = false;
-                for lnm in &c.lookup_names {
-                    let mm = m.get(lnm.as_str());
-                    if mm.is_some() {
-                        let opv: Option<Arc<ParsedValue>> = str2val(mm.unwrap(), c.col_type());
-                        if opv.is_some() {
-                            hm.insert(Arc::from(lnm.as_str()), opv.unwrap());
-                            found = true;
-                            break;
-                        }
-                    }
-                }
-                if c.required && !found {
-                    return Err(LogParseError::from_string(
-                        format!(
-                            "Required field not found: {} RAW: {}",
-                            c.pcd.name(),
-                            msg.as_str()
-                        ),
-                        msg,
-                    ));
+        // the patterns are tried in order, the first one which matches and
+        // provides all the required fields wins
+        let mut last_err: Option<String> = None;
+        for (pat_name, pattern) in &self.patterns {
+            if let Some(m) = pattern.match_against(msg.as_str()) {
+                let parsed = self
+                    .schema
+                    .lookup_parsed_data(&msg, |c, lnm| match m.get(lnm) {
+                        Some(s) => c.str2val(s, msg.ref_time()),
+                        None if lnm == GROK_PATTERN_COLUMN => Ok(Some(pat_name.clone())),
+                        None => Ok(None),
+                    });
+                match parsed {
+                    Ok(pd) => return Ok(ParsedMessage::new(msg, pd)),
+                    Err(desc) => last_err = Some(desc),
                 }
             }
-            Ok(ParsedMessage::new(msg, ParsedData::new(hm)))
-        } else {
-            Err(LogParseError::new("GROK pattern did not match", msg))
+        }
+        match last_err {
+            Some(desc) => Err(LogParseError::from_string(desc, msg)),
+            None => Err(LogParseError::new("GROK pattern did not match", msg)),
         }
     }
 }
@@ -171,9 +445,20 @@ impl LogParser for GrokParser {
 pub mod tests {
     use super::*;
 
+    /// A column looked up by its own name, e.g. a JSON path or a logfmt key
+    pub fn test_col_def(name: &str, ctype: ParsedValueType, required: bool) -> GrokColumnDef {
+        GrokColumnDef::new(
+            Arc::from(name),
+            ctype,
+            vec![Arc::new(String::from(name))],
+            required,
+        )
+    }
+
     pub fn test_syslog_schema() -> GrokSchema {
         GrokSchema {
             pattern: String::from("SYSLOGLINE"),
+            fallback_patterns: vec![],
             load_default: true,
             columns: vec![
                 GrokColumnDef::new(
@@ -210,12 +495,15 @@ pub mod tests {
             grok_with_alias_only: false,
             extra_patterns: vec![],
             output_name: String::from("SYSLOGLINE"),
+            derived_columns: vec![],
+            kv_extractors: vec![],
         }
     }
 
     pub fn test_dummy_schema() -> GrokSchema {
         GrokSchema {
             pattern: String::from("DUMMY"),
+            fallback_patterns: vec![],
             load_default: true,
             columns: vec![
                 // GrokColumnDef::new(
@@ -243,6 +531,8 @@ pub mod tests {
                 "%{NUMBER:num} +%{GREEDYDATA:message}".to_string(),
             )],
             output_name: String::from("DUMMY"),
+            derived_columns: vec![],
+            kv_extractors: vec![],
         }
     }
 
@@ -257,6 +547,7 @@ pub mod tests {
     fn parse_works() {
         let schema = GrokSchema {
             pattern: String::from("test_pat"),
+            fallback_patterns: vec![],
             load_default: false,
             columns: vec![
                 GrokColumnDef::new(
@@ -282,6 +573,8 @@ pub mod tests {
                 ),
             ],
             output_name: String::from("test_pat"),
+            derived_columns: vec![],
+            kv_extractors: vec![],
         };
         let parser = GrokParser::new(schema).unwrap();
         let lines = vec![
@@ -314,7 +607,147 @@ pub mod tests {
         let line_no_msg = "Apr 22 02:34:54 actek-mac";
         let raw_msg = RawMessage::new(String::from(line_no_msg));
         let parsed = parser.parse(raw_msg);
-        assert_eq!(parsed.as_ref().err().is_some(), true);
+        assert!(parsed.is_err());
         println!("{:?}", parsed)
     }
+
+    #[test]
+    fn parse_fallback_patterns_works() {
+        let schema = GrokSchema::new(
+            String::from("WITH_PID"),
+            vec![
+                GrokColumnDef::new(
+                    Arc::from("program"),
+                    ParsedValueType::StrType(256),
+                    vec![Arc::new(String::from("program"))],
+                    true,
+                ),
+                GrokColumnDef::new(
+                    Arc::from("pid"),
+                    ParsedValueType::LongType,
+                    vec![Arc::new(String::from("pid"))],
+                    false,
+                ),
+            ],
+            true,
+            vec![
+                (
+                    String::from("WITH_PID"),
+                    String::from("%{WORD:program}\\[%{NUMBER:pid}\\]: %{GREEDYDATA:message}"),
+                ),
+                (
+                    String::from("NO_PID"),
+                    String::from("%{WORD:program}: %{GREEDYDATA:message}"),
+                ),
+            ],
+            false,
+            String::from("test"),
+        )
+        .with_fallback_patterns(vec![String::from("NO_PID")]);
+        assert_eq!(
+            schema.columns().last().unwrap().col_name().as_ref(),
+            GROK_PATTERN_COLUMN
+        );
+        let parser = GrokParser::new(schema).unwrap();
+        let with_pid = parser
+            .parse(RawMessage::new(String::from("sshd[42]: login")))
+            .unwrap();
+        assert_eq!(
+            with_pid
+                .get_parsed()
+                .get_value(GROK_PATTERN_COLUMN)
+                .unwrap()
+                .as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("WITH_PID")))
+        );
+        assert_eq!(
+            with_pid.get_parsed().get_value("pid").unwrap().as_ref(),
+            &ParsedValue::LongVal(42)
+        );
+        let no_pid = parser
+            .parse(RawMessage::new(String::from("kernel: boot")))
+            .unwrap();
+        assert_eq!(
+            no_pid
+                .get_parsed()
+                .get_value(GROK_PATTERN_COLUMN)
+                .unwrap()
+                .as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("NO_PID")))
+        );
+        assert!(no_pid.get_parsed().get_value("pid").is_none());
+        assert!(parser
+            .parse(RawMessage::new(String::from("no match here")))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_syslog_pri_columns_works() {
+        let mut schema = test_syslog_schema();
+        schema.columns.push(GrokColumnDef::new(
+            Arc::from("severity"),
+            ParsedValueType::LongType,
+            vec![Arc::new(String::from("severity"))],
+            false,
+        ));
+        schema.columns.push(GrokColumnDef::new(
+            Arc::from("facility_name"),
+            ParsedValueType::StrType(16),
+            vec![Arc::new(String::from("facility_name"))],
+            false,
+        ));
+        let parser = GrokParser::new(schema).unwrap();
+        let line = "Apr 22 04:42:04 actek-mac syslogd[104]: ASL Sender Statistics";
+        let parsed = parser
+            .parse(RawMessage::with_pri(
+                line.to_string(),
+                SyslogPri::parse("27"),
+            ))
+            .unwrap();
+        assert_eq!(
+            parsed.get_parsed().get_value("severity").unwrap().as_ref(),
+            &ParsedValue::LongVal(3)
+        );
+        assert_eq!(
+            parsed
+                .get_parsed()
+                .get_value("facility_name")
+                .unwrap()
+                .as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("daemon")))
+        );
+        let no_pri = parser.parse(RawMessage::new(line.to_string())).unwrap();
+        assert!(no_pri.get_parsed().get_value("severity").is_none());
+    }
+
+    #[test]
+    fn str_overflow_policies_work() {
+        let col = |required: bool| test_col_def("msg", ParsedValueType::StrType(5), required);
+        let str_val = |s: &str| Some(Arc::new(ParsedValue::StrVal(Arc::new(s.to_string()))));
+        assert!(col(true).str2val("abcdefgh", None).is_err());
+        assert_eq!(
+            col(false).str2val("abcdefgh", None).unwrap(),
+            Some(arc_null_pv())
+        );
+        assert_eq!(col(true).str2val("abcde", None).unwrap(), str_val("abcde"));
+        let truncate = col(true).with_str_overflow(StrOverflowPolicy::TRUNCATE);
+        assert_eq!(
+            truncate.str2val("abcdefgh", None).unwrap(),
+            str_val("abcde")
+        );
+        // the clones share the stats
+        let marker = col(false).with_str_overflow(StrOverflowPolicy::MARKER);
+        assert_eq!(
+            marker.clone().str2val("abcdefgh", None).unwrap(),
+            str_val("ab...")
+        );
+        assert_eq!(
+            marker.str2val("abcdefghij", None).unwrap(),
+            str_val("ab...")
+        );
+        assert_eq!(marker.str_overflow_stats().count(), 2);
+        assert_eq!(marker.str_overflow_stats().max_len(), 10);
+        let reject = col(false).with_str_overflow(StrOverflowPolicy::REJECT);
+        assert!(reject.str2val("abcdefgh", None).is_err());
+    }
 }
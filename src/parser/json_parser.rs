@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::parser::grok_parser::{GrokColumnDef, GrokSchema};
+use crate::parser::parser::*;
+
+/// A parser for JSON-lines input (one JSON object per line). The schema columns
+/// lookup names are treated as dot-separated paths into the object, e.g. `http.status`
+/// or `user.id`. Array elements can be addressed by index, e.g. `tags.0`
+pub struct JsonParser {
+    schema: GrokSchema,
+}
+
+impl JsonParser {
+    pub fn new(schema: GrokSchema) -> JsonParser {
+        JsonParser { schema }
+    }
+}
+
+fn lookup_path<'a>(root: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(root, |v, key| match v {
+        Value::Object(m) => m.get(key),
+        Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    })
+}
+
+fn json2val(
+    v: &Value,
+    c: &GrokColumnDef,
+    ref_time: Option<&DateTime<Utc>>,
+) -> Result<Option<Arc<ParsedValue>>, String> {
+    match (v, c.col_type()) {
+        // a null is a missing value for required columns
+        (Value::Null, _) => {
+            if c.required() {
+                Ok(None)
+            } else {
+                Ok(Some(arc_null_pv()))
+            }
+        }
+        (Value::Bool(b), ParsedValueType::BoolType) => Ok(Some(Arc::new(ParsedValue::BoolVal(*b)))),
+        (Value::Number(n), ParsedValueType::LongType) => {
+            Ok(n.as_i64().map(|x| Arc::new(ParsedValue::LongVal(x))))
+        }
+        (Value::Number(n), ParsedValueType::DoubleType) => {
+            Ok(n.as_f64().map(|x| Arc::new(ParsedValue::DoubleVal(x))))
+        }
+        (Value::String(s), _) => c.str2val(s, ref_time),
+        // numbers/bools into other types and nested objects/arrays (as JSON text)
+        (v, _) => c.str2val(v.to_string().as_str(), ref_time),
+    }
+}
+
+impl LogParser for JsonParser {
+    fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError> {
+        let json: Value = match serde_json::from_str(msg.as_str()) {
+            Ok(Value::Object(m)) => Value::Object(m),
+            Ok(_) => return Err(LogParseError::new("JSON message is not an object", msg)),
+            Err(err) => {
+                return Err(LogParseError::from_string(
+                    format!("Invalid JSON: {}", err),
+                    msg,
+                ))
+            }
+        };
+        let parsed = self
+            .schema
+            .lookup_parsed_data(&msg, |c, lnm| match lookup_path(&json, lnm) {
+                Some(v) => json2val(v, c, msg.ref_time()),
+                None => Ok(None),
+            });
+        match parsed {
+            Ok(pd) => Ok(ParsedMessage::new(msg, pd)),
+            Err(desc) => Err(LogParseError::from_string(desc, msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_col_def;
+
+    fn test_json_schema() -> GrokSchema {
+        GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def(
+                    "ts",
+                    ParsedValueType::TimeType(TimeTypeFormat::new("%Y-%m-%dT%H:%M:%S%z")),
+                    true,
+                ),
+                test_col_def("http.status", ParsedValueType::LongType, true),
+                test_col_def("http.took", ParsedValueType::DoubleType, false),
+                test_col_def("user.id", ParsedValueType::StrType(256), false),
+                test_col_def("cached", ParsedValueType::BoolType, false),
+                test_col_def("tags.1", ParsedValueType::StrType(256), false),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        )
+    }
+
+    #[test]
+    fn json_parse_works() {
+        let parser = JsonParser::new(test_json_schema());
+        let parsed = parser
+            .parse(RawMessage::new(String::from(
+                r#"{"ts":"2022-04-20T21:12:55+0300","http":{"status":200,"took":1.5},"user":{"id":null},"cached":true,"tags":["a","b"]}"#,
+            )))
+            .unwrap();
+        let pd = parsed.get_parsed();
+        assert_eq!(
+            pd.get_value("http.status").unwrap().as_ref(),
+            &ParsedValue::LongVal(200)
+        );
+        assert_eq!(
+            pd.get_value("http.took").unwrap().as_ref(),
+            &ParsedValue::DoubleVal(1.5)
+        );
+        assert_eq!(
+            pd.get_value("user.id").unwrap().as_ref(),
+            &ParsedValue::NullVal
+        );
+        assert_eq!(
+            pd.get_value("cached").unwrap().as_ref(),
+            &ParsedValue::BoolVal(true)
+        );
+        assert_eq!(
+            pd.get_value("tags.1").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("b")))
+        );
+        assert!(matches!(
+            pd.get_value("ts").unwrap().as_ref(),
+            ParsedValue::TimeVal(_)
+        ));
+    }
+
+    #[test]
+    fn json_parse_errors() {
+        let parser = JsonParser::new(test_json_schema());
+        // required http.status is missing
+        let missing = parser.parse(RawMessage::new(String::from(
+            r#"{"ts":"2022-04-20T21:12:55+0300","http":{}}"#,
+        )));
+        assert!(missing.is_err());
+        // required http.status is null
+        let null_req = parser.parse(RawMessage::new(String::from(
+            r#"{"ts":"2022-04-20T21:12:55+0300","http":{"status":null}}"#,
+        )));
+        assert!(null_req.is_err());
+        let not_json = parser.parse(RawMessage::new(String::from("not json")));
+        assert!(not_json.is_err());
+        let not_obj = parser.parse(RawMessage::new(String::from("[1, 2]")));
+        assert!(not_obj.is_err());
+    }
+}
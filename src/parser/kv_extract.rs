@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::parser::grok_parser::GrokColumnDef;
+use crate::parser::parser::ParsedValueType;
+
+pub const DEFAULT_KV_DELIMITER: &str = " ";
+pub const DEFAULT_KV_SEPARATOR: &str = "=";
+
+/// Splits a captured field (e.g. the free text message) into key/value pairs, which are
+/// available to the schema columns as <field>.<key> lookup names, e.g. message.user
+#[derive(Debug, Clone)]
+pub struct KvExtractor {
+    field: Arc<str>,
+    // looks up the field as an (unlimited) string
+    source_col: GrokColumnDef,
+    delimiter: String,
+    separator: String,
+}
+
+impl KvExtractor {
+    /// Parse a <field>[:<delimiter>[:<separator>]] spec, the delimiter between the
+    /// pairs defaults to a space and the separator between the key and value to "="
+    pub fn parse(spec: &str) -> Result<KvExtractor, String> {
+        let mut split = spec.splitn(3, ':');
+        let field = split.next().unwrap_or("");
+        let delimiter = split.next().unwrap_or(DEFAULT_KV_DELIMITER);
+        let separator = split.next().unwrap_or(DEFAULT_KV_SEPARATOR);
+        if field.is_empty() || delimiter.is_empty() || separator.is_empty() {
+            return Err(format!(
+                "Invalid key-value extraction spec (<field>[:<delimiter>[:<separator>]]): {}",
+                spec
+            ));
+        }
+        Ok(KvExtractor {
+            field: Arc::from(field),
+            source_col: GrokColumnDef::new(
+                Arc::from(field),
+                ParsedValueType::StrType(usize::MAX),
+                vec![Arc::new(field.to_string())],
+                false,
+            ),
+            delimiter: delimiter.to_string(),
+            separator: separator.to_string(),
+        })
+    }
+
+    pub fn field(&self) -> &Arc<str> {
+        &self.field
+    }
+
+    pub fn source_col(&self) -> &GrokColumnDef {
+        &self.source_col
+    }
+
+    /// The lookup name of a key, <field>.<key>
+    pub fn sub_field_name(&self, key: &str) -> String {
+        format!("{}.{}", self.field, key)
+    }
+
+    /// The (key, value) pairs of the text, the words without a separator are skipped.
+    /// Values can be double quoted, to contain the delimiter.
+    pub fn split_pairs(&self, text: &str) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let end = find_unquoted(rest, &self.delimiter).unwrap_or(rest.len());
+            if let Some((k, v)) = rest[..end].split_once(self.separator.as_str()) {
+                let k = k.trim();
+                if !k.is_empty() && !k.contains(char::is_whitespace) {
+                    ret.push((k.to_string(), unquote(v.trim()).to_string()));
+                }
+            }
+            rest = rest[end..]
+                .strip_prefix(self.delimiter.as_str())
+                .unwrap_or("");
+        }
+        ret
+    }
+}
+
+/// The position of the first delimiter which is not inside double quotes
+fn find_unquoted(s: &str, delimiter: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (ix, c) in s.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && s[ix..].starts_with(delimiter) {
+            return Some(ix);
+        }
+    }
+    None
+}
+
+fn unquote(v: &str) -> &str {
+    if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') {
+        &v[1..v.len() - 1]
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_extractor_works() {
+        let kve = KvExtractor::parse("message").unwrap();
+        assert_eq!(
+            kve.split_pairs(r#"User logged in user=bob status=200 agent="curl 7.1" x="#),
+            vec![
+                ("user".to_string(), "bob".to_string()),
+                ("status".to_string(), "200".to_string()),
+                ("agent".to_string(), "curl 7.1".to_string()),
+                ("x".to_string(), "".to_string()),
+            ]
+        );
+        assert_eq!(kve.sub_field_name("user"), "message.user");
+        let kve = KvExtractor::parse("msg:; :: ").unwrap();
+        assert_eq!(
+            kve.split_pairs("a: 1; b: two words; c"),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "two words".to_string()),
+            ]
+        );
+        assert!(KvExtractor::parse("").is_err());
+        assert!(KvExtractor::parse("message::").is_err());
+    }
+}
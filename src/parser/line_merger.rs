@@ -1,40 +1,168 @@
 use crate::parser::RawMessage;
+use crate::{ConfigError, DynError};
+use regex::Regex;
 
 pub trait LineMerger {
-    fn add_line(&mut self, line: String) -> Option<RawMessage>;
+    fn add_line(&mut self, line: RawMessage) -> Option<RawMessage>;
     fn flush(&mut self) -> Option<RawMessage>;
 }
 
-pub struct SpaceLineMerger {
+pub type DynLineMerger = Box<dyn LineMerger + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineMergerType {
+    SPACE,
+    START,
+    CONTINUATION,
+}
+
+/// How to merge multi-line messages:
+///  space - lines starting with whitespace are continuations of the previous ones
+///  start-regex - lines matching the pattern start a new message, the others are continuations
+///  continuation-regex - lines matching the pattern are continuations of the previous ones
+/// A message is also ended when it reaches max_lines lines or would exceed max_bytes bytes.
+#[derive(Debug, Clone)]
+pub struct LineMergerConfig {
+    merger_type: LineMergerType,
+    pattern: Option<Regex>,
+    max_lines: usize,
+    max_bytes: usize,
+    join_str: String,
+}
+
+pub const DEFAULT_MERGE_MAX_LINES: usize = 1000;
+pub const DEFAULT_MERGE_MAX_BYTES: usize = 1024 * 1024;
+
+impl LineMergerConfig {
+    pub fn new(
+        merger_type: &str,
+        pattern: Option<&str>,
+        max_lines: usize,
+        max_bytes: usize,
+        join_str: &str,
+    ) -> Result<LineMergerConfig, DynError> {
+        let merger_type = match merger_type {
+            "space" => LineMergerType::SPACE,
+            "start-regex" => LineMergerType::START,
+            "continuation-regex" => LineMergerType::CONTINUATION,
+            x => {
+                return Err(Box::new(ConfigError::new(
+                    format!("Invalid multi-line merger type: {}", x).as_str(),
+                )))
+            }
+        };
+        let pattern = match (merger_type, pattern) {
+            (LineMergerType::SPACE, _) => None,
+            (_, Some(p)) => Some(Regex::new(p)?),
+            (_, None) => {
+                return Err(Box::new(ConfigError::new(
+                    "The regex multi-line mergers require a pattern (--merge-pattern)",
+                )))
+            }
+        };
+        if max_lines == 0 || max_bytes == 0 {
+            return Err(Box::new(ConfigError::new(
+                "The multi-line merge limits must be positive",
+            )));
+        }
+        Ok(LineMergerConfig {
+            merger_type,
+            pattern,
+            max_lines,
+            max_bytes,
+            join_str: unescape_join_str(join_str),
+        })
+    }
+
+    pub fn create_line_merger(&self) -> DynLineMerger {
+        let buf = MergeBuffer::new(self.max_lines, self.max_bytes, self.join_str.clone());
+        match (self.merger_type, &self.pattern) {
+            (LineMergerType::START, Some(regex)) => {
+                Box::new(StartRegexLineMerger::new(buf, regex.clone()))
+            }
+            (LineMergerType::CONTINUATION, Some(regex)) => {
+                Box::new(ContinuationRegexLineMerger::new(buf, regex.clone()))
+            }
+            _ => Box::new(SpaceLineMerger::new(buf)),
+        }
+    }
+}
+
+/// Support \n, \r, \t and \\ escapes in the join string, so it can be given on the command line
+fn unescape_join_str(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some('r') => ret.push('\r'),
+            Some('t') => ret.push('\t'),
+            Some('\\') => ret.push('\\'),
+            Some(x) => {
+                ret.push('\\');
+                ret.push(x);
+            }
+            None => ret.push('\\'),
+        }
+    }
+    ret
+}
+
+/// The lines of the message being merged, shared by the LineMerger implementations
+struct MergeBuffer {
     buf: Vec<String>,
+    buf_bytes: usize,
+    head: Option<RawMessage>,
+    max_lines: usize,
+    max_bytes: usize,
     join_str: String,
 }
 
-impl SpaceLineMerger {
-    pub fn new() -> SpaceLineMerger {
-        // TODO configure capcity?
-        // TODO: make join str configurable
+impl MergeBuffer {
+    fn new(max_lines: usize, max_bytes: usize, join_str: String) -> MergeBuffer {
         Self {
             buf: Vec::with_capacity(10),
-            join_str: " ".to_string(),
+            buf_bytes: 0,
+            head: None,
+            max_lines,
+            max_bytes,
+            join_str,
         }
     }
-}
 
-impl LineMerger for SpaceLineMerger {
-    fn add_line(&mut self, line: String) -> Option<RawMessage> {
+    // the merged message keeps the attributes (syslog priority etc.) of its first line
+    fn start_message(&mut self, mut line: RawMessage) {
+        let s = line.replace_line(String::new());
+        self.buf_bytes = s.len();
+        self.buf.push(s);
+        self.head = Some(line);
+    }
+
+    fn take_message(&mut self) -> RawMessage {
+        let mut ret = self.head.take().unwrap();
+        ret.replace_line(self.buf.join(&self.join_str));
+        self.buf.clear();
+        self.buf_bytes = 0;
+        ret
+    }
+
+    fn add_line(&mut self, line: RawMessage, is_continuation: bool) -> Option<RawMessage> {
         if self.buf.is_empty() {
-            self.buf.push(line);
+            self.start_message(line);
             return None;
         }
-        if line.starts_with(" ") || line.starts_with("\t") {
-            // line continuation
-            self.buf.push(line);
+        let new_bytes = self.buf_bytes + self.join_str.len() + line.as_str().len();
+        if is_continuation && self.buf.len() < self.max_lines && new_bytes <= self.max_bytes {
+            self.buf_bytes = new_bytes;
+            self.buf.push(line.into_line());
             return None;
         }
-        let ret = Some(RawMessage::new(self.buf.join(&self.join_str)));
-        self.buf.clear();
-        self.buf.push(line);
+        let ret = Some(self.take_message());
+        self.start_message(line);
         ret
     }
 
@@ -42,9 +170,145 @@ impl LineMerger for SpaceLineMerger {
         if self.buf.is_empty() {
             None
         } else {
-            let ret = Some(RawMessage::new(self.buf.join(&self.join_str)));
-            self.buf.clear();
-            ret
+            Some(self.take_message())
         }
     }
 }
+
+/// Lines starting with space or tab are continuations of the previous ones
+pub struct SpaceLineMerger {
+    buf: MergeBuffer,
+}
+
+impl SpaceLineMerger {
+    fn new(buf: MergeBuffer) -> SpaceLineMerger {
+        Self { buf }
+    }
+}
+
+impl LineMerger for SpaceLineMerger {
+    fn add_line(&mut self, line: RawMessage) -> Option<RawMessage> {
+        let is_continuation = line.as_str().starts_with(' ') || line.as_str().starts_with('\t');
+        self.buf.add_line(line, is_continuation)
+    }
+
+    fn flush(&mut self) -> Option<RawMessage> {
+        self.buf.flush()
+    }
+}
+
+/// Lines matching the regex start a new message, e.g. `^\d{4}-\d{2}-\d{2} ` (a timestamp)
+pub struct StartRegexLineMerger {
+    buf: MergeBuffer,
+    regex: Regex,
+}
+
+impl StartRegexLineMerger {
+    fn new(buf: MergeBuffer, regex: Regex) -> StartRegexLineMerger {
+        Self { buf, regex }
+    }
+}
+
+impl LineMerger for StartRegexLineMerger {
+    fn add_line(&mut self, line: RawMessage) -> Option<RawMessage> {
+        // lines before the first message start are merged together
+        let is_continuation = !self.regex.is_match(line.as_str());
+        self.buf.add_line(line, is_continuation)
+    }
+
+    fn flush(&mut self) -> Option<RawMessage> {
+        self.buf.flush()
+    }
+}
+
+/// Lines matching the regex are continuations of the previous ones,
+/// e.g. `^(\s|Caused by:)` (Java stack traces)
+pub struct ContinuationRegexLineMerger {
+    buf: MergeBuffer,
+    regex: Regex,
+}
+
+impl ContinuationRegexLineMerger {
+    fn new(buf: MergeBuffer, regex: Regex) -> ContinuationRegexLineMerger {
+        Self { buf, regex }
+    }
+}
+
+impl LineMerger for ContinuationRegexLineMerger {
+    fn add_line(&mut self, line: RawMessage) -> Option<RawMessage> {
+        let is_continuation = self.regex.is_match(line.as_str());
+        self.buf.add_line(line, is_continuation)
+    }
+
+    fn flush(&mut self) -> Option<RawMessage> {
+        self.buf.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_all(lmc: &LineMergerConfig, lines: &[&str]) -> Vec<String> {
+        let mut lm = lmc.create_line_merger();
+        let mut ret = lines
+            .iter()
+            .filter_map(|s| lm.add_line(RawMessage::new(s.to_string())))
+            .map(|m| m.as_str().to_string())
+            .collect::<Vec<_>>();
+        ret.extend(lm.flush().map(|m| m.as_str().to_string()));
+        ret
+    }
+
+    const JAVA_TRACE: [&str; 6] = [
+        "2022-05-10 10:00:00 ERROR request failed",
+        "java.lang.IllegalStateException: boom",
+        "\tat com.example.Foo.bar(Foo.java:42)",
+        "Caused by: java.io.IOException: closed",
+        "\t... 3 more",
+        "2022-05-10 10:00:01 INFO next",
+    ];
+
+    #[test]
+    fn start_regex_merger_works() {
+        let lmc = LineMergerConfig::new(
+            "start-regex",
+            Some(r"^\d{4}-\d{2}-\d{2} "),
+            100,
+            4096,
+            r"\n",
+        )
+        .unwrap();
+        let merged = merge_all(&lmc, &JAVA_TRACE);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0], JAVA_TRACE[..5].join("\n"));
+        assert_eq!(merged[1], JAVA_TRACE[5]);
+    }
+
+    #[test]
+    fn continuation_regex_merger_works() {
+        let lmc = LineMergerConfig::new(
+            "continuation-regex",
+            Some(r"^(\s|Caused by:|java\.)"),
+            100,
+            4096,
+            " | ",
+        )
+        .unwrap();
+        let merged = merge_all(&lmc, &JAVA_TRACE);
+        assert_eq!(merged.len(), 2);
+        assert!(merged[0].ends_with(" | Caused by: java.io.IOException: closed | \t... 3 more"));
+        assert!(LineMergerConfig::new("continuation-regex", None, 100, 4096, " ").is_err());
+        assert!(LineMergerConfig::new("whatever", None, 100, 4096, " ").is_err());
+    }
+
+    #[test]
+    fn merger_limits_work() {
+        let lmc = LineMergerConfig::new("space", None, 3, 4096, " ").unwrap();
+        let merged = merge_all(&lmc, &["a", " b", " c", " d", "e"]);
+        assert_eq!(merged, vec!["a  b  c", " d", "e"]);
+        let lmc = LineMergerConfig::new("space", None, 100, 8, "").unwrap();
+        let merged = merge_all(&lmc, &["abc", " de", " fg", "h"]);
+        assert_eq!(merged, vec!["abc de", " fg", "h"]);
+    }
+}
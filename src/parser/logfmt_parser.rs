@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::parser::grok_parser::GrokSchema;
+use crate::parser::parser::*;
+
+/// A parser for logfmt (`key=value key2="quoted value"`) lines. The schema columns
+/// lookup names are matched against the keys. Keys which are not used by any column
+/// can optionally be collected (as logfmt) in an extra string column.
+pub struct LogfmtParser {
+    schema: GrokSchema,
+    extra_column: Option<Arc<str>>,
+    known_keys: HashSet<String>,
+}
+
+impl LogfmtParser {
+    pub fn new(schema: GrokSchema, extra_column: Option<Arc<str>>) -> LogfmtParser {
+        let known_keys = schema
+            .columns()
+            .iter()
+            .filter(|c| Some(c.col_name()) != extra_column.as_ref())
+            .flat_map(|c| c.lookup_names().iter().map(|lnm| lnm.to_string()))
+            .collect::<HashSet<_>>();
+        LogfmtParser {
+            schema,
+            extra_column,
+            known_keys,
+        }
+    }
+
+    fn extra_value(&self, pairs: &[(String, String)]) -> Option<String> {
+        let unknown = pairs
+            .iter()
+            .filter(|(k, _)| !self.known_keys.contains(k))
+            .map(|(k, v)| format!("{}={}", k, quote_logfmt_value(v)))
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            None
+        } else {
+            Some(unknown.join(" "))
+        }
+    }
+}
+
+fn quote_logfmt_value(v: &str) -> String {
+    if !v.is_empty() && !v.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        return v.to_string();
+    }
+    let mut ret = String::with_capacity(v.len() + 2);
+    ret.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Split a logfmt line into (key, value) pairs. Keys without a value (`key` or `key=`)
+/// get an empty value, quoted values can contain \" \\ \n \r and \t escapes.
+pub fn tokenize_logfmt(line: &str) -> Result<Vec<(String, String)>, String> {
+    let mut ret = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if key.is_empty() {
+            return Err(format!("Empty logfmt key in: {}", line));
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('r') => value.push('\r'),
+                            Some('t') => value.push('\t'),
+                            Some(x) => value.push(x),
+                            None => break,
+                        },
+                        c => value.push(c),
+                    }
+                }
+                if !closed {
+                    return Err(format!("Unterminated logfmt quoted value for key: {}", key));
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
+        }
+        ret.push((key, value));
+    }
+    Ok(ret)
+}
+
+impl LogParser for LogfmtParser {
+    fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError> {
+        let pairs = match tokenize_logfmt(msg.as_str()) {
+            Ok(pairs) => pairs,
+            Err(desc) => return Err(LogParseError::from_string(desc, msg)),
+        };
+        let extra = self
+            .extra_column
+            .as_ref()
+            .and_then(|_| self.extra_value(&pairs));
+        // with duplicate keys the last one wins
+        let hm: HashMap<&str, &str> = pairs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let extra_column = self.extra_column.as_deref();
+        let parsed = self.schema.lookup_parsed_data(&msg, |c, lnm| {
+            let value = if Some(c.col_name().as_ref()) == extra_column {
+                extra.as_deref()
+            } else {
+                hm.get(lnm).copied()
+            };
+            match value {
+                Some(s) => c.str2val(s, msg.ref_time()),
+                None => Ok(None),
+            }
+        });
+        match parsed {
+            Ok(pd) => Ok(ParsedMessage::new(msg, pd)),
+            Err(desc) => Err(LogParseError::from_string(desc, msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{test_col_def, GrokColumnDef, StrOverflowPolicy};
+
+    fn test_logfmt_schema() -> GrokSchema {
+        test_logfmt_schema_with_extra(test_col_def("extra", ParsedValueType::StrType(256), false))
+    }
+
+    fn test_logfmt_schema_with_extra(extra: GrokColumnDef) -> GrokSchema {
+        GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def("level", ParsedValueType::StrType(16), true),
+                test_col_def("msg", ParsedValueType::StrType(256), true),
+                test_col_def("status", ParsedValueType::LongType, false),
+                extra,
+            ],
+            false,
+            vec![],
+            false,
+            String::from("logfmt"),
+        )
+    }
+
+    #[test]
+    fn tokenize_logfmt_works() {
+        let pairs = tokenize_logfmt(r#"level=info msg="say \"hi\"\tthere" empty= flag path=/a=b "#)
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("level".to_string(), "info".to_string()),
+                ("msg".to_string(), "say \"hi\"\tthere".to_string()),
+                ("empty".to_string(), "".to_string()),
+                ("flag".to_string(), "".to_string()),
+                ("path".to_string(), "/a=b".to_string()),
+            ]
+        );
+        assert!(tokenize_logfmt(r#"msg="unterminated"#).is_err());
+        assert!(tokenize_logfmt("=value").is_err());
+    }
+
+    #[test]
+    fn logfmt_parse_works() {
+        let parser = LogfmtParser::new(test_logfmt_schema(), Some(Arc::from("extra")));
+        let parsed = parser
+            .parse(RawMessage::new(String::from(
+                r#"level=warn msg="disk almost full" status=507 host=db1 note="a b""#,
+            )))
+            .unwrap();
+        let pd = parsed.get_parsed();
+        assert_eq!(
+            pd.get_value("msg").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("disk almost full")))
+        );
+        assert_eq!(
+            pd.get_value("status").unwrap().as_ref(),
+            &ParsedValue::LongVal(507)
+        );
+        assert_eq!(
+            pd.get_value("extra").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from(r#"host=db1 note="a b""#)))
+        );
+        let no_extra = parser
+            .parse(RawMessage::new(String::from("level=info msg=ok")))
+            .unwrap();
+        assert!(no_extra.get_parsed().get_value("extra").is_none());
+        let missing = parser.parse(RawMessage::new(String::from("level=info status=200")));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn logfmt_extra_column_max_len_works() {
+        let extra = test_col_def("extra", ParsedValueType::StrType(8), false);
+        let schema = test_logfmt_schema_with_extra(
+            extra.clone().with_str_overflow(StrOverflowPolicy::TRUNCATE),
+        );
+        let line = r#"level=warn msg=full host=db1 note="a b""#;
+        let parser = LogfmtParser::new(schema, Some(Arc::from("extra")));
+        let parsed = parser.parse(RawMessage::new(String::from(line))).unwrap();
+        assert_eq!(
+            parsed.get_parsed().get_value("extra").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("host=db1")))
+        );
+        let schema =
+            test_logfmt_schema_with_extra(extra.with_str_overflow(StrOverflowPolicy::REJECT));
+        let parser = LogfmtParser::new(schema, Some(Arc::from("extra")));
+        assert!(parser.parse(RawMessage::new(String::from(line))).is_err());
+    }
+}
@@ -1,14 +1,32 @@
 // Copyright 2022 Asen Lazarov
 
+mod derived_column;
+mod duration;
+mod grok_debug;
 mod grok_parser;
+mod json_parser;
+mod kv_extract;
 mod line_merger;
+mod logfmt_parser;
 mod parser;
+mod regex_parser;
+mod rfc5424_parser;
 mod schema;
+mod schema_infer;
 
+pub use derived_column::{DerivedColumnDef, DerivedValue};
+pub use duration::*;
+pub use grok_debug::GrokDebugger;
 pub use grok_parser::{GrokColumnDef, GrokParser, GrokSchema};
+pub use json_parser::JsonParser;
+pub use kv_extract::KvExtractor;
 pub use line_merger::*;
+pub use logfmt_parser::LogfmtParser;
 pub use parser::*;
+pub use regex_parser::RegexParser;
+pub use rfc5424_parser::Rfc5424Parser;
 pub use schema::*;
+pub use schema_infer::SchemaInference;
 
 #[cfg(test)]
 pub use grok_parser::tests::*;
@@ -5,23 +5,230 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
 use std::rc::Rc;
 use std::sync::Arc;
 
 use chrono::Datelike;
-use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, TimeZone};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use log::warn;
 
+use crate::parser::duration::{
+    duration_unit_nanos, format_duration, parse_duration, DEFAULT_DURATION_UNIT_NANOS,
+};
+
+const SYSLOG_FACILITY_NAMES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SYSLOG_SEVERITY_NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// Syslog priority value, i.e. facility * 8 + severity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyslogPri(u8);
+
+impl SyslogPri {
+    pub fn new(pri: u8) -> Option<SyslogPri> {
+        if (pri as usize) < SYSLOG_FACILITY_NAMES.len() * 8 {
+            Some(SyslogPri(pri))
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<SyslogPri> {
+        // at most 3 digits and no leading zeroes, as per RFC 5424
+        if s.is_empty() || s.len() > 3 || (s.len() > 1 && s.starts_with('0')) {
+            return None;
+        }
+        s.parse::<u8>().ok().and_then(SyslogPri::new)
+    }
+
+    pub fn facility(&self) -> u8 {
+        self.0 >> 3
+    }
+
+    pub fn severity(&self) -> u8 {
+        self.0 & 7
+    }
+
+    pub fn facility_name(&self) -> &'static str {
+        SYSLOG_FACILITY_NAMES[self.facility() as usize]
+    }
+
+    pub fn severity_name(&self) -> &'static str {
+        SYSLOG_SEVERITY_NAMES[self.severity() as usize]
+    }
+
+    /// Get the value of one of the facility, severity, facility_name or severity_name
+    /// built-in columns, converted to the column type
+    pub fn column_value(
+        &self,
+        col_name: &str,
+        ctype: &ParsedValueType,
+    ) -> Option<Arc<ParsedValue>> {
+        match col_name {
+            "facility" => str2val(self.facility().to_string().as_str(), ctype),
+            "severity" => str2val(self.severity().to_string().as_str(), ctype),
+            "facility_name" => str2val(self.facility_name(), ctype),
+            "severity_name" => str2val(self.severity_name(), ctype),
+            _ => None,
+        }
+    }
+}
+
+/// Where the messages of a syslog server connection (or UDP stream) came from
+#[derive(Debug)]
+pub struct SourceMeta {
+    remote_addr: Arc<str>,
+    listener: Arc<str>,
+    protocol: &'static str,
+}
+
+impl SourceMeta {
+    pub fn new(remote_addr: Arc<str>, listener: Arc<str>, protocol: &'static str) -> SourceMeta {
+        SourceMeta {
+            remote_addr,
+            listener,
+            protocol,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct RawMessage(String);
+pub struct RawMessage {
+    line: String,
+    pri: Option<SyslogPri>,
+    ref_time: Option<DateTime<Utc>>,
+    received_at: Option<DateTime<Utc>>,
+    source: Option<Arc<str>>,
+    meta: Option<Arc<SourceMeta>>,
+}
 
 impl RawMessage {
+    #[cfg(test)]
     pub fn new(s: String) -> RawMessage {
-        RawMessage(s)
+        RawMessage::with_pri(s, None)
+    }
+
+    pub fn with_pri(s: String, pri: Option<SyslogPri>) -> RawMessage {
+        RawMessage {
+            line: s,
+            pri,
+            ref_time: None,
+            received_at: None,
+            source: None,
+            meta: None,
+        }
+    }
+
+    /// Set the reference time (e.g. file mtime) used to infer the year
+    /// of timestamps without one, the receive time is used if not set
+    pub fn with_ref_time(mut self, ref_time: Option<DateTime<Utc>>) -> RawMessage {
+        self.ref_time = ref_time;
+        self
+    }
+
+    pub fn with_received_at(mut self, received_at: Option<DateTime<Utc>>) -> RawMessage {
+        self.received_at = received_at;
+        self
+    }
+
+    /// Set where the message came from - the input file or the remote address
+    pub fn with_source(mut self, source: Option<Arc<str>>) -> RawMessage {
+        self.source = source;
+        self
+    }
+
+    pub fn with_meta(mut self, meta: Option<Arc<SourceMeta>>) -> RawMessage {
+        self.meta = meta;
+        self
     }
 
     pub fn as_str(&self) -> &str {
-        self.0.as_str()
+        self.line.as_str()
+    }
+
+    pub fn into_line(self) -> String {
+        self.line
+    }
+
+    /// Replace the line keeping the rest of the message attributes, return the old line
+    pub fn replace_line(&mut self, line: String) -> String {
+        std::mem::replace(&mut self.line, line)
+    }
+
+    pub fn pri(&self) -> Option<SyslogPri> {
+        self.pri
+    }
+
+    pub fn ref_time(&self) -> Option<&DateTime<Utc>> {
+        self.ref_time.as_ref().or(self.received_at.as_ref())
+    }
+
+    pub fn received_at(&self) -> Option<&DateTime<Utc>> {
+        self.received_at.as_ref()
+    }
+
+    pub fn source(&self) -> Option<&Arc<str>> {
+        self.source.as_ref()
+    }
+
+    /// The input file the message was read from, None for stdin and the syslog servers
+    pub fn input_file(&self) -> Option<&Arc<str>> {
+        self.source
+            .as_ref()
+            .filter(|s| self.meta.is_none() && s.as_ref() != "-")
+    }
+
+    /// Get the value of one of the _remote_addr, _listener, _protocol (syslog server
+    /// messages only) or _received_at built-in columns, converted to the column type
+    pub fn meta_column_value(
+        &self,
+        col_name: &str,
+        ctype: &ParsedValueType,
+    ) -> Option<Arc<ParsedValue>> {
+        match (col_name, ctype) {
+            ("_remote_addr", _) => self
+                .meta
+                .as_ref()
+                .and_then(|m| str2val(&m.remote_addr, ctype)),
+            ("_listener", _) => self.meta.as_ref().and_then(|m| str2val(&m.listener, ctype)),
+            ("_protocol", _) => self.meta.as_ref().and_then(|m| str2val(m.protocol, ctype)),
+            ("_received_at", ParsedValueType::TimeType(_)) => self.received_at.map(utc_time_val),
+            ("_received_at", _) => self
+                .received_at
+                .and_then(|ts| str2val(ts.to_rfc3339().as_str(), ctype)),
+            _ => None,
+        }
     }
 }
 
@@ -53,6 +260,10 @@ impl LogParseError {
     pub fn get_desc(&self) -> &String {
         &self.desc
     }
+
+    pub fn consume_raw(self) -> RawMessage {
+        self.raw_msg
+    }
 }
 
 impl fmt::Display for LogParseError {
@@ -72,6 +283,13 @@ pub fn arc_null_pv() -> Arc<ParsedValue> {
     Arc::new(ParsedValue::NullVal)
 }
 
+/// A (UTC offset) timestamp value
+pub fn utc_time_val(ts: DateTime<Utc>) -> Arc<ParsedValue> {
+    Arc::new(ParsedValue::TimeVal(
+        ts.with_timezone(&FixedOffset::east(0)),
+    ))
+}
+
 #[derive(Debug, Clone)]
 pub enum ParsedValue {
     NullVal,
@@ -79,6 +297,8 @@ pub enum ParsedValue {
     LongVal(i64),
     DoubleVal(f64),
     TimeVal(DateTime<FixedOffset>),
+    DurationVal(Duration),
+    IpVal(IpAddr),
     StrVal(Arc<String>),
 }
 
@@ -98,6 +318,8 @@ impl Hash for ParsedValue {
                 }
             }
             ParsedValue::TimeVal(t) => t.hash(state),
+            ParsedValue::DurationVal(d) => d.hash(state),
+            ParsedValue::IpVal(ip) => ip.hash(state),
             ParsedValue::StrVal(s) => s.hash(state),
         }
     }
@@ -106,7 +328,7 @@ impl Hash for ParsedValue {
 impl PartialEq for ParsedValue {
     fn eq(&self, other: &Self) -> bool {
         match self {
-            ParsedValue::NullVal => other == &ParsedValue::NullVal,
+            ParsedValue::NullVal => matches!(other, ParsedValue::NullVal),
             ParsedValue::BoolVal(b) => {
                 if let ParsedValue::BoolVal(x) = other {
                     x == b
@@ -140,9 +362,15 @@ impl PartialEq for ParsedValue {
                     false
                 }
             }
+            // strings are parsed as durations/ips by the query comparisons,
+            // e.g. took = '1.5s' or client_ip = '10.0.0.1'
+            ParsedValue::DurationVal(d) => matches!(other, ParsedValue::DurationVal(x) if x == d),
+            ParsedValue::IpVal(ip) => matches!(other, ParsedValue::IpVal(x) if x == ip),
             ParsedValue::StrVal(s) => {
                 if let ParsedValue::StrVal(x) = other {
                     s.as_str().eq(x.as_str())
+                } else if let ParsedValue::IpVal(_) | ParsedValue::DurationVal(_) = other {
+                    false
                 } else {
                     s.as_str().eq(other.to_rc_str().as_ref())
                 }
@@ -171,7 +399,9 @@ impl PartialOrd for ParsedValue {
                 ParsedValue::LongVal(x) => l.partial_cmp(x),
                 ParsedValue::DoubleVal(x) => (*l as f64).partial_cmp(x),
                 ParsedValue::TimeVal(x) => l.partial_cmp(&x.timestamp_millis()),
-                ParsedValue::StrVal(_) => Some(Ordering::Less),
+                ParsedValue::DurationVal(_) | ParsedValue::IpVal(_) | ParsedValue::StrVal(_) => {
+                    Some(Ordering::Less)
+                }
             },
             ParsedValue::DoubleVal(d) => match other {
                 ParsedValue::NullVal => Some(Ordering::Greater),
@@ -179,7 +409,9 @@ impl PartialOrd for ParsedValue {
                 ParsedValue::LongVal(x) => d.partial_cmp(&(*x as f64)),
                 ParsedValue::DoubleVal(x) => d.partial_cmp(x),
                 ParsedValue::TimeVal(x) => d.partial_cmp(&(x.timestamp_millis() as f64)),
-                ParsedValue::StrVal(_) => Some(Ordering::Less),
+                ParsedValue::DurationVal(_) | ParsedValue::IpVal(_) | ParsedValue::StrVal(_) => {
+                    Some(Ordering::Less)
+                }
             },
             ParsedValue::TimeVal(t) => match other {
                 ParsedValue::NullVal => Some(Ordering::Greater),
@@ -187,7 +419,20 @@ impl PartialOrd for ParsedValue {
                 ParsedValue::LongVal(x) => t.timestamp_millis().partial_cmp(x),
                 ParsedValue::DoubleVal(x) => (t.timestamp_millis() as f64).partial_cmp(x),
                 ParsedValue::TimeVal(x) => t.timestamp_nanos().partial_cmp(&x.timestamp_nanos()),
+                ParsedValue::DurationVal(_) | ParsedValue::IpVal(_) | ParsedValue::StrVal(_) => {
+                    Some(Ordering::Less)
+                }
+            },
+            ParsedValue::DurationVal(d) => match other {
+                ParsedValue::DurationVal(x) => d.partial_cmp(x),
+                ParsedValue::IpVal(_) | ParsedValue::StrVal(_) => Some(Ordering::Less),
+                _ => Some(Ordering::Greater),
+            },
+            // IPv4 addresses sort before the IPv6 ones
+            ParsedValue::IpVal(ip) => match other {
+                ParsedValue::IpVal(x) => ip.partial_cmp(x),
                 ParsedValue::StrVal(_) => Some(Ordering::Less),
+                _ => Some(Ordering::Greater),
             },
             ParsedValue::StrVal(s) => {
                 match other {
@@ -202,6 +447,11 @@ impl PartialOrd for ParsedValue {
     }
 }
 
+/// Durations in query string literals (e.g. '100ms'), numbers without a unit are seconds
+pub fn str2duration(s: &str) -> Option<Duration> {
+    parse_duration(s, DEFAULT_DURATION_UNIT_NANOS)
+}
+
 impl ParsedValue {
     pub fn as_bool(&self) -> Option<bool> {
         match self {
@@ -211,6 +461,8 @@ impl ParsedValue {
             ParsedValue::LongVal(x) => Some(*x != 0),
             ParsedValue::DoubleVal(x) => Some(*x != 0.0),
             ParsedValue::TimeVal(_) => None,
+            ParsedValue::DurationVal(_) => None,
+            ParsedValue::IpVal(_) => None,
         }
     }
 
@@ -222,6 +474,37 @@ impl ParsedValue {
             ParsedValue::LongVal(x) => Rc::from(x.to_string().as_str()),
             ParsedValue::DoubleVal(x) => Rc::from(x.to_string().as_str()),
             ParsedValue::TimeVal(x) => Rc::from(x.to_string().as_str()),
+            ParsedValue::DurationVal(x) => Rc::from(format_duration(x).as_str()),
+            ParsedValue::IpVal(x) => Rc::from(x.to_string().as_str()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EpochUnit {
+    Secs,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl EpochUnit {
+    fn from_format(fmt: &str) -> Option<EpochUnit> {
+        match fmt {
+            "epoch_s" => Some(EpochUnit::Secs),
+            "epoch_ms" => Some(EpochUnit::Millis),
+            "epoch_us" => Some(EpochUnit::Micros),
+            "epoch_ns" => Some(EpochUnit::Nanos),
+            _ => None,
+        }
+    }
+
+    fn nanos(&self) -> i128 {
+        match self {
+            EpochUnit::Secs => 1_000_000_000,
+            EpochUnit::Millis => 1_000_000,
+            EpochUnit::Micros => 1_000,
+            EpochUnit::Nanos => 1,
         }
     }
 }
@@ -231,11 +514,10 @@ pub struct TimeTypeFormat {
     format_specifier: Box<String>,
     needs_year: bool,
     needs_tz: bool,
-    local_tz_offset: i32,
-}
-
-fn local_timezone_offset() -> i32 {
-    Local.timestamp(0, 0).offset().fix().local_minus_utc()
+    // the zone of timestamps without an offset, None is the system local zone
+    tz: Option<Tz>,
+    // set for the epoch_s/ms/us/ns formats, which are not parsed using the format specifier
+    epoch_unit: Option<EpochUnit>,
 }
 
 // https://docs.rs/chrono/0.4.19/chrono/format/strftime/index.html#specifiers
@@ -245,7 +527,27 @@ const YEAR_SPECIFIERS: [&str; 11] = [
 const TZ_SPECIFIERS: [&str; 6] = ["%Z", "%z", "%:z", "%#z", "%+", "%s"];
 
 impl TimeTypeFormat {
+    /// The format can end with an IANA time zone name to use for timestamps without
+    /// an offset, e.g. "%b %e %H:%M:%S:Europe/Sofia". The epoch_s, epoch_ms, epoch_us
+    /// and epoch_ns formats are (possibly fractional) Unix epoch times, shown in UTC
+    /// unless a zone is set.
     pub fn new(fmt: &str) -> TimeTypeFormat {
+        let (fmt, tz) = match fmt.rsplit_once(':') {
+            Some((f, zone)) => match zone.parse::<Tz>() {
+                Ok(tz) => (f, Some(tz)),
+                Err(_) => (fmt, None),
+            },
+            None => (fmt, None),
+        };
+        if let Some(epoch_unit) = EpochUnit::from_format(fmt) {
+            return TimeTypeFormat {
+                format_specifier: Box::new(fmt.to_string()),
+                needs_year: false,
+                needs_tz: false,
+                tz,
+                epoch_unit: Some(epoch_unit),
+            };
+        }
         let (specifier, need_year) = if YEAR_SPECIFIERS.iter().any(|&ys| fmt.contains(ys)) {
             (fmt.to_string(), false)
         } else {
@@ -256,9 +558,72 @@ impl TimeTypeFormat {
             format_specifier: Box::new(specifier),
             needs_year: need_year,
             needs_tz: !has_tz,
-            local_tz_offset: local_timezone_offset(),
+            tz,
+            epoch_unit: None,
+        }
+    }
+
+    /// Use the given zone (instead of the system local one) unless
+    /// the format already has a zone set
+    pub fn with_default_tz(mut self, tz: Option<Tz>) -> TimeTypeFormat {
+        if self.tz.is_none() {
+            self.tz = tz;
+        }
+        self
+    }
+}
+
+// The offset for a local time in the given zone. Ambiguous times (DST end) get the
+// earlier offset, non-existent ones (DST start) the offset before the transition.
+fn local_to_fixed<Z: TimeZone>(tz: &Z, nd: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    let fixed_offset = match tz.offset_from_local_datetime(nd) {
+        LocalResult::Single(off) => off.fix(),
+        LocalResult::Ambiguous(off, _) => off.fix(),
+        LocalResult::None => tz
+            .offset_from_utc_datetime(&(*nd - Duration::days(1)))
+            .fix(),
+    };
+    fixed_offset.from_local_datetime(nd).single()
+}
+
+// Parse a decimal number of epoch units (e.g. 1652140319.123 seconds) without
+// going through f64, to keep the precision
+fn parse_epoch(s: &str, unit: EpochUnit, tz: &Option<Tz>) -> Option<DateTime<FixedOffset>> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, s),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+    if int_part.is_empty() || int_part.len() > 20 || !is_digits(int_part) || !is_digits(frac_part) {
+        return None;
+    }
+    let mut nanos = int_part.parse::<i128>().ok()? * unit.nanos();
+    let mut scale = unit.nanos();
+    // digits beyond nanosecond precision are dropped
+    for d in frac_part.bytes() {
+        scale /= 10;
+        if scale == 0 {
+            break;
         }
+        nanos += (d - b'0') as i128 * scale;
     }
+    if negative {
+        nanos = -nanos;
+    }
+    let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+    let nsecs = nanos.rem_euclid(1_000_000_000) as u32;
+    let utc = Utc.timestamp_opt(secs, nsecs).single()?;
+    match tz {
+        Some(tz) => Some(ts_to_timezone(&utc.into(), tz)),
+        None => Some(utc.into()),
+    }
+}
+
+/// Convert a timestamp to (the offset of) the given zone, e.g. for output
+pub fn ts_to_timezone(ts: &DateTime<FixedOffset>, tz: &Tz) -> DateTime<FixedOffset> {
+    let zoned = ts.with_timezone(tz);
+    zoned.with_timezone(&zoned.offset().fix())
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -268,28 +633,60 @@ pub enum ParsedValueType {
     LongType,
     DoubleType,
     TimeType(TimeTypeFormat), // format specifier
+    DurationType(i64),        // the unit (in nanoseconds) of the numbers without one
+    IpType,                   // IPv4 or IPv6 address
     StrType(usize),           // max len
 }
 
-fn parse_ts(s: &str, fmt: &TimeTypeFormat) -> Option<DateTime<FixedOffset>> {
-    let needs_year_str: String;
-    let to_parse = if fmt.needs_year {
-        let current_date = chrono::Utc::now(); // TODO this is broken
-        let year = current_date.year();
-        needs_year_str = format!("{} {}", s, year);
-        //println!("{} - {}", needs_year_str, &fmt.format_specifier);
-        needs_year_str.as_str()
+// how far after the reference time a timestamp with an inferred year can be
+const YEAR_INFERENCE_MAX_AHEAD_SECS: i64 = 24 * 3600;
+
+// The latest year which does not put the timestamp (much) after the reference time,
+// i.e. Dec 31 with a Jan 2 reference (file mtime or receive time) is in the previous year.
+// Going up to 4 years back, in case of Feb 29
+fn parse_ts_infer_year(
+    s: &str,
+    fmt: &TimeTypeFormat,
+    ref_time: &DateTime<Utc>,
+) -> Option<DateTime<FixedOffset>> {
+    let max_ts = *ref_time + Duration::seconds(YEAR_INFERENCE_MAX_AHEAD_SECS);
+    let mut earliest = None;
+    for year in (ref_time.year() - 4..=ref_time.year() + 1).rev() {
+        let with_year = format!("{} {}", s, year);
+        if let Some(ts) = parse_ts_str(with_year.as_str(), fmt) {
+            if ts <= max_ts {
+                return Some(ts);
+            }
+            earliest = Some(ts);
+        }
+    }
+    earliest
+}
+
+fn parse_ts(
+    s: &str,
+    fmt: &TimeTypeFormat,
+    ref_time: Option<&DateTime<Utc>>,
+) -> Option<DateTime<FixedOffset>> {
+    if let Some(unit) = fmt.epoch_unit {
+        parse_epoch(s, unit, &fmt.tz)
+    } else if fmt.needs_year {
+        match ref_time {
+            Some(rt) => parse_ts_infer_year(s, fmt, rt),
+            None => parse_ts_infer_year(s, fmt, &Utc::now()),
+        }
     } else {
-        s
-    };
+        parse_ts_str(s, fmt)
+    }
+}
+
+fn parse_ts_str(to_parse: &str, fmt: &TimeTypeFormat) -> Option<DateTime<FixedOffset>> {
     if fmt.needs_tz {
-        //println!("{} - {}", fmt.needs_tz, &fmt.local_tz_offset);
         NaiveDateTime::parse_from_str(to_parse, &fmt.format_specifier)
             .ok()
-            .and_then(|nd| {
-                FixedOffset::east(fmt.local_tz_offset)
-                    .from_local_datetime(&nd)
-                    .single()
+            .and_then(|nd| match &fmt.tz {
+                Some(tz) => local_to_fixed(tz, &nd),
+                None => local_to_fixed(&Local, &nd),
             })
     } else {
         DateTime::parse_from_str(to_parse, &fmt.format_specifier).ok()
@@ -297,6 +694,16 @@ fn parse_ts(s: &str, fmt: &TimeTypeFormat) -> Option<DateTime<FixedOffset>> {
 }
 
 pub fn str2val(s: &str, ctype: &ParsedValueType) -> Option<Arc<ParsedValue>> {
+    str2val_ref(s, ctype, None)
+}
+
+/// Same as str2val, with a reference time (instead of now) used to infer
+/// the year of timestamp values which have none
+pub fn str2val_ref(
+    s: &str,
+    ctype: &ParsedValueType,
+    ref_time: Option<&DateTime<Utc>>,
+) -> Option<Arc<ParsedValue>> {
     let pv_opt = match ctype {
         ParsedValueType::StrType(max_len) => {
             if s.len() <= *max_len {
@@ -308,7 +715,11 @@ pub fn str2val(s: &str, ctype: &ParsedValueType) -> Option<Arc<ParsedValue>> {
         }
         ParsedValueType::LongType => s.parse::<i64>().ok().map(|v| ParsedValue::LongVal(v)),
         ParsedValueType::DoubleType => s.parse::<f64>().ok().map(|v| ParsedValue::DoubleVal(v)),
-        ParsedValueType::TimeType(fmt) => parse_ts(s, fmt).map(|v| ParsedValue::TimeVal(v)),
+        ParsedValueType::TimeType(fmt) => parse_ts(s, fmt, ref_time).map(ParsedValue::TimeVal),
+        ParsedValueType::DurationType(unit) => {
+            parse_duration(s, *unit).map(ParsedValue::DurationVal)
+        }
+        ParsedValueType::IpType => s.parse::<IpAddr>().ok().map(ParsedValue::IpVal),
         ParsedValueType::NullType => Some(ParsedValue::NullVal),
         ParsedValueType::BoolType => {
             if s.eq_ignore_ascii_case("true") {
@@ -337,6 +748,14 @@ pub fn str2type(s: &str) -> Option<ParsedValueType> {
         "int" | "long" => Some(ParsedValueType::LongType),
         "float" | "double" => Some(ParsedValueType::DoubleType),
         "bool" => Some(ParsedValueType::BoolType),
+        "ip" => Some(ParsedValueType::IpType),
+        "duration" => {
+            if split.len() < 2 {
+                Some(ParsedValueType::DurationType(DEFAULT_DURATION_UNIT_NANOS))
+            } else {
+                duration_unit_nanos(split[1]).map(ParsedValueType::DurationType)
+            }
+        }
         "null" => Some(ParsedValueType::NullType),
         "ts" => {
             if split.len() < 2 {
@@ -349,17 +768,93 @@ pub fn str2type(s: &str) -> Option<ParsedValueType> {
     }
 }
 
+/// What to do with the string values exceeding the max length of their column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrOverflowPolicy {
+    REJECT,   // reject the whole message
+    NULL,     // the value becomes NULL
+    TRUNCATE, // truncate to the max length (at an UTF-8 char boundary)
+    MARKER,   // truncate, replacing the end of the value with STR_TRUNCATION_MARKER
+}
+
+pub const STR_TRUNCATION_MARKER: &str = "...";
+
+impl StrOverflowPolicy {
+    pub fn from_name(s: &str) -> Option<StrOverflowPolicy> {
+        match s {
+            "reject" => Some(StrOverflowPolicy::REJECT),
+            "null" => Some(StrOverflowPolicy::NULL),
+            "truncate" => Some(StrOverflowPolicy::TRUNCATE),
+            "truncate-marker" => Some(StrOverflowPolicy::MARKER),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrOverflowPolicy::REJECT => "reject",
+            StrOverflowPolicy::NULL => "null",
+            StrOverflowPolicy::TRUNCATE => "truncate",
+            StrOverflowPolicy::MARKER => "truncate-marker",
+        }
+    }
+}
+
+/// Split the oversize policy off a str type spec, e.g. "str:64:truncate"
+pub fn split_str_overflow_policy(
+    type_spec: &str,
+) -> Result<(&str, Option<StrOverflowPolicy>), String> {
+    let split = type_spec.splitn(3, ':').collect::<Vec<&str>>();
+    if split.len() < 3 || split[0] != "str" {
+        return Ok((type_spec, None));
+    }
+    match StrOverflowPolicy::from_name(split[2]) {
+        Some(policy) => Ok((&type_spec[..split[0].len() + 1 + split[1].len()], Some(policy))),
+        None => Err(format!(
+            "Invalid oversize string policy (must be one of reject, null, truncate, truncate-marker): {}",
+            split[2]
+        )),
+    }
+}
+
+/// The longest prefix of s not exceeding max_len bytes (and ending at a char
+/// boundary), ending with the marker (if it fits)
+pub fn truncate_str(s: &str, max_len: usize, marker: &str) -> String {
+    if s.len() <= max_len {
+        return s.to_string();
+    }
+    let marker = if marker.len() <= max_len { marker } else { "" };
+    let mut end = max_len - marker.len();
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &s[..end], marker)
+}
+
+/// The values of the fields which are not schema columns (e.g. the extracted key/value
+/// pairs), by lookup name
+pub type DynamicFields = Arc<HashMap<Arc<str>, Arc<ParsedValue>>>;
+
 #[derive(Debug)]
-pub struct ParsedData(HashMap<Arc<str>, Arc<ParsedValue>>);
+pub struct ParsedData(HashMap<Arc<str>, Arc<ParsedValue>>, Option<DynamicFields>);
 
 impl ParsedData {
     pub fn new(hm: HashMap<Arc<str>, Arc<ParsedValue>>) -> ParsedData {
-        ParsedData(hm)
+        ParsedData(hm, None)
+    }
+
+    pub fn with_dynamic_fields(mut self, dynamic: Option<DynamicFields>) -> ParsedData {
+        self.1 = dynamic;
+        self
     }
 
     pub fn get_value(&self, key: &str) -> Option<&Arc<ParsedValue>> {
         self.0.get(key)
     }
+
+    pub fn dynamic_fields(&self) -> Option<&DynamicFields> {
+        self.1.as_ref()
+    }
 }
 
 #[derive(Debug)]
@@ -381,7 +876,6 @@ impl ParsedMessage {
         self.raw
     }
 
-    #[cfg(test)]
     pub fn get_raw(&self) -> &RawMessage {
         &self.raw
     }
@@ -391,6 +885,8 @@ pub trait LogParser {
     fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError>;
 }
 
+pub type DynLogParser = Arc<dyn LogParser + Send + Sync>;
+
 #[cfg(test)]
 mod tests {
     use chrono::TimeZone;
@@ -404,6 +900,65 @@ mod tests {
         println!("{:?}", ret)
     }
 
+    #[test]
+    fn meta_column_value_works() {
+        let received_at = Utc.ymd(2022, 5, 10).and_hms(10, 0, 0);
+        let meta = SourceMeta::new(Arc::from("10.1.2.3:55123"), Arc::from("0.0.0.0:514"), "udp");
+        let msg = RawMessage::new(String::from("abc"))
+            .with_received_at(Some(received_at))
+            .with_meta(Some(Arc::new(meta)));
+        let str_type = ParsedValueType::StrType(64);
+        let value_of = |col: &str, ctype: &ParsedValueType| {
+            msg.meta_column_value(col, ctype)
+                .map(|v| v.to_rc_str().to_string())
+        };
+        assert_eq!(
+            value_of("_remote_addr", &str_type).unwrap(),
+            "10.1.2.3:55123"
+        );
+        assert_eq!(value_of("_listener", &str_type).unwrap(), "0.0.0.0:514");
+        assert_eq!(value_of("_protocol", &str_type).unwrap(), "udp");
+        let ts_type = ParsedValueType::TimeType(TimeTypeFormat::new("%+"));
+        assert_eq!(
+            msg.meta_column_value("_received_at", &ts_type).unwrap(),
+            utc_time_val(received_at)
+        );
+        assert_eq!(
+            value_of("_received_at", &str_type).unwrap(),
+            "2022-05-10T10:00:00+00:00"
+        );
+        assert!(value_of("_whatever", &str_type).is_none());
+        // no metadata outside of the syslog servers
+        let file_msg = RawMessage::new(String::from("abc"));
+        assert!(file_msg
+            .meta_column_value("_remote_addr", &str_type)
+            .is_none());
+    }
+
+    #[test]
+    fn str_overflow_works() {
+        assert_eq!(truncate_str("abcdef", 4, ""), "abcd");
+        assert_eq!(truncate_str("abcdef", 6, STR_TRUNCATION_MARKER), "abcdef");
+        assert_eq!(truncate_str("abcdefg", 6, STR_TRUNCATION_MARKER), "abc...");
+        // "ж" is 2 bytes
+        assert_eq!(truncate_str("жжж", 5, ""), "жж");
+        assert_eq!(truncate_str("жжж", 4, STR_TRUNCATION_MARKER), "...");
+        assert_eq!(truncate_str("жжж", 2, STR_TRUNCATION_MARKER), "ж");
+        assert_eq!(
+            split_str_overflow_policy("str:64:truncate-marker").unwrap(),
+            ("str:64", Some(StrOverflowPolicy::MARKER))
+        );
+        assert_eq!(
+            split_str_overflow_policy("str:64").unwrap(),
+            ("str:64", None)
+        );
+        assert_eq!(
+            split_str_overflow_policy("ts:%H:%M:%S").unwrap(),
+            ("ts:%H:%M:%S", None)
+        );
+        assert!(split_str_overflow_policy("str:64:cut").is_err());
+    }
+
     #[test]
     fn str2val_works() {
         assert_eq!(
@@ -435,23 +990,182 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ip_val_works() {
+        let ip = |s| str2val(s, &ParsedValueType::IpType);
+        assert_eq!(str2type("ip"), Some(ParsedValueType::IpType));
+        assert!(ip("10.0.0.300").is_none());
+        assert!(ip("example.com").is_none());
+        let v4 = ip("10.0.0.9").unwrap();
+        let v4b = ip("10.0.0.10").unwrap();
+        let v6 = ip("::1").unwrap();
+        assert_eq!(v6.to_rc_str().as_ref(), "::1");
+        // numeric, not lexicographic order
+        assert!(v4 < v4b);
+        assert!(v4b < v6);
+        assert!(ParsedValue::LongVal(1) < *v4);
+        assert!(*v4 < ParsedValue::StrVal(Arc::new(String::from("x"))));
+        assert_eq!(
+            *ip("2001:db8::1").unwrap(),
+            *ip("2001:0db8:0:0::1").unwrap()
+        );
+        // equal values hash the same, the strings are coerced by the query comparisons
+        assert_ne!(ParsedValue::StrVal(Arc::new(String::from("10.0.0.9"))), *v4);
+        assert_ne!(*v4, *v4b);
+    }
+
+    #[test]
+    fn duration_val_works() {
+        assert_eq!(
+            str2type("duration:ms"),
+            Some(ParsedValueType::DurationType(1_000_000))
+        );
+        assert!(str2type("duration:weeks").is_none());
+        let dur = |s| str2val(s, &str2type("duration:ms").unwrap()).unwrap();
+        assert_eq!(
+            *dur("1500"),
+            ParsedValue::DurationVal(Duration::milliseconds(1500))
+        );
+        assert_eq!(*dur("1500"), *dur("1.5s"));
+        assert!(dur("999ms") < dur("1s"));
+        assert!(*dur("2m") > *dur("90s"));
+        // equal values hash the same, the strings are coerced by the query comparisons
+        assert_ne!(
+            *dur("1500"),
+            ParsedValue::StrVal(Arc::new(String::from("1.5s")))
+        );
+        assert_eq!(str2duration("1.5"), Some(Duration::milliseconds(1500)));
+        assert_eq!(dur("00:01:02.300").to_rc_str().as_ref(), "1m2.3s");
+    }
+
     #[test]
     fn test_parse_date_syslog() {
+        let ref_time = Utc.ymd(2022, 5, 10).and_hms(0, 0, 0);
         assert_eq!(
-            str2val(
+            str2val_ref(
                 "Apr 22 02:34:54",
-                &ParsedValueType::TimeType(TimeTypeFormat::new("%b %e %H:%M:%S"))
+                &ParsedValueType::TimeType(TimeTypeFormat::new("%b %e %H:%M:%S")),
+                Some(&ref_time)
             )
             .unwrap()
             .as_ref(),
-            &ParsedValue::TimeVal(
-                FixedOffset::east(local_timezone_offset())
-                    .ymd(2022, 4, 22)
-                    .and_hms(2, 34, 54)
-            )
+            &ParsedValue::TimeVal(Local.ymd(2022, 4, 22).and_hms(2, 34, 54).into())
+        );
+        // without a reference time the year is inferred relative to now
+        let now = Utc::now();
+        match str2val(
+            "Apr 22 02:34:54",
+            &ParsedValueType::TimeType(TimeTypeFormat::new("%b %e %H:%M:%S")),
+        )
+        .unwrap()
+        .as_ref()
+        {
+            ParsedValue::TimeVal(ts) => {
+                assert!(*ts <= now + Duration::days(1));
+                assert!(*ts > now - Duration::days(366));
+            }
+            x => panic!("Unexpected value: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_parse_date_timezone() {
+        let ts = |s: &str, fmt: TimeTypeFormat| match str2val(s, &ParsedValueType::TimeType(fmt))
+            .as_deref()
+        {
+            Some(ParsedValue::TimeVal(ts)) => *ts,
+            x => panic!("Unexpected value: {:?}", x),
+        };
+        let sofia = TimeTypeFormat::new("%Y-%m-%d %H:%M:%S:Europe/Sofia");
+        // EET in winter, EEST in summer
+        let winter = ts("2022-01-10 12:00:00", sofia.clone());
+        assert_eq!(winter.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(winter, Utc.ymd(2022, 1, 10).and_hms(10, 0, 0));
+        let summer = ts("2022-07-10 12:00:00", sofia.clone());
+        assert_eq!(summer.offset().local_minus_utc(), 3 * 3600);
+        // 03:30 does not exist on the DST start day, the offset before the change is used
+        let gap = ts("2022-03-27 03:30:00", sofia.clone());
+        assert_eq!(gap, Utc.ymd(2022, 3, 27).and_hms(1, 30, 0));
+        // the explicit zone is kept, the default applies to formats without one
+        let utc = Some("UTC".parse::<Tz>().unwrap());
+        assert_eq!(sofia.clone().with_default_tz(utc), sofia);
+        let default_utc = TimeTypeFormat::new("%Y-%m-%d %H:%M:%S").with_default_tz(utc);
+        assert_eq!(
+            ts("2022-07-10 12:00:00", default_utc),
+            Utc.ymd(2022, 7, 10).and_hms(12, 0, 0)
+        );
+        // not a zone name, just part of the format
+        let not_tz = TimeTypeFormat::new("%H:%M:%S");
+        assert!(not_tz.tz.is_none());
+        assert_eq!(
+            ts_to_timezone(&summer, &"America/New_York".parse::<Tz>().unwrap()).to_string(),
+            "2022-07-10 05:00:00 -04:00"
         );
     }
 
+    #[test]
+    fn test_parse_date_epoch() {
+        let ts = |s: &str, fmt: &str| {
+            str2val(s, &ParsedValueType::TimeType(TimeTypeFormat::new(fmt))).map(|v| {
+                match v.as_ref() {
+                    ParsedValue::TimeVal(ts) => *ts,
+                    x => panic!("Unexpected value: {:?}", x),
+                }
+            })
+        };
+        let expected = Utc.timestamp(1652140319, 123_000_000);
+        assert_eq!(ts("1652140319.123", "epoch_s").unwrap(), expected);
+        assert_eq!(ts("1652140319123", "epoch_ms").unwrap(), expected);
+        assert_eq!(ts("1652140319123000", "epoch_us").unwrap(), expected);
+        assert_eq!(ts("1652140319123000000", "epoch_ns").unwrap(), expected);
+        assert_eq!(
+            ts("1652140319.1234567891", "epoch_s").unwrap(),
+            Utc.timestamp(1652140319, 123_456_789)
+        );
+        assert_eq!(
+            ts("-1.5", "epoch_s").unwrap(),
+            Utc.timestamp(-2, 500_000_000)
+        );
+        // shown in UTC, unless a zone is set
+        assert_eq!(
+            ts("1652140319", "epoch_s").unwrap().to_string(),
+            "2022-05-09 23:51:59 +00:00"
+        );
+        assert_eq!(
+            ts("1652140319", "epoch_s:Europe/Sofia")
+                .unwrap()
+                .to_string(),
+            "2022-05-10 02:51:59 +03:00"
+        );
+        assert!(ts("", "epoch_s").is_none());
+        assert!(ts("1e9", "epoch_s").is_none());
+        assert!(ts("12.3.4", "epoch_ms").is_none());
+        assert!(ts("99999999999999999999", "epoch_s").is_none());
+    }
+
+    #[test]
+    fn test_parse_date_infer_year() {
+        let fmt = ParsedValueType::TimeType(TimeTypeFormat::new("%b %e %H:%M:%S"));
+        let year_of = |s: &str, ref_time: &DateTime<Utc>| match str2val_ref(s, &fmt, Some(ref_time))
+            .as_deref()
+        {
+            Some(ParsedValue::TimeVal(ts)) => ts.year(),
+            x => panic!("Unexpected value: {:?}", x),
+        };
+        // a file spanning new year, modified on Jan 2
+        let jan2 = Utc.ymd(2023, 1, 2).and_hms(12, 0, 0);
+        assert_eq!(year_of("Dec 30 23:59:59", &jan2), 2022);
+        assert_eq!(year_of("Jan  1 00:00:01", &jan2), 2023);
+        assert_eq!(year_of("Jan  2 13:00:00", &jan2), 2023);
+        // a bit ahead of the reference time (e.g. clock skew) is still the same year
+        let dec31 = Utc.ymd(2022, 12, 31).and_hms(23, 0, 0);
+        assert_eq!(year_of("Jan  1 00:10:00", &dec31), 2023);
+        assert_eq!(year_of("Dec 31 22:00:00", &dec31), 2022);
+        // Feb 29 goes back to the latest leap year
+        assert_eq!(year_of("Feb 29 10:00:00", &jan2), 2020);
+        assert!(str2val_ref("Feb 30 10:00:00", &fmt, Some(&jan2)).is_none());
+    }
+
     #[test]
     fn test_parse() {
         let parsed = NaiveDateTime::parse_from_str("Apr 22 02:34:54 2022", "%b %e %H:%M:%S %Y")
@@ -473,4 +1187,31 @@ mod tests {
             .unwrap();
         println!("{}", parsed)
     }
+
+    #[test]
+    fn syslog_pri_works() {
+        let pri = SyslogPri::parse("191").unwrap();
+        assert_eq!(pri.facility(), 23);
+        assert_eq!(pri.severity(), 7);
+        assert_eq!(pri.facility_name(), "local7");
+        assert_eq!(pri.severity_name(), "debug");
+        assert_eq!(
+            pri.column_value("severity", &ParsedValueType::LongType)
+                .unwrap()
+                .as_ref(),
+            &ParsedValue::LongVal(7)
+        );
+        assert_eq!(
+            pri.column_value("facility_name", &ParsedValueType::StrType(16))
+                .unwrap()
+                .as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("local7")))
+        );
+        let pri = SyslogPri::parse("0").unwrap();
+        assert_eq!(pri.facility_name(), "kern");
+        assert_eq!(pri.severity_name(), "emerg");
+        assert!(SyslogPri::parse("192").is_none());
+        assert!(SyslogPri::parse("013").is_none());
+        assert!(SyslogPri::parse("").is_none());
+    }
 }
@@ -0,0 +1,89 @@
+use regex::Regex;
+
+use crate::parser::grok_parser::GrokSchema;
+use crate::parser::parser::*;
+use crate::DynError;
+
+/// A parser using a plain (rust) regular expression with named capture groups,
+/// e.g. `(?P<ts>\S+ \S+) (?P<level>\w+) (?P<message>.*)`. Capture group names are
+/// mapped onto the schema columns lookup names, the same way grok captures are.
+pub struct RegexParser {
+    schema: GrokSchema,
+    regex: Regex,
+}
+
+impl RegexParser {
+    pub fn new(schema: GrokSchema) -> Result<RegexParser, DynError> {
+        let regex = Regex::new(schema.pattern())?;
+        Ok(RegexParser { schema, regex })
+    }
+}
+
+impl LogParser for RegexParser {
+    fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError> {
+        let parsed = match self.regex.captures(msg.as_str()) {
+            Some(caps) => self
+                .schema
+                .lookup_parsed_data(&msg, |c, lnm| match caps.name(lnm) {
+                    Some(m) => c.str2val(m.as_str(), msg.ref_time()),
+                    None => Ok(None),
+                }),
+            None => return Err(LogParseError::new("Regex pattern did not match", msg)),
+        };
+        match parsed {
+            Ok(pd) => Ok(ParsedMessage::new(msg, pd)),
+            Err(desc) => Err(LogParseError::from_string(desc, msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{GrokColumnDef, ParsedValue, ParsedValueType};
+    use std::sync::Arc;
+
+    fn test_regex_schema() -> GrokSchema {
+        GrokSchema::new(
+            String::from(r"^(?P<num>\d+) +(?P<message>.*)$"),
+            vec![
+                GrokColumnDef::new(
+                    Arc::from("num"),
+                    ParsedValueType::LongType,
+                    vec![Arc::new(String::from("num"))],
+                    true,
+                ),
+                GrokColumnDef::new(
+                    Arc::from("message"),
+                    ParsedValueType::StrType(256),
+                    vec![Arc::new(String::from("message"))],
+                    true,
+                ),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("regex"),
+        )
+    }
+
+    #[test]
+    fn regex_parse_works() {
+        let parser = RegexParser::new(test_regex_schema()).unwrap();
+        let parsed = parser
+            .parse(RawMessage::new(String::from("42 line number 42")))
+            .unwrap();
+        assert_eq!(
+            parsed.get_parsed().get_value("num").unwrap().as_ref(),
+            &ParsedValue::LongVal(42)
+        );
+        assert_eq!(
+            parsed.get_parsed().get_value("message").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("line number 42")))
+        );
+        let not_matched = parser.parse(RawMessage::new(String::from("no number here")));
+        assert!(not_matched.is_err());
+        let bad_type = parser.parse(RawMessage::new(String::from("99999999999999999999 x")));
+        assert!(bad_type.is_err());
+    }
+}
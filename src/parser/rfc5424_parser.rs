@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::parser::grok_parser::{GrokColumnDef, GrokSchema};
+use crate::parser::parser::*;
+
+/// A parser for RFC 5424 syslog messages:
+///     VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]
+/// The <PRI> prefix is expected to be already consumed (as the line buffers do) and is
+/// available via the facility/severity built-in columns. The header fields are available
+/// as the version, timestamp, hostname, app_name, procid, msgid and message lookup names,
+/// the raw structured data as sd and each SD-PARAM as sd.<SD-ID>.<PARAM-NAME>,
+/// e.g. sd.origin.ip. Nil ("-") fields are treated as missing.
+pub struct Rfc5424Parser {
+    schema: GrokSchema,
+}
+
+impl Rfc5424Parser {
+    pub fn new(schema: GrokSchema) -> Rfc5424Parser {
+        Rfc5424Parser { schema }
+    }
+}
+
+const RFC5424_HEADER_FIELDS: [&str; 6] = [
+    "version",
+    "timestamp",
+    "hostname",
+    "app_name",
+    "procid",
+    "msgid",
+];
+
+const NIL_VALUE: &str = "-";
+const UTF8_BOM: &str = "\u{feff}";
+
+fn next_header_field<'a>(rest: &mut &'a str, name: &str) -> Result<&'a str, String> {
+    let (field, tail) = match rest.find(' ') {
+        Some(ix) => (&rest[..ix], &rest[ix + 1..]),
+        None => return Err(format!("RFC 5424 message is truncated before {}", name)),
+    };
+    if field.is_empty() {
+        return Err(format!("RFC 5424 message has an empty {}", name));
+    }
+    *rest = tail;
+    Ok(field)
+}
+
+/// (sd.<SD-ID>.<PARAM-NAME>, value) pairs
+type SdParams = Vec<(String, String)>;
+
+/// Parse the STRUCTURED-DATA part starting at the beginning of s,
+/// return the SD-PARAMs and the rest of s
+fn parse_structured_data(s: &str) -> Result<(SdParams, &str), String> {
+    let mut ret = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while chars.next_if(|&(_, c)| c == '[').is_some() {
+        let mut sd_id = String::new();
+        while let Some((_, c)) = chars.next_if(|&(_, c)| c != ' ' && c != ']') {
+            sd_id.push(c);
+        }
+        if sd_id.is_empty() {
+            return Err("RFC 5424 structured data element has an empty SD-ID".to_string());
+        }
+        loop {
+            match chars.next() {
+                Some((_, ']')) => break,
+                Some((_, ' ')) => {}
+                _ => return Err(format!("Unterminated RFC 5424 SD-ELEMENT: {}", sd_id)),
+            }
+            let mut param_name = String::new();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| c != '=' && c != ' ' && c != ']') {
+                param_name.push(c);
+            }
+            if param_name.is_empty() || chars.next().map(|(_, c)| c) != Some('=') {
+                return Err(format!("Invalid RFC 5424 SD-PARAM in: {}", sd_id));
+            }
+            if chars.next().map(|(_, c)| c) != Some('"') {
+                return Err(format!("Unquoted RFC 5424 SD-PARAM value: {}", param_name));
+            }
+            let mut value = String::new();
+            let mut closed = false;
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    // only \" \\ and \] are escapes, any other backslash is kept as is
+                    '\\' => match chars.next_if(|&(_, x)| x == '"' || x == '\\' || x == ']') {
+                        Some((_, x)) => value.push(x),
+                        None => value.push('\\'),
+                    },
+                    c => value.push(c),
+                }
+            }
+            if !closed {
+                return Err(format!(
+                    "Unterminated RFC 5424 SD-PARAM value: {}",
+                    param_name
+                ));
+            }
+            ret.push((format!("sd.{}.{}", sd_id, param_name), value));
+        }
+    }
+    let rest_ix = chars.peek().map(|&(ix, _)| ix).unwrap_or(s.len());
+    Ok((ret, &s[rest_ix..]))
+}
+
+/// Split an RFC 5424 message (without the <PRI>) into (lookup name, value) pairs
+pub fn tokenize_rfc5424(line: &str) -> Result<Vec<(String, String)>, String> {
+    let mut ret = Vec::new();
+    let mut rest = line;
+    for &name in RFC5424_HEADER_FIELDS.iter() {
+        let field = next_header_field(&mut rest, name)?;
+        if name == "version" && (field.len() > 3 || !field.bytes().all(|b| b.is_ascii_digit())) {
+            return Err(format!("Invalid RFC 5424 version: {}", field));
+        }
+        if field != NIL_VALUE {
+            ret.push((name.to_string(), field.to_string()));
+        }
+    }
+    let after_sd = if let Some(tail) = rest.strip_prefix(NIL_VALUE) {
+        tail
+    } else if rest.starts_with('[') {
+        let (params, tail) = parse_structured_data(rest)?;
+        ret.push((
+            "sd".to_string(),
+            rest[..rest.len() - tail.len()].to_string(),
+        ));
+        ret.extend(params);
+        tail
+    } else {
+        return Err("RFC 5424 message has invalid structured data".to_string());
+    };
+    if !after_sd.is_empty() {
+        let msg = match after_sd.strip_prefix(' ') {
+            Some(msg) => msg,
+            None => return Err("RFC 5424 structured data is not followed by a space".to_string()),
+        };
+        let msg = msg.strip_prefix(UTF8_BOM).unwrap_or(msg);
+        ret.push(("message".to_string(), msg.to_string()));
+    }
+    Ok(ret)
+}
+
+fn rfc5424_val(
+    s: &str,
+    c: &GrokColumnDef,
+    lnm: &str,
+    ref_time: Option<&DateTime<Utc>>,
+) -> Result<Option<Arc<ParsedValue>>, String> {
+    match c.col_type() {
+        // the RFC 5424 timestamp is always RFC 3339, no matter the column format
+        ParsedValueType::TimeType(_) if lnm == "timestamp" => Ok(DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|ts| Arc::new(ParsedValue::TimeVal(ts)))
+            .or_else(|| str2val_ref(s, c.col_type(), ref_time))),
+        _ => c.str2val(s, ref_time),
+    }
+}
+
+impl LogParser for Rfc5424Parser {
+    fn parse(&self, msg: RawMessage) -> Result<ParsedMessage, LogParseError> {
+        let pairs = match tokenize_rfc5424(msg.as_str()) {
+            Ok(pairs) => pairs,
+            Err(desc) => return Err(LogParseError::from_string(desc, msg)),
+        };
+        let hm: HashMap<&str, &str> = pairs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let parsed = self
+            .schema
+            .lookup_parsed_data(&msg, |c, lnm| match hm.get(lnm) {
+                Some(s) => rfc5424_val(s, c, lnm, msg.ref_time()),
+                None => Ok(None),
+            });
+        match parsed {
+            Ok(pd) => Ok(ParsedMessage::new(msg, pd)),
+            Err(desc) => Err(LogParseError::from_string(desc, msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_col_def;
+
+    fn test_rfc5424_schema() -> GrokSchema {
+        GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def(
+                    "timestamp",
+                    ParsedValueType::TimeType(TimeTypeFormat::new("%Y-%m-%dT%H:%M:%S%.f%:z")),
+                    true,
+                ),
+                test_col_def("hostname", ParsedValueType::StrType(256), true),
+                test_col_def("app_name", ParsedValueType::StrType(48), false),
+                test_col_def("procid", ParsedValueType::LongType, false),
+                test_col_def("sd.origin.ip", ParsedValueType::StrType(64), false),
+                test_col_def("severity", ParsedValueType::LongType, false),
+                test_col_def("message", ParsedValueType::StrType(256), false),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("rfc5424"),
+        )
+    }
+
+    #[test]
+    fn tokenize_rfc5424_works() {
+        let pairs = tokenize_rfc5424(
+            r#"1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Appl\"ication\]"][origin ip="192.0.2.1"] An application event"#,
+        )
+        .unwrap();
+        let hm: HashMap<&str, &str> = pairs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(hm.get("version"), Some(&"1"));
+        assert_eq!(hm.get("app_name"), Some(&"evntslog"));
+        assert_eq!(hm.get("procid"), None);
+        assert_eq!(hm.get("msgid"), Some(&"ID47"));
+        assert_eq!(hm.get("sd.exampleSDID@32473.iut"), Some(&"3"));
+        assert_eq!(
+            hm.get("sd.exampleSDID@32473.eventSource"),
+            Some(&"Appl\"ication]")
+        );
+        assert_eq!(hm.get("sd.origin.ip"), Some(&"192.0.2.1"));
+        assert_eq!(
+            hm.get("sd"),
+            Some(
+                &r#"[exampleSDID@32473 iut="3" eventSource="Appl\"ication\]"][origin ip="192.0.2.1"]"#
+            )
+        );
+        assert_eq!(hm.get("message"), Some(&"An application event"));
+
+        let no_msg = tokenize_rfc5424("1 - - - - - -").unwrap();
+        assert_eq!(no_msg, vec![("version".to_string(), "1".to_string())]);
+        let bom = tokenize_rfc5424("1 - host app 12 - - \u{feff}hello").unwrap();
+        assert_eq!(bom.last().unwrap().1, "hello");
+
+        assert!(tokenize_rfc5424("1 - host app").is_err());
+        assert!(tokenize_rfc5424("1 - host app - - [origin ip=192.0.2.1] x").is_err());
+        assert!(tokenize_rfc5424("1 - host app - - [origin ip=\"192.0.2.1\" x").is_err());
+        assert!(tokenize_rfc5424("1 - host app - - nosd x").is_err());
+    }
+
+    #[test]
+    fn rfc5424_parse_works() {
+        let parser = Rfc5424Parser::new(test_rfc5424_schema());
+        let parsed = parser
+            .parse(RawMessage::with_pri(
+                String::from(
+                    r#"1 2022-05-10T00:11:22.123+03:00 web1 nginx 4242 - [origin ip="10.1.2.3"] GET / 200"#,
+                ),
+                SyslogPri::new(165),
+            ))
+            .unwrap();
+        let pd = parsed.get_parsed();
+        assert!(matches!(
+            pd.get_value("timestamp").unwrap().as_ref(),
+            ParsedValue::TimeVal(_)
+        ));
+        assert_eq!(
+            pd.get_value("procid").unwrap().as_ref(),
+            &ParsedValue::LongVal(4242)
+        );
+        assert_eq!(
+            pd.get_value("sd.origin.ip").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("10.1.2.3")))
+        );
+        assert_eq!(
+            pd.get_value("severity").unwrap().as_ref(),
+            &ParsedValue::LongVal(5)
+        );
+        assert_eq!(
+            pd.get_value("message").unwrap().as_ref(),
+            &ParsedValue::StrVal(Arc::new(String::from("GET / 200")))
+        );
+        // the timestamp is required
+        let nil_ts = parser.parse(RawMessage::new(String::from("1 - web1 nginx - - - x")));
+        assert!(nil_ts.is_err());
+        let not_rfc5424 = parser.parse(RawMessage::new(String::from(
+            "May 10 00:11:22 web1 nginx[4242]: GET / 200",
+        )));
+        assert!(not_rfc5424.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::parser::parser::*;
+
+/// The timestamp formats tried (in order) when inferring timestamp columns
+const INFER_TS_FORMATS: [&str; 10] = [
+    "%+",
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S,%3f",
+    "%d/%b/%Y:%H:%M:%S %z",
+    "%Y/%m/%d %H:%M:%S",
+    "%a %b %e %H:%M:%S %Y",
+    "%b %e %H:%M:%S",
+];
+
+/// grok names the captures of inline pattern definitions name0, name1, ...
+fn is_internal_capture_name(name: &str) -> bool {
+    match name.strip_prefix("name") {
+        Some(idx) => !idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// What is known about a column from the sampled values
+struct ColumnStats {
+    name: String,
+    first_offset: usize,
+    seen: usize,
+    max_len: usize,
+    is_bool: bool,
+    is_int: bool,
+    is_float: bool,
+    ts_formats: Vec<&'static str>,
+}
+
+impl ColumnStats {
+    fn new(name: &str, first_offset: usize) -> ColumnStats {
+        ColumnStats {
+            name: name.to_string(),
+            first_offset,
+            seen: 0,
+            max_len: 0,
+            is_bool: true,
+            is_int: true,
+            is_float: true,
+            ts_formats: INFER_TS_FORMATS.to_vec(),
+        }
+    }
+
+    fn add_value(&mut self, v: &str) {
+        self.seen += 1;
+        self.max_len = self.max_len.max(v.len());
+        self.is_bool &= v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false");
+        self.is_int &= v.parse::<i64>().is_ok();
+        self.is_float &= v.parse::<f64>().is_ok();
+        self.ts_formats.retain(|&fmt| {
+            str2val(v, &ParsedValueType::TimeType(TimeTypeFormat::new(fmt))).is_some()
+        });
+    }
+
+    fn type_spec(&self) -> String {
+        if self.is_bool {
+            "bool".to_string()
+        } else if self.is_int {
+            "int".to_string()
+        } else if self.is_float {
+            "float".to_string()
+        } else if let Some(fmt) = self.ts_formats.first() {
+            format!("ts:{}", fmt)
+        } else {
+            format!("str:{}", self.max_len)
+        }
+    }
+}
+
+/// Infers the schema columns (as -s column specs) from the named captures of sampled lines
+#[derive(Default)]
+pub struct SchemaInference {
+    columns: HashMap<String, ColumnStats>,
+    sampled: usize,
+    matched: usize,
+}
+
+impl SchemaInference {
+    pub fn new() -> SchemaInference {
+        SchemaInference::default()
+    }
+
+    /// Add the (name, position) captures of a sampled line, None if the line did not match
+    pub fn add_line(&mut self, line: &str, captures: Option<Vec<(String, Range<usize>)>>) {
+        self.sampled += 1;
+        let captures = match captures {
+            Some(captures) => captures,
+            None => return,
+        };
+        self.matched += 1;
+        for (name, pos) in captures {
+            if is_internal_capture_name(&name) {
+                continue;
+            }
+            let offset = pos.start;
+            self.columns
+                .entry(name)
+                .or_insert_with_key(|name| ColumnStats::new(name, offset))
+                .add_value(&line[pos]);
+        }
+    }
+
+    pub fn sampled(&self) -> usize {
+        self.sampled
+    }
+
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    /// The inferred column specs in the order the captures appear in the lines, the
+    /// columns present in all matched lines are required (+)
+    pub fn column_specs(&self) -> Vec<String> {
+        let mut cols = self.columns.values().collect::<Vec<_>>();
+        cols.sort_by(|a, b| (a.first_offset, &a.name).cmp(&(b.first_offset, &b.name)));
+        cols.iter()
+            .map(|c| {
+                let required = if c.seen == self.matched { "+" } else { "" };
+                format!("{}{}:{}", required, c.name, c.type_spec())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(line: &str, names: &[&str]) -> Option<Vec<(String, Range<usize>)>> {
+        let mut start = 0;
+        let positions = line.split(' ').map(|v| {
+            let pos = start..start + v.len();
+            start = pos.end + 1;
+            pos
+        });
+        Some(names.iter().map(|n| n.to_string()).zip(positions).collect())
+    }
+
+    #[test]
+    fn schema_inference_works() {
+        let names = ["ts", "level", "took", "cached", "status", "path"];
+        let mut si = SchemaInference::new();
+        for line in [
+            "2022-05-10T10:00:00Z INFO 1.5 true 200 /",
+            "2022-05-10T10:00:01.123+03:00 WARN 2 false 404 /favicon.ico",
+            "2022-05-10T10:00:02Z ERROR 0.25 TRUE 500",
+        ] {
+            si.add_line(line, captures(line, &names));
+        }
+        si.add_line("garbage", None);
+        assert_eq!(si.sampled(), 4);
+        assert_eq!(si.matched(), 3);
+        assert_eq!(
+            si.column_specs(),
+            vec![
+                "+ts:ts:%+",
+                "+level:str:5",
+                "+took:float",
+                "+cached:bool",
+                "+status:int",
+                "path:str:12",
+            ]
+        );
+    }
+
+    #[test]
+    fn schema_inference_syslog_ts() {
+        let mut si = SchemaInference::new();
+        let line = "May 25 00:30:05";
+        si.add_line(line, Some(vec![("timestamp".to_string(), 0..line.len())]));
+        assert_eq!(si.column_specs(), vec!["+timestamp:ts:%b %e %H:%M:%S"]);
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use crate::ql_processor::ql_schema::QlRowContext;
+use chrono::Duration;
 use sqlparser::ast::{Expr, FunctionArg, FunctionArgExpr};
 use std::borrow::BorrowMut;
 use std::collections::{HashMap, HashSet};
@@ -214,6 +215,8 @@ fn add_parsed_values(
             ParsedValue::NullVal => Ok(v1.clone()),
             ParsedValue::LongVal(x2) => Ok(Arc::new(ParsedValue::LongVal(*x1 + *x2))),
             ParsedValue::DoubleVal(x2) => Ok(Arc::new(ParsedValue::DoubleVal(*x2 + *x1 as f64))),
+            // the (integer) zero the sum starts from
+            ParsedValue::DurationVal(_) if *x1 == 0 => Ok(v2.clone()),
             _ => Err(QueryError::new(
                 "Addition is only supported for numeric values",
             )),
@@ -226,6 +229,11 @@ fn add_parsed_values(
                 "Addition is only supported for numeric values",
             )),
         },
+        ParsedValue::DurationVal(x1) => match v2.as_ref() {
+            ParsedValue::NullVal => Ok(v1.clone()),
+            ParsedValue::DurationVal(x2) => Ok(Arc::new(ParsedValue::DurationVal(*x1 + *x2))),
+            _ => Err(QueryError::new("Durations can only be added to durations")),
+        },
         _ => Err(QueryError::new(
             "Addition is only supported for numeric values",
         )), // ParsedValue::BoolVal(_) => {}
@@ -298,6 +306,14 @@ impl AggExpr for AvgExpr {
                 *x as f64 / self.cnt as f64,
             ))),
             ParsedValue::DoubleVal(x) => Ok(Arc::new(ParsedValue::DoubleVal(*x / self.cnt as f64))),
+            ParsedValue::DurationVal(x) => x
+                .num_nanoseconds()
+                .map(|n| {
+                    Arc::new(ParsedValue::DurationVal(Duration::nanoseconds(
+                        n / self.cnt as i64,
+                    )))
+                })
+                .ok_or_else(|| QueryError::new("Duration value is out of range")),
             _ => Err(QueryError::new(
                 "Averaging is only supported for numeric values",
             )),
@@ -324,9 +340,12 @@ impl AggExpr for AvgExpr {
 
     fn result_type(
         &self,
-        _ctx: &HashMap<Arc<str>, ParsedValueType>,
+        ctx: &HashMap<Arc<str>, ParsedValueType>,
     ) -> Result<ParsedValueType, QueryError> {
-        Ok(ParsedValueType::DoubleType)
+        match self.sum_expr.result_type(ctx) {
+            Ok(ParsedValueType::DurationType(unit)) => Ok(ParsedValueType::DurationType(unit)),
+            _ => Ok(ParsedValueType::DoubleType),
+        }
     }
 }
 
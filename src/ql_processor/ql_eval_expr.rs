@@ -1,12 +1,18 @@
+use chrono::Duration;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::ops::{Add, Div, Mul, Rem, Sub};
 use std::sync::Arc;
 
-use crate::parser::{arc_null_pv, str2val, ParsedValue, ParsedValueType, TimeTypeFormat};
+use crate::parser::{
+    arc_null_pv, parse_duration, str2duration, str2val, ParsedValue, ParsedValueType,
+    TimeTypeFormat, DEFAULT_DURATION_UNIT_NANOS,
+};
 use crate::ql_processor::ql_schema::QlRowContext;
 use crate::ql_processor::QueryError;
 use sqlparser::ast::{
-    BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, ObjectName, UnaryOperator, Value,
+    BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, Ident, ObjectName, UnaryOperator,
+    Value,
 };
 
 // pub struct StaticCtx<'a> {
@@ -155,6 +161,113 @@ where
     }
 }
 
+fn duration_nanos(d: &Duration) -> Result<i64, QueryError> {
+    d.num_nanoseconds()
+        .ok_or_else(|| QueryError::new("Duration value is out of range"))
+}
+
+fn scale_duration(d: &Duration, factor: f64) -> Result<Duration, QueryError> {
+    let nanos = (duration_nanos(d)? as f64 * factor).round();
+    if nanos.is_finite() && nanos.abs() < i64::MAX as f64 {
+        Ok(Duration::nanoseconds(nanos as i64))
+    } else {
+        Err(QueryError::new("Duration value is out of range"))
+    }
+}
+
+/// Timestamp and duration arithmetic: ts - ts is a duration, ts +/- duration is a timestamp,
+/// durations can be added, subtracted, divided and multiplied/divided by numbers.
+/// None if the operands are not timestamps or durations.
+fn eval_duration_op(
+    lval: &ParsedValue,
+    rval: &ParsedValue,
+    op: &BinaryOperator,
+) -> Result<Option<ParsedValue>, QueryError> {
+    let out_of_range = || QueryError::new("Timestamp value is out of range");
+    let number = |v: &ParsedValue| match v {
+        ParsedValue::LongVal(x) => Some(*x as f64),
+        ParsedValue::DoubleVal(x) => Some(*x),
+        _ => None,
+    };
+    let ret = match (lval, rval, op) {
+        (ParsedValue::TimeVal(l), ParsedValue::TimeVal(r), BinaryOperator::Minus) => {
+            ParsedValue::DurationVal(l.signed_duration_since(*r))
+        }
+        (ParsedValue::TimeVal(t), ParsedValue::DurationVal(d), BinaryOperator::Plus)
+        | (ParsedValue::DurationVal(d), ParsedValue::TimeVal(t), BinaryOperator::Plus) => {
+            ParsedValue::TimeVal(t.checked_add_signed(*d).ok_or_else(out_of_range)?)
+        }
+        (ParsedValue::TimeVal(t), ParsedValue::DurationVal(d), BinaryOperator::Minus) => {
+            ParsedValue::TimeVal(t.checked_sub_signed(*d).ok_or_else(out_of_range)?)
+        }
+        (ParsedValue::DurationVal(l), ParsedValue::DurationVal(r), _) => match op {
+            BinaryOperator::Plus => ParsedValue::DurationVal(*l + *r),
+            BinaryOperator::Minus => ParsedValue::DurationVal(*l - *r),
+            BinaryOperator::Divide => {
+                let r = duration_nanos(r)?;
+                if r == 0 {
+                    return Err(QueryError::new("Attempt to divide by zero"));
+                }
+                ParsedValue::DoubleVal(duration_nanos(l)? as f64 / r as f64)
+            }
+            _ => return Ok(None),
+        },
+        (ParsedValue::DurationVal(d), x, BinaryOperator::Multiply)
+        | (x, ParsedValue::DurationVal(d), BinaryOperator::Multiply) => match number(x) {
+            Some(n) => ParsedValue::DurationVal(scale_duration(d, n)?),
+            None => return Ok(None),
+        },
+        (ParsedValue::DurationVal(d), x, BinaryOperator::Divide) => match number(x) {
+            Some(n) => {
+                if n == 0.0 {
+                    return Err(QueryError::new("Attempt to divide by zero"));
+                }
+                ParsedValue::DurationVal(scale_duration(d, 1.0 / n)?)
+            }
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(ret))
+}
+
+/// The result type of eval_duration_op, None if the operands are not timestamps or durations
+fn duration_op_type(
+    lval: &ParsedValueType,
+    rval: &ParsedValueType,
+    op: &BinaryOperator,
+) -> Option<ParsedValueType> {
+    let is_number =
+        |t: &ParsedValueType| matches!(t, ParsedValueType::LongType | ParsedValueType::DoubleType);
+    match (lval, rval, op) {
+        (ParsedValueType::TimeType(_), ParsedValueType::TimeType(_), BinaryOperator::Minus) => {
+            Some(ParsedValueType::DurationType(DEFAULT_DURATION_UNIT_NANOS))
+        }
+        (ParsedValueType::TimeType(_), ParsedValueType::DurationType(_), BinaryOperator::Plus)
+        | (ParsedValueType::TimeType(_), ParsedValueType::DurationType(_), BinaryOperator::Minus) => {
+            Some(lval.clone())
+        }
+        (ParsedValueType::DurationType(_), ParsedValueType::TimeType(_), BinaryOperator::Plus) => {
+            Some(rval.clone())
+        }
+        (ParsedValueType::DurationType(_), ParsedValueType::DurationType(_), _) => match op {
+            BinaryOperator::Plus | BinaryOperator::Minus => Some(lval.clone()),
+            BinaryOperator::Divide => Some(ParsedValueType::DoubleType),
+            _ => None,
+        },
+        (ParsedValueType::DurationType(_), x, BinaryOperator::Multiply)
+        | (ParsedValueType::DurationType(_), x, BinaryOperator::Divide)
+            if is_number(x) =>
+        {
+            Some(lval.clone())
+        }
+        (x, ParsedValueType::DurationType(_), BinaryOperator::Multiply) if is_number(x) => {
+            Some(rval.clone())
+        }
+        _ => None,
+    }
+}
+
 pub fn object_name_to_string(onm: &ObjectName) -> String {
     onm.0
         .iter()
@@ -245,6 +358,13 @@ fn eval_function_date(
         ParsedValue::StrVal(s) => str2val(s.as_str(), &ParsedValueType::TimeType(tformat)).ok_or(
             QueryError::new(&format!("Failed to parse date from string {}", s.as_str())),
         ),
+        // numeric (e.g. epoch) values
+        ParsedValue::LongVal(_) | ParsedValue::DoubleVal(_) => {
+            let s = curv.to_rc_str();
+            str2val(s.as_ref(), &ParsedValueType::TimeType(tformat)).ok_or(QueryError::new(
+                &format!("Failed to parse date from number {}", s),
+            ))
+        }
         x => Err(QueryError::not_impl(&format!(
             "Unsupported argument type for DATE function {:?}",
             x
@@ -252,6 +372,197 @@ fn eval_function_date(
     }
 }
 
+fn function_args(
+    fun: &Function,
+    ctx: &QlRowContext,
+    dctx: &mut LazyContext,
+    num_args: usize,
+) -> Result<Vec<Arc<ParsedValue>>, QueryError> {
+    let fun_name = object_name_to_string(&fun.name);
+    if fun.args.len() != num_args {
+        return Err(QueryError::new(&format!(
+            "{} function requires {} argument(s)",
+            fun_name, num_args
+        )));
+    }
+    fun.args
+        .iter()
+        .map(|a| func_arg_to_pv(a, ctx, dctx))
+        .collect()
+}
+
+/// The ip address of an ip or string value, None for NULL or a string which is not an address
+fn ip_arg(pv: &ParsedValue, fun_name: &str) -> Result<Option<IpAddr>, QueryError> {
+    match pv {
+        ParsedValue::IpVal(ip) => Ok(Some(*ip)),
+        ParsedValue::StrVal(s) => Ok(s.parse::<IpAddr>().ok()),
+        ParsedValue::NullVal => Ok(None),
+        x => Err(QueryError::new(&format!(
+            "Unsupported argument type for {} function {:?}",
+            fun_name, x
+        ))),
+    }
+}
+
+/// Parse an address/prefix-length network, e.g. 10.0.0.0/8 (a single address without a prefix)
+fn parse_cidr(s: &str) -> Result<(IpAddr, u8), QueryError> {
+    let invalid = || QueryError::new(&format!("Invalid CIDR network: {}", s));
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (s, None),
+    };
+    let addr = addr.trim().parse::<IpAddr>().map_err(|_| invalid())?;
+    let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(p) => p.trim().parse::<u8>().map_err(|_| invalid())?,
+        None => max_prefix,
+    };
+    if prefix > max_prefix {
+        return Err(invalid());
+    }
+    Ok((addr, prefix))
+}
+
+fn ip_in_cidr(ip: &IpAddr, net: &IpAddr, prefix: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(*net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(*net) & mask
+        }
+        // IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) match IPv4 networks
+        (IpAddr::V6(ip), IpAddr::V4(_)) => match ip.to_ipv4_mapped() {
+            Some(ip4) => ip_in_cidr(&IpAddr::V4(ip4), net, prefix),
+            None => false,
+        },
+        (IpAddr::V4(_), IpAddr::V6(_)) => false,
+    }
+}
+
+/// IP_IN_CIDR(ip, 'network/prefix') - whether the address is in the network
+fn eval_function_ip_in_cidr(
+    fun: &Function,
+    ctx: &QlRowContext,
+    dctx: &mut LazyContext,
+) -> Result<Arc<ParsedValue>, QueryError> {
+    let args = function_args(fun, ctx, dctx, 2)?;
+    let ip = match ip_arg(&args[0], "IP_IN_CIDR")? {
+        Some(ip) => ip,
+        None => return Ok(arc_null_pv()),
+    };
+    let (net, prefix) = match args[1].as_ref() {
+        ParsedValue::StrVal(s) => parse_cidr(s.as_str())?,
+        ParsedValue::IpVal(ip) => (*ip, if ip.is_ipv4() { 32 } else { 128 }),
+        ParsedValue::NullVal => return Ok(arc_null_pv()),
+        x => {
+            return Err(QueryError::new(&format!(
+                "IP_IN_CIDR network must be a string: {:?}",
+                x
+            )))
+        }
+    };
+    Ok(Arc::new(ParsedValue::BoolVal(ip_in_cidr(
+        &ip, &net, prefix,
+    ))))
+}
+
+/// DURATION('1.5s') - a duration value, numbers without a unit are seconds
+fn eval_function_duration(
+    fun: &Function,
+    ctx: &QlRowContext,
+    dctx: &mut LazyContext,
+) -> Result<Arc<ParsedValue>, QueryError> {
+    let args = function_args(fun, ctx, dctx, 1)?;
+    match args[0].as_ref() {
+        ParsedValue::DurationVal(_) | ParsedValue::NullVal => Ok(Arc::clone(&args[0])),
+        ParsedValue::StrVal(_) | ParsedValue::LongVal(_) | ParsedValue::DoubleVal(_) => {
+            let s = args[0].to_rc_str();
+            parse_duration(s.as_ref(), DEFAULT_DURATION_UNIT_NANOS)
+                .map(|d| Arc::new(ParsedValue::DurationVal(d)))
+                .ok_or_else(|| QueryError::new(&format!("Failed to parse duration from {}", s)))
+        }
+        x => Err(QueryError::new(&format!(
+            "Unsupported argument type for DURATION function {:?}",
+            x
+        ))),
+    }
+}
+
+/// The operands of a comparison with a string compared to a duration or an ip value
+/// parsed as one, e.g. took > '1.5s' or client_ip = '10.0.0.1', None if there is nothing
+/// to coerce
+fn coerce_comparison(lval: &ParsedValue, rval: &ParsedValue) -> Option<(ParsedValue, ParsedValue)> {
+    match (lval, rval) {
+        (ParsedValue::IpVal(_), ParsedValue::StrVal(s)) => {
+            let ip = s.parse::<IpAddr>().ok()?;
+            Some((lval.clone(), ParsedValue::IpVal(ip)))
+        }
+        (ParsedValue::StrVal(s), ParsedValue::IpVal(_)) => {
+            let ip = s.parse::<IpAddr>().ok()?;
+            Some((ParsedValue::IpVal(ip), rval.clone()))
+        }
+        (ParsedValue::DurationVal(_), ParsedValue::StrVal(s)) => {
+            let d = str2duration(s)?;
+            Some((lval.clone(), ParsedValue::DurationVal(d)))
+        }
+        (ParsedValue::StrVal(s), ParsedValue::DurationVal(_)) => {
+            let d = str2duration(s)?;
+            Some((ParsedValue::DurationVal(d), rval.clone()))
+        }
+        _ => None,
+    }
+}
+
+fn eval_comparison(
+    lval: &ParsedValue,
+    rval: &ParsedValue,
+    op: &BinaryOperator,
+) -> Result<bool, QueryError> {
+    // the unit of a bare number is ambiguous, the values do not know their column's
+    if let (ParsedValue::DurationVal(_), ParsedValue::LongVal(_) | ParsedValue::DoubleVal(_))
+    | (ParsedValue::LongVal(_) | ParsedValue::DoubleVal(_), ParsedValue::DurationVal(_)) =
+        (lval, rval)
+    {
+        return Err(QueryError::new(&format!(
+            "Can not compare a duration to a number without a unit, use e.g. '500ms': {} {} {}",
+            lval.to_rc_str(),
+            op,
+            rval.to_rc_str()
+        )));
+    }
+    let coerced = coerce_comparison(lval, rval);
+    let (l, r) = match &coerced {
+        Some((l, r)) => (l, r),
+        None => (lval, rval),
+    };
+    match op {
+        BinaryOperator::Gt => Ok(l > r),
+        BinaryOperator::Lt => Ok(l < r),
+        BinaryOperator::GtEq => Ok(l >= r),
+        BinaryOperator::LtEq => Ok(l <= r),
+        BinaryOperator::Eq => Ok(l == r),
+        BinaryOperator::NotEq => Ok(l != r),
+        x => Err(QueryError::unexpected(&format!("not a comparison: {}", x))),
+    }
+}
+
+/// IP_FAMILY(ip) - 4 or 6
+fn eval_function_ip_family(
+    fun: &Function,
+    ctx: &QlRowContext,
+    dctx: &mut LazyContext,
+) -> Result<Arc<ParsedValue>, QueryError> {
+    let args = function_args(fun, ctx, dctx, 1)?;
+    Ok(match ip_arg(&args[0], "IP_FAMILY")? {
+        Some(IpAddr::V4(_)) => Arc::new(ParsedValue::LongVal(4)),
+        Some(IpAddr::V6(_)) => Arc::new(ParsedValue::LongVal(6)),
+        None => arc_null_pv(),
+    })
+}
+
 fn eval_function(
     fun: &Function,
     ctx: &QlRowContext,
@@ -260,6 +571,9 @@ fn eval_function(
     let fun_name = object_name_to_string(&fun.name);
     match fun_name.as_str() {
         "DATE" => eval_function_date(fun, ctx, dctx),
+        "DURATION" => eval_function_duration(fun, ctx, dctx),
+        "IP_IN_CIDR" => eval_function_ip_in_cidr(fun, ctx, dctx),
+        "IP_FAMILY" => eval_function_ip_family(fun, ctx, dctx),
         _ => Err(QueryError::not_supported(&format!("Function: {:?}", fun))),
     }
 }
@@ -274,10 +588,49 @@ fn eval_function_type(
             let tfmt = date_function_format(&mut fun.args.iter())?;
             Ok(ParsedValueType::TimeType(tfmt))
         }
+        "DURATION" => Ok(ParsedValueType::DurationType(DEFAULT_DURATION_UNIT_NANOS)),
+        "IP_IN_CIDR" => Ok(ParsedValueType::BoolType),
+        "IP_FAMILY" => Ok(ParsedValueType::LongType),
         _ => Err(QueryError::not_supported(&format!("Function: {:?}", fun))),
     }
 }
 
+/// The column name and key of a column['key'] expression
+fn map_access_name(column: &Expr, keys: &[Expr]) -> Result<(String, String), QueryError> {
+    let column = match column {
+        Expr::Identifier(id) => id.value.clone(),
+        Expr::CompoundIdentifier(ids) => ids
+            .iter()
+            .map(|id| id.value.as_str())
+            .collect::<Vec<_>>()
+            .join("."),
+        x => return Err(QueryError::not_supported(&format!("Map access on {}", x))),
+    };
+    let key = match keys {
+        [Expr::Value(Value::SingleQuotedString(k))]
+        | [Expr::Value(Value::DoubleQuotedString(k))]
+        | [Expr::Identifier(Ident { value: k, .. })] => k.clone(),
+        [Expr::Value(Value::Number(k, _))] => k.clone(),
+        _ => {
+            return Err(QueryError::not_supported(
+                "Map access with other than a single (string) key",
+            ))
+        }
+    };
+    Ok((column, key))
+}
+
+/// Dotted column names (e.g. http.status from the JSON parser) are parsed as compound
+/// identifiers, resolve them as a single column name
+fn compound_to_identifier(ids: &[Ident]) -> Expr {
+    Expr::Identifier(Ident::new(
+        ids.iter()
+            .map(|id| id.value.as_str())
+            .collect::<Vec<_>>()
+            .join("."),
+    ))
+}
+
 pub fn eval_expr(
     expr: &Expr,
     ctx: &QlRowContext,
@@ -297,7 +650,7 @@ pub fn eval_expr(
                 }
             }
         }
-        Expr::CompoundIdentifier(_) => Err(QueryError::not_impl("Expr::CompoundIdentifier")),
+        Expr::CompoundIdentifier(ids) => eval_expr(&compound_to_identifier(ids), ctx, dctx),
         Expr::IsNull(x) => {
             let res = eval_expr(x, ctx, dctx)?;
             Ok(Arc::new(ParsedValue::BoolVal(
@@ -345,7 +698,10 @@ pub fn eval_expr(
                     (ParsedValue::DoubleVal(lv), ParsedValue::DoubleVal(rv)) => Ok(Arc::new(
                         ParsedValue::DoubleVal(eval_aritmethic_op(*lv, *rv, &op)?),
                     )),
-                    _ => Err(QueryError::incompatible_types(&lval, &rvalv, &op)),
+                    (lv, rv) => match eval_duration_op(lv, rv, op)? {
+                        Some(v) => Ok(Arc::new(v)),
+                        None => Err(QueryError::incompatible_types(&lval, &rvalv, op)),
+                    },
                 }
             };
             match op {
@@ -361,13 +717,17 @@ pub fn eval_expr(
                         [lstr.as_ref(), rstr.as_ref()].join(""),
                     ))))
                 }
-                BinaryOperator::Gt => Ok(Arc::new(ParsedValue::BoolVal(lval > rval()?))),
-                BinaryOperator::Lt => Ok(Arc::new(ParsedValue::BoolVal(lval < rval()?))),
-                BinaryOperator::GtEq => Ok(Arc::new(ParsedValue::BoolVal(lval >= rval()?))),
-                BinaryOperator::LtEq => Ok(Arc::new(ParsedValue::BoolVal(lval > rval()?))),
+                BinaryOperator::Gt
+                | BinaryOperator::Lt
+                | BinaryOperator::GtEq
+                | BinaryOperator::LtEq
+                | BinaryOperator::Eq
+                | BinaryOperator::NotEq => Ok(Arc::new(ParsedValue::BoolVal(eval_comparison(
+                    &lval,
+                    rval()?.as_ref(),
+                    op,
+                )?))),
                 BinaryOperator::Spaceship => Err(QueryError::not_impl("BinaryOperator::Spaceship")),
-                BinaryOperator::Eq => Ok(Arc::new(ParsedValue::BoolVal(lval == rval()?))),
-                BinaryOperator::NotEq => Ok(Arc::new(ParsedValue::BoolVal(lval != rval()?))),
                 BinaryOperator::And => Ok(Arc::new(ParsedValue::BoolVal(
                     lval.as_bool().unwrap_or(false) && rval()?.as_bool().unwrap_or(false),
                 ))),
@@ -481,7 +841,13 @@ pub fn eval_expr(
             }
         }
         Expr::TypedString { .. } => Err(QueryError::not_impl("Expr::TypedString")),
-        Expr::MapAccess { .. } => Err(QueryError::not_impl("Expr::MapAccess")),
+        // the extracted key/value pairs, e.g. message['user']
+        Expr::MapAccess { column, keys } => {
+            let (column, key) = map_access_name(column, keys)?;
+            Ok(ctx
+                .get_dynamic_value(&format!("{}.{}", column, key))
+                .unwrap_or_else(arc_null_pv))
+        }
         Expr::Function(f) => eval_function(f, ctx, dctx),
         Expr::Case { .. } => Err(QueryError::not_impl("Expr::Case")),
         Expr::Exists(_) => Err(QueryError::not_impl("Expr::Exists")),
@@ -515,7 +881,7 @@ pub fn eval_expr_type(
                 }
             }
         }
-        Expr::CompoundIdentifier(_) => Err(QueryError::not_impl("Expr::CompoundIdentifier")),
+        Expr::CompoundIdentifier(ids) => eval_expr_type(&compound_to_identifier(ids), ctx),
         Expr::IsNull(_x) => Ok(ParsedValueType::BoolType),
         Expr::IsNotNull(_x) => Ok(ParsedValueType::BoolType),
         Expr::IsDistinctFrom(_, _) => Err(QueryError::not_impl("Expr::IsDistinctFrom")),
@@ -533,8 +899,11 @@ pub fn eval_expr_type(
             //         op, &lval, &rval
             //     ))
             // };
-            let arithmetic_op_type = |_op| {
-                if lval == ParsedValueType::DoubleType || rval == ParsedValueType::DoubleType {
+            let arithmetic_op_type = |op| {
+                if let Some(t) = duration_op_type(&lval, &rval, op) {
+                    Ok(t)
+                } else if lval == ParsedValueType::DoubleType || rval == ParsedValueType::DoubleType
+                {
                     Ok(ParsedValueType::DoubleType)
                 } else {
                     Ok(ParsedValueType::LongType)
@@ -663,7 +1032,14 @@ pub fn eval_expr_type(
             }
         }
         Expr::TypedString { .. } => Err(QueryError::not_impl("Expr::TypedString")),
-        Expr::MapAccess { .. } => Err(QueryError::not_impl("Expr::MapAccess")),
+        Expr::MapAccess { column, keys } => {
+            // a value is a part of the column one
+            let (column, _) = map_access_name(column, keys)?;
+            match ctx.get(column.as_str()) {
+                Some(ParsedValueType::StrType(n)) => Ok(ParsedValueType::StrType(*n)),
+                _ => Ok(ParsedValueType::StrType(256)),
+            }
+        }
         Expr::Function(f) => eval_function_type(f, ctx),
         Expr::Case { .. } => Err(QueryError::not_impl("Expr::Case")),
         Expr::Exists(_) => Err(QueryError::not_impl("Expr::Exists")),
@@ -693,3 +1069,26 @@ pub fn eval_integer_expr(
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_in_cidr_works() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let (net, prefix) = parse_cidr("10.0.0.0/8").unwrap();
+        assert!(ip_in_cidr(&ip("10.200.1.2"), &net, prefix));
+        assert!(!ip_in_cidr(&ip("11.0.0.1"), &net, prefix));
+        assert!(ip_in_cidr(&ip("::ffff:10.0.0.1"), &net, prefix));
+        let (net, prefix) = parse_cidr("2001:db8::/32").unwrap();
+        assert!(ip_in_cidr(&ip("2001:db8:1::5"), &net, prefix));
+        assert!(!ip_in_cidr(&ip("2001:db9::5"), &net, prefix));
+        assert!(!ip_in_cidr(&ip("10.0.0.1"), &net, prefix));
+        let (net, prefix) = parse_cidr("0.0.0.0/0").unwrap();
+        assert!(ip_in_cidr(&ip("192.168.1.1"), &net, prefix));
+        assert_eq!(parse_cidr("192.168.1.1").unwrap().1, 32);
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("10.0.0/8").is_err());
+    }
+}
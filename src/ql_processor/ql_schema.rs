@@ -1,12 +1,13 @@
 use crate::parser::{
-    GrokColumnDef, GrokSchema, ParsedMessage, ParsedValue, ParsedValueType, ParserColDef,
-    ParserSchema, RawMessage,
+    ts_to_timezone, DerivedColumnDef, DerivedValue, DynamicFields, GrokColumnDef, GrokSchema,
+    ParsedMessage, ParsedValue, ParsedValueType, ParserColDef, ParserSchema, RawMessage,
 };
 use crate::ql_processor::ql_agg_expr::{get_agg_expr, AggExpr, DynAggExpr};
 use crate::ql_processor::ql_eval_expr::{
     eval_expr_type, object_name_to_string, LazyContext, LazyExpr,
 };
 use crate::ql_processor::SqlSelectQuery;
+use chrono_tz::Tz;
 use sqlparser::ast::{BinaryOperator, Expr, SelectItem};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
@@ -60,18 +61,28 @@ impl Error for QueryError {}
 #[derive(Clone)]
 pub struct QlColDef {
     pcd: ParserColDef,
+    derived: Option<DerivedValue>,
 }
 
 impl QlColDef {
     pub fn new(name: &str, pv_type: ParsedValueType, required: bool) -> Self {
         Self {
             pcd: ParserColDef::new(name, &pv_type, required),
+            derived: None,
         }
     }
 
     pub fn from(gcd: &GrokColumnDef) -> Self {
         Self {
             pcd: ParserColDef::new(gcd.col_name(), gcd.col_type(), gcd.required()),
+            derived: None,
+        }
+    }
+
+    pub fn from_derived(dcd: &DerivedColumnDef) -> Self {
+        Self {
+            pcd: dcd.pcd().clone(),
+            derived: Some(dcd.value().clone()),
         }
     }
 
@@ -99,6 +110,7 @@ impl QlSchema {
             .columns()
             .iter()
             .map(|gcd| QlColDef::from(gcd))
+            .chain(gs.derived_columns().iter().map(QlColDef::from_derived))
             .collect::<Vec<_>>();
         Self {
             name: Arc::from(gs.output_name()),
@@ -121,13 +133,19 @@ impl ParserSchema for QlSchema {
 pub struct QlRow {
     raw: Option<RawMessage>,
     data: Vec<(Arc<str>, Arc<ParsedValue>)>,
+    // the parsed fields which are not columns, e.g. the extracted key/value pairs
+    dynamic: Option<DynamicFields>,
 }
 
 pub type QlRowBatch = Vec<QlRow>;
 
 impl QlRow {
     pub fn new(raw: Option<RawMessage>, data: Vec<(Arc<str>, Arc<ParsedValue>)>) -> Self {
-        Self { raw, data }
+        Self {
+            raw,
+            data,
+            dynamic: None,
+        }
     }
 
     pub fn from_parsed_message(pm: ParsedMessage, schema: &QlSchema) -> QlRow {
@@ -136,19 +154,22 @@ impl QlRow {
             .cols
             .iter()
             .map(|qc| {
-                (
-                    qc.name().clone(),
-                    parsed
+                let val = match &qc.derived {
+                    Some(derived) => derived.value_of(pm.get_raw()),
+                    None => parsed
                         //TODO - is it possible to consume the values instead of cloning???
                         .get_value(qc.name().as_ref())
-                        .map(|x| x.clone())
+                        .cloned()
                         .unwrap_or(Arc::new(ParsedValue::NullVal)),
-                )
+                };
+                (qc.name().clone(), val)
             })
             .collect::<Vec<_>>();
+        let dynamic = parsed.dynamic_fields().cloned();
         Self {
             raw: Some(pm.consume_raw()),
             data: rdata,
+            dynamic,
         }
     }
 
@@ -160,6 +181,16 @@ impl QlRow {
         &self.data
     }
 
+    /// Convert the timestamp values to the given time zone
+    pub fn with_timezone(mut self, tz: &Tz) -> QlRow {
+        for (_, v) in self.data.iter_mut() {
+            if let ParsedValue::TimeVal(ts) = v.as_ref() {
+                *v = Arc::new(ParsedValue::TimeVal(ts_to_timezone(ts, tz)));
+            }
+        }
+        self
+    }
+
     pub fn data_as_strs(&self) -> Vec<Rc<str>> {
         self.data()
             .iter()
@@ -199,6 +230,14 @@ impl<'a> QlRowContext<'a> {
     pub fn get_value(&self, key: &str) -> Option<Arc<ParsedValue>> {
         self.lookup_map.get(key).map(|v| v.clone())
     }
+
+    /// A parsed field which is not a column, e.g. an extracted key/value pair (message.user)
+    pub fn get_dynamic_value(&self, key: &str) -> Option<Arc<ParsedValue>> {
+        self.row
+            .and_then(|r| r.dynamic.as_ref())
+            .and_then(|d| d.get(key))
+            .cloned()
+    }
 }
 
 pub enum QlSelectItem {
@@ -348,10 +387,10 @@ impl QlSelectCols {
 }
 
 fn get_select_expr_name(expr: &Expr) -> Option<String> {
-    if let Expr::Identifier(idt) = expr {
-        Some(idt.value.clone())
-    } else {
-        None
+    match expr {
+        Expr::Identifier(idt) => Some(idt.value.clone()),
+        Expr::CompoundIdentifier(_) => Some(expr.to_string()),
+        _ => None,
     }
 }
 
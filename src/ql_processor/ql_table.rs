@@ -431,7 +431,10 @@ pub mod tests {
     use sqlparser::ast::Value;
     use std::sync::Arc;
 
-    use crate::parser::{test_syslog_schema, GrokSchema, LogParser};
+    use crate::parser::{
+        str2type, test_col_def, test_syslog_schema, GrokSchema, JsonParser, KvExtractor, LogParser,
+        ParsedValueType, ParserSchema, TimeTypeFormat,
+    };
     use crate::ql_processor::{get_res_cols, QlMemTable, QlSchema};
     use crate::{DynError, GrokParser};
 
@@ -482,10 +485,18 @@ pub mod tests {
 
     pub fn input_to_table_test(input: &'static str, schema: GrokSchema) -> QlMemTable {
         let ql_schema = Arc::new(QlSchema::from(&schema));
+        let parser = GrokParser::new(schema).unwrap();
+        parsed_input_to_table_test(input, ql_schema, &parser)
+    }
+
+    pub fn parsed_input_to_table_test(
+        input: &'static str,
+        ql_schema: Arc<QlSchema>,
+        parser: &dyn LogParser,
+    ) -> QlMemTable {
         let mut ret = QlMemTable::new(ql_schema.clone());
-        let mut lb = LinesBuffer::new(false);
+        let mut lb = LinesBuffer::new(None);
         lb.get_buf().put(input.as_bytes());
-        let parser = GrokParser::new(schema).unwrap();
         for ln in lb.flush() {
             if let Ok(parsed) = parser.parse(ln) {
                 ret.write_row(QlRow::from_parsed_message(parsed, ql_schema.as_ref()))
@@ -495,6 +506,15 @@ pub mod tests {
         ret
     }
 
+    /// The result rows as comma separated values, for comparing
+    fn rows_as_csv(table: &QlMemTable) -> Vec<String> {
+        table
+            .get_rows()
+            .iter()
+            .map(|r| r.data_as_strs().join(","))
+            .collect()
+    }
+
     fn test_query(query: &str, input: &'static str) -> Result<Box<QlMemTable>, DynError> {
         let schema = test_syslog_schema();
         let mut in_table = input_to_table_test(input, schema);
@@ -641,4 +661,215 @@ pub mod tests {
         let rt = test_query(query, LINES1).unwrap();
         assert!(rt.get_rows().len() == 2)
     }
+
+    #[test]
+    fn test_process_sql_json_paths() {
+        let schema = GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def("http.method", ParsedValueType::StrType(16), true),
+                test_col_def("http.status", ParsedValueType::LongType, true),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        );
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        let mut in_table = parsed_input_to_table_test(
+            "{\"http\":{\"method\":\"GET\",\"status\":200}}\n\
+            {\"http\":{\"method\":\"GET\",\"status\":404}}\n\
+            {\"http\":{\"method\":\"POST\",\"status\":500}}\n",
+            ql_schema.clone(),
+            &JsonParser::new(schema),
+        );
+        let mut rrt = QlMemTable::new(ql_schema);
+        process_sql_test(
+            "select http.method, http.status from json where http.status >= 400",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(rrt.get_rows().len(), 2);
+        assert_eq!(rrt.ql_schema().col_defs()[1].name().as_ref(), "http.status");
+    }
+
+    #[test]
+    fn test_process_sql_epoch_date() {
+        let schema = GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def(
+                    "ts",
+                    ParsedValueType::TimeType(TimeTypeFormat::new("epoch_ms")),
+                    true,
+                ),
+                test_col_def("msg", ParsedValueType::StrType(16), true),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        );
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        let mut in_table = parsed_input_to_table_test(
+            "{\"ts\":1652140318999,\"msg\":\"before\"}\n\
+            {\"ts\":1652140319123,\"msg\":\"after\"}\n\
+            {\"ts\":\"1652140320000.5\",\"msg\":\"after\"}\n",
+            ql_schema.clone(),
+            &JsonParser::new(schema),
+        );
+        let mut rrt = QlMemTable::new(ql_schema);
+        process_sql_test(
+            "select ts, msg from json where ts >= DATE(\"epoch_s\", \"1652140319.000\")",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(rrt.get_rows().len(), 2);
+    }
+
+    #[test]
+    fn test_process_sql_ip_functions() {
+        let schema = GrokSchema::new(
+            String::new(),
+            vec![test_col_def("client", ParsedValueType::IpType, true)],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        );
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        let input = "{\"client\":\"10.1.2.3\"}\n\
+            {\"client\":\"192.168.0.1\"}\n\
+            {\"client\":\"2001:db8::1\"}\n\
+            {\"client\":\"not-an-ip\"}\n";
+        let parser = JsonParser::new(schema);
+        let mut in_table = parsed_input_to_table_test(input, ql_schema.clone(), &parser);
+        let mut rrt = QlMemTable::new(ql_schema.clone());
+        process_sql_test(
+            "select client, IP_FAMILY(client) as family from json \
+            where IP_IN_CIDR(client, '10.0.0.0/8') or IP_IN_CIDR(client, '2001:db8::/32')",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(rows_as_csv(&rrt), vec!["10.1.2.3,4", "2001:db8::1,6"]);
+
+        // the string literals are compared as ips
+        let mut in_table = parsed_input_to_table_test(input, ql_schema.clone(), &parser);
+        let mut rrt = QlMemTable::new(ql_schema);
+        process_sql_test(
+            "select client from json where client = '2001:0db8:0:0::1' or client < '10.2.0.0'",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(rows_as_csv(&rrt), vec!["10.1.2.3", "2001:db8::1"]);
+    }
+
+    #[test]
+    fn test_process_sql_durations() {
+        let schema = GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def("path", ParsedValueType::StrType(16), true),
+                test_col_def(
+                    "start",
+                    ParsedValueType::TimeType(TimeTypeFormat::new("%+")),
+                    true,
+                ),
+                test_col_def(
+                    "end",
+                    ParsedValueType::TimeType(TimeTypeFormat::new("%+")),
+                    true,
+                ),
+                test_col_def("took", str2type("duration:ms").unwrap(), true),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        );
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        let input = "{\"path\":\"/a\",\"start\":\"2022-05-10T10:00:00Z\",\"end\":\"2022-05-10T10:00:01.5Z\",\"took\":\"12ms\"}\n\
+            {\"path\":\"/a\",\"start\":\"2022-05-10T10:00:00Z\",\"end\":\"2022-05-10T10:00:00.5Z\",\"took\":\"1.5s\"}\n\
+            {\"path\":\"/b\",\"start\":\"2022-05-10T10:00:00Z\",\"end\":\"2022-05-10T10:01:02.3Z\",\"took\":300}\n";
+        let parser = JsonParser::new(schema);
+        let mut in_table = parsed_input_to_table_test(input, ql_schema.clone(), &parser);
+        let mut rrt = QlMemTable::new(ql_schema.clone());
+        process_sql_test(
+            "select path, sum(took) as total, avg(took) as avg, min(took) as min, \
+            max(end - start) as max_elapsed, count() as cnt from json \
+            where took * 2 > '100ms' group by 1",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(
+            rows_as_csv(&rrt),
+            vec!["/a,1.5s,1.5s,1.5s,500ms,1", "/b,300ms,300ms,300ms,1m2.3s,1"]
+        );
+        assert_eq!(
+            rrt.ql_schema().col_defs()[1].pv_type(),
+            &ParsedValueType::DurationType(1_000_000)
+        );
+
+        // strings with units on either side of the comparison
+        let mut in_table = parsed_input_to_table_test(input, ql_schema.clone(), &parser);
+        let mut rrt = QlMemTable::new(ql_schema.clone());
+        process_sql_test(
+            "select path, took from json where took > '1s' or took = '300ms' or '10ms' >= took",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(rows_as_csv(&rrt), vec!["/a,1.5s", "/b,300ms"]);
+
+        // a number without a unit is ambiguous (the column is in ms)
+        let mut in_table = parsed_input_to_table_test(input, ql_schema.clone(), &parser);
+        let mut rrt = QlMemTable::new(ql_schema);
+        let res = process_sql_test(
+            "select path, took from json where took > 500",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_process_sql_kv_extract() {
+        let schema = GrokSchema::new(
+            String::new(),
+            vec![
+                test_col_def("message", ParsedValueType::StrType(256), true),
+                test_col_def("message.status", ParsedValueType::LongType, false),
+            ],
+            false,
+            vec![],
+            false,
+            String::from("json"),
+        )
+        .with_kv_extractors(vec![KvExtractor::parse("message").unwrap()]);
+        let ql_schema = Arc::new(QlSchema::from(&schema));
+        let mut in_table = parsed_input_to_table_test(
+            "{\"message\":\"login user=bob status=200\"}\n\
+            {\"message\":\"logout user=\\\"alice b\\\" status=500\"}\n\
+            {\"message\":\"no pairs\"}\n",
+            ql_schema.clone(),
+            &JsonParser::new(schema),
+        );
+        let mut rrt = QlMemTable::new(ql_schema);
+        process_sql_test(
+            "select message['user'] as user, message.status as status, \
+            message['missing'] as missing from json",
+            Box::new(&mut in_table),
+            Box::new(&mut rrt),
+        )
+        .unwrap();
+        assert_eq!(
+            rows_as_csv(&rrt),
+            vec!["bob,200,NULL", "alice b,500,NULL", "NULL,NULL,NULL"]
+        );
+    }
 }
@@ -22,7 +22,7 @@ impl SqlCreateCol {
         }
     }
 
-    fn from_parser_col_def(pcd: &ParserColDef) -> SqlCreateCol {
+    fn from_parser_col_def(pcd: &ParserColDef, ip_type: &Arc<str>) -> SqlCreateCol {
         let sql_type = match pcd.pv_type() {
             ParsedValueType::NullType => {
                 Arc::from("NULL") // this shouldn't happen?
@@ -31,6 +31,9 @@ impl SqlCreateCol {
             ParsedValueType::LongType => Arc::from("BIGINT"),
             ParsedValueType::DoubleType => Arc::from("DOUBLE"),
             ParsedValueType::TimeType(_) => Arc::from("TIMESTAMP"),
+            // in seconds
+            ParsedValueType::DurationType(_) => Arc::from("DOUBLE"),
+            ParsedValueType::IpType => Arc::clone(ip_type),
             ParsedValueType::StrType(n) => {
                 if *n < 16384 {
                     Arc::from(format!("VARCHAR({})", n).as_str())
@@ -74,11 +77,17 @@ impl SqlCreateSchema {
         }
     }
 
-    pub fn from_ql_schema(schema: &QlSchema, pre_name_opts: Arc<str>, table_opts:Arc<str>) -> Self {
+    /// ip_type is the SQL type of the ip columns, e.g. INET for PostgreSQL
+    pub fn from_ql_schema(
+        schema: &QlSchema,
+        pre_name_opts: Arc<str>,
+        table_opts: Arc<str>,
+        ip_type: &Arc<str>,
+    ) -> Self {
         let col_defs = schema
             .col_defs()
             .iter()
-            .map(|&x| SqlCreateCol::from_parser_col_def(x))
+            .map(|&x| SqlCreateCol::from_parser_col_def(x, ip_type))
             .collect();
         Self {
             table_name: Arc::from(schema.output_name()),
@@ -1,4 +1,4 @@
-use crate::parser::{DynParserSchema, ParsedValue};
+use crate::parser::{duration_secs, DynParserSchema, ParsedValue};
 use crate::ql_processor::QlRow;
 use crate::{DynBoxWrite, DynError};
 use std::io::Write;
@@ -28,6 +28,14 @@ fn output_value_for_sql(pv: &ParsedValue, outp: &mut DynBoxWrite) -> Result<(),
             outp.write(t.format(SQL_TIME_FORMAT).to_string().as_bytes())?;
             outp.write("'".as_bytes())?;
         }
+        ParsedValue::DurationVal(d) => {
+            outp.write_all(duration_secs(d).to_string().as_bytes())?;
+        }
+        ParsedValue::IpVal(ip) => {
+            outp.write_all("'".as_bytes())?;
+            outp.write_all(ip.to_string().as_bytes())?;
+            outp.write_all("'".as_bytes())?;
+        }
         ParsedValue::StrVal(s) => {
             outp.write("'".as_bytes())?;
             // escape quotes - replace all single quotes with two single quotes
@@ -191,7 +199,12 @@ mod tests {
     fn test_sql_gen1() {
         let schema = test_syslog_schema();
         let ql_schema = Arc::new(QlSchema::from(&schema));
-        let ddl = SqlCreateSchema::from_ql_schema(&ql_schema, Arc::from(""), Arc::from(""));
+        let ddl = SqlCreateSchema::from_ql_schema(
+            &ql_schema,
+            Arc::from(""),
+            Arc::from(""),
+            &Arc::from("VARCHAR(45)"),
+        );
         let s = ddl.get_create_sql();
         let mut out = get_dummy_outp();
         out.write(s.as_bytes()).unwrap();
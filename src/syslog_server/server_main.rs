@@ -14,8 +14,8 @@ pub async fn server_main(hc: HustlogConfig) -> Result<(), DynError> {
     let hcrc = Arc::new(hc);
     let (raw_sender, join_handles) = create_processing_pipeline(&hcrc).await?;
     match sc.proto.as_str() {
-        "tcp" => TcpServerConnection::tcp_server_main(raw_sender, hcrc, &host_port).await?,
-        "udp" => UdpServerState::udp_server_main(raw_sender, hcrc, &host_port).await?,
+        "tcp" => TcpServerConnection::tcp_server_main(raw_sender, hcrc.clone(), &host_port).await?,
+        "udp" => UdpServerState::udp_server_main(raw_sender, hcrc.clone(), &host_port).await?,
         x => {
             return Err(Box::new(ConnectionError::new(
                 format!(
@@ -29,6 +29,7 @@ pub async fn server_main(hc: HustlogConfig) -> Result<(), DynError> {
     for jh in join_handles {
         jh.join().await;
     }
+    hcrc.log_str_overflow_stats();
     info!("Server shut down");
     Ok(())
 }
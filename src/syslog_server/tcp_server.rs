@@ -1,6 +1,6 @@
 use crate::async_pipeline::lines_buffer::LinesBuffer;
 use crate::async_pipeline::message_queue::MessageSender;
-use crate::parser::RawMessage;
+use crate::parser::{LineMergerConfig, RawMessage, SourceMeta};
 use crate::{DynError, HustlogConfig};
 use log::{debug, error, info, log_enabled, trace, Level};
 use std::error::Error;
@@ -55,13 +55,18 @@ impl TcpServerConnection {
         raw_sender: MessageSender<Vec<RawMessage>>,
         socket: TcpStream,
         remote_addr: String,
-        use_line_merger: bool,
+        listener: Arc<str>,
+        line_merger_conf: Option<&LineMergerConfig>,
     ) -> Self {
+        let mut buffer = LinesBuffer::new(line_merger_conf);
+        let source: Arc<str> = Arc::from(remote_addr.as_str());
+        buffer.set_source(Some(source.clone()));
+        buffer.set_source_meta(Some(Arc::new(SourceMeta::new(source, listener, "tcp"))));
         Self {
             raw_sender,
             socket,
             remote_addr: remote_addr,
-            buffer: LinesBuffer::new(use_line_merger),
+            buffer,
             is_closed: false,
             is_error: false,
         }
@@ -116,11 +121,17 @@ impl TcpServerConnection {
         raw_sender: MessageSender<Vec<RawMessage>>,
         socket: TcpStream,
         remote_addr: String,
-        merge_multi_line: bool,
+        listener: Arc<str>,
+        line_merger_conf: Option<LineMergerConfig>,
     ) {
         tokio::spawn(async move {
-            let mut conn =
-                TcpServerConnection::new(raw_sender, socket, remote_addr, merge_multi_line);
+            let mut conn = TcpServerConnection::new(
+                raw_sender,
+                socket,
+                remote_addr,
+                listener,
+                line_merger_conf.as_ref(),
+            );
             let conn_result = conn.process_socket().await;
             //process_socket(socket, &remote_addr, hc, sender).await;
             if let Err(err) = conn_result {
@@ -147,6 +158,7 @@ impl TcpServerConnection {
             &host_port, hcrc
         );
         let hcrc = Arc::clone(&hcrc);
+        let listener_addr: Arc<str> = Arc::from(host_port.as_str());
         loop {
             // accept connections or process events, in a loop
             let raw_sender = raw_sender.clone_sender();
@@ -170,7 +182,8 @@ impl TcpServerConnection {
                         raw_sender,
                         socket,
                         remote_addr_str,
-                        hcrc.merge_multi_line(),
+                        listener_addr.clone(),
+                        hcrc.line_merger_conf().clone(),
                     );
                 }
             }
@@ -2,9 +2,10 @@ use crate::async_pipeline::lines_buffer::LinesBuffer;
 use crate::async_pipeline::message_queue::{
     ChannelReceiver, ChannelSender, MessageSender, QueueMessage,
 };
-use crate::parser::RawMessage;
+use crate::parser::{LineMergerConfig, RawMessage, SourceMeta};
 use crate::{DynError, HustlogConfig};
 use bytes::BufMut;
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, log_enabled, trace, Level};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -27,11 +28,19 @@ pub struct UdpStream {
 }
 
 impl UdpStream {
-    pub fn new(remote_addr: Arc<str>, use_line_merger: bool) -> Self {
+    pub fn new(
+        remote_addr: Arc<str>,
+        listener: Arc<str>,
+        line_merger_conf: Option<&LineMergerConfig>,
+    ) -> Self {
+        let mut buffer = LinesBuffer::new(line_merger_conf);
+        buffer.set_source(Some(remote_addr.clone()));
+        let meta = SourceMeta::new(remote_addr.clone(), listener, "udp");
+        buffer.set_source_meta(Some(Arc::new(meta)));
         Self {
             last_data_rcvd: system_time_now(),
             remote_addr,
-            buffer: LinesBuffer::new(use_line_merger),
+            buffer,
         }
     }
 
@@ -60,11 +69,16 @@ impl UdpStream {
 pub struct UdpData {
     sender: Arc<str>,
     data: Vec<u8>,
+    received_at: DateTime<Utc>,
 }
 
 impl UdpData {
     pub fn new(sender: Arc<str>, data: Vec<u8>) -> Self {
-        Self { sender, data }
+        Self {
+            sender,
+            data,
+            received_at: Utc::now(),
+        }
     }
 }
 
@@ -74,15 +88,17 @@ pub struct UdpServerState {
     rx: ChannelReceiver<QueueMessage<UdpData>>,
     streams: HashMap<Arc<str>, UdpStream>,
     min_idle_ttl: u64,
-    use_line_merger: bool,
+    line_merger_conf: Option<LineMergerConfig>,
+    listener: Arc<str>,
 }
 
 impl UdpServerState {
     pub fn new(
         parser_tx: MessageSender<Vec<RawMessage>>,
         min_idle_ttl: u64,
-        use_line_merger: bool,
+        line_merger_conf: Option<LineMergerConfig>,
         channel_size: usize,
+        listener: Arc<str>,
     ) -> Self {
         let (tx, rx) = tokio::sync::mpsc::channel(channel_size);
         Self {
@@ -91,7 +107,8 @@ impl UdpServerState {
             rx,
             streams: HashMap::new(),
             min_idle_ttl,
-            use_line_merger,
+            line_merger_conf,
+            listener,
         }
     }
 
@@ -140,13 +157,16 @@ impl UdpServerState {
                     let UdpData {
                         sender: remote_addr,
                         data,
+                        received_at,
                     } = ud;
-                    let stream = self
-                        .streams
-                        .entry(remote_addr.clone())
-                        .or_insert(UdpStream::new(remote_addr, self.use_line_merger));
+                    let listener = &self.listener;
+                    let line_merger_conf = self.line_merger_conf.as_ref();
+                    let stream = self.streams.entry(remote_addr.clone()).or_insert_with(|| {
+                        UdpStream::new(remote_addr, listener.clone(), line_merger_conf)
+                    });
                     stream.touch();
                     let lines_buf = stream.get_buffer();
+                    lines_buf.set_received_at(Some(received_at));
                     lines_buf.get_buf().put(data.as_slice());
                     let msgs = lines_buf.read_messages_from_buf();
                     if let Err(err) = self.parser_tx.send(msgs).await {
@@ -164,6 +184,11 @@ impl UdpServerState {
                         flushed
                     );
                 }
+                QueueMessage::Checkpoint(marker) => {
+                    if let Err(err) = self.parser_tx.checkpoint(marker).await {
+                        error!("Failed to send checkpoint to parser: {:?}", err)
+                    }
+                }
                 QueueMessage::Shutdown => {
                     let flushed = self.flush(0).await; //everything is expired when shutting down
                     info!("Shutdown message received: flushed={}", flushed);
@@ -202,8 +227,9 @@ impl UdpServerState {
         let server_state = UdpServerState::new(
             raw_sender,
             hcrc.get_idle_timeout(),
-            hcrc.merge_multi_line(),
+            hcrc.line_merger_conf().clone(),
             hcrc.get_async_channel_size(),
+            Arc::from(host_port.as_str()),
         );
         let udp_data_sender = server_state.clone_sender();
         server_state.consume_udp_data_queue_async();